@@ -0,0 +1,90 @@
+//! Counts the opcodes dispatched by the examples of the README at `-O1` without and with the
+//! superinstructions, and times their execution, to show what the superinstructions alone save.
+//!
+//! `cargo bench --bench dispatch` prints a line per example. Arguments select the examples whose
+//! name contains one of them.
+
+use std::ops::ControlFlow;
+use std::time::{Duration, Instant};
+
+use abyssix::Vm;
+use abyssix::code_generator::generate_with_superinstructions;
+use abyssix::executor::{Machine, Observer};
+use abyssix::io::MemoryIo;
+use abyssix::optimizer::OptLevel;
+
+const README: &str = include_str!("../README.md");
+const RUNS: u32 = 200;
+
+/// Returns the name and the source of each example, the code blocks under `# Examples`.
+fn examples() -> Vec<(&'static str, &'static str)> {
+    let start = README
+        .find("\n# Examples\n")
+        .expect("the README has examples");
+    let end = README[start + 1..]
+        .find("\n# ")
+        .map_or(README.len(), |end| start + 1 + end);
+    README[start..end]
+        .split("\n## ")
+        .skip(1)
+        .filter_map(|section| {
+            let (name, rest) = section.split_once('\n')?;
+            let source = rest.strip_prefix("```\n")?.split("```").next()?;
+            Some((name.trim(), source))
+        })
+        .collect()
+}
+
+struct Counter(u64);
+
+impl Observer for Counter {
+    fn before(&mut self, _: &Machine<'_>) -> ControlFlow<()> {
+        self.0 += 1;
+        ControlFlow::Continue(())
+    }
+}
+
+/// Returns the number of dispatched opcodes and the mean time of a run without an observer, the
+/// code being generated at `-O1` with or without the superinstructions.
+fn measure(source: &str, superinstructions: bool) -> (u64, Duration) {
+    let prog = abyssix::parse(source).expect("the example compiles");
+    let codes = generate_with_superinstructions(prog, OptLevel::O1, superinstructions)
+        .expect("the example compiles");
+    let mut counter = Counter(0);
+    Vm::with_io(codes.clone(), MemoryIo::memory(b""))
+        .run_observed(&mut counter)
+        .expect("the example runs");
+
+    let start = Instant::now();
+    for _ in 0..RUNS {
+        Vm::with_io(codes.clone(), MemoryIo::memory(b""))
+            .run()
+            .unwrap();
+    }
+    (counter.0, start.elapsed() / RUNS)
+}
+
+fn main() {
+    // `cargo bench` passes `--bench`.
+    let filters: Vec<String> = std::env::args()
+        .skip(1)
+        .filter(|arg| !arg.starts_with("--"))
+        .collect();
+    println!(
+        "{:<16}{:>14}{:>12}{:>10}{:>14}{:>14}",
+        "example", "plain opcodes", "fused", "saved", "plain time", "fused time"
+    );
+    for (name, source) in examples() {
+        if !filters.is_empty() && !filters.iter().any(|filter| name.contains(filter.as_str())) {
+            continue;
+        }
+        let (plain_count, plain_time) = measure(source, false);
+        let (fused_count, fused_time) = measure(source, true);
+        // Negative if the fused code ever dispatches more opcodes.
+        let saved = 100.0 * (plain_count as f64 - fused_count as f64) / plain_count as f64;
+        println!(
+            "{:<16}{:>14}{:>12}{:>9.1}%{:>14?}{:>14?}",
+            name, plain_count, fused_count, saved, plain_time, fused_time
+        );
+    }
+}
@@ -0,0 +1,432 @@
+//! A textual form of [`Codes`], to read the generated opcodes or to write them by hand.
+//!
+//! ```text
+//! .entry main
+//!
+//! .func main params 0 alloc 1
+//!     PushRbpAndMovEspToEbp                   ; 0  line 1
+//!     Alloc(1)                                ; 1  line 1
+//! L0:
+//!     GetVar(0, R1)                           ; 2  line 4
+//!     JmpAddrIfZero(R1, L1)                   ; 3  line 4
+//!     FunctionCall(step)                      ; 4  line 5
+//!     JmpAddr(L0)                             ; 5  line 5
+//! L1:
+//!     Exit                                    ; 6  line 7
+//! ```
+//!
+//! A function starts at its `.func NAME params N alloc M` line and ends at the next one. Jump
+//! targets are labels defined by `LABEL:` lines and call targets are function names, but both
+//! can also be opcode indices. Everything after a `;` is a comment.
+
+use std::collections::HashMap;
+use std::fmt::Write as _;
+
+use crate::code_generator::{Codes, FunctionInfo, OpCode};
+use crate::error::CompileError;
+use crate::executor::Register;
+use crate::lexer::Span;
+
+/// Writes `codes` in the textual form, with the index and the source line of each opcode as a
+/// comment.
+pub fn disassemble(codes: &Codes) -> String {
+    let len = codes.opcodes.len();
+    let mut targets: Vec<usize> = (codes.opcodes.iter())
+        .filter_map(|opcode| match *opcode {
+            OpCode::JmpAddr(target)
+            | OpCode::JmpAddrIfZero(_, target)
+            | OpCode::JmpAddrIfNotEqInt(_, _, target)
+            | OpCode::JmpAddrIfNotNeInt(_, _, target)
+            | OpCode::JmpAddrIfNotLtInt(_, _, target)
+            | OpCode::JmpAddrIfNotLeInt(_, _, target)
+            | OpCode::JmpAddrIfNotGtInt(_, _, target)
+            | OpCode::JmpAddrIfNotGeInt(_, _, target) => Some(target),
+            _ => None,
+        })
+        .filter(|&target| target <= len)
+        .collect();
+    targets.sort_unstable();
+    targets.dedup();
+    let target = |index: usize| match targets.binary_search(&index) {
+        Ok(label) => format!("L{}", label),
+        Err(_) => index.to_string(),
+    };
+    let function = |index: usize| match codes.functions.iter().find(|f| f.start == index) {
+        Some(f) => f.name.clone(),
+        None => index.to_string(),
+    };
+    let branch = |name: &str, r: Register, v: i64, t: usize| {
+        format!("{}({:?}, {}, {})", name, r, v, target(t))
+    };
+
+    let mut out = String::new();
+    writeln!(out, ".entry {}", function(codes.entry_point)).unwrap();
+    let mut functions = codes.functions.iter().peekable();
+    let mut labels = targets.iter().enumerate().peekable();
+    for index in 0..=len {
+        while let Some(f) = functions.next_if(|f| f.start == index) {
+            writeln!(
+                out,
+                "\n.func {} params {} alloc {}",
+                f.name, f.params_size, f.alloc_size
+            )
+            .unwrap();
+        }
+        while let Some((label, _)) = labels.next_if(|&(_, &target)| target == index) {
+            writeln!(out, "L{}:", label).unwrap();
+        }
+        let Some(opcode) = codes.opcodes.get(index) else {
+            break;
+        };
+        let text = match *opcode {
+            OpCode::JmpAddr(t) => format!("JmpAddr({})", target(t)),
+            OpCode::JmpAddrIfZero(r, t) => format!("JmpAddrIfZero({:?}, {})", r, target(t)),
+            OpCode::JmpAddrIfNotEqInt(r, v, t) => branch("JmpAddrIfNotEqInt", r, v, t),
+            OpCode::JmpAddrIfNotNeInt(r, v, t) => branch("JmpAddrIfNotNeInt", r, v, t),
+            OpCode::JmpAddrIfNotLtInt(r, v, t) => branch("JmpAddrIfNotLtInt", r, v, t),
+            OpCode::JmpAddrIfNotLeInt(r, v, t) => branch("JmpAddrIfNotLeInt", r, v, t),
+            OpCode::JmpAddrIfNotGtInt(r, v, t) => branch("JmpAddrIfNotGtInt", r, v, t),
+            OpCode::JmpAddrIfNotGeInt(r, v, t) => branch("JmpAddrIfNotGeInt", r, v, t),
+            OpCode::FunctionCall(t) => format!("FunctionCall({})", function(t)),
+            OpCode::TailCall(n, t) => format!("TailCall({}, {})", n, function(t)),
+            opcode => format!("{:?}", opcode),
+        };
+        write!(out, "    {:<40}; {}", text, index).unwrap();
+        if let Some(span) = codes.spans.get(index) {
+            write!(out, "  line {}", span.line).unwrap();
+        }
+        out.push('\n');
+    }
+    out
+}
+
+/// A line of the text, without its comment and surrounding whitespace.
+struct Line<'a> {
+    text: &'a str,
+    span: Span,
+}
+
+fn lines(text: &str) -> impl Iterator<Item = Line<'_>> {
+    let mut start = 0;
+    text.split_inclusive('\n')
+        .enumerate()
+        .map(move |(i, raw)| {
+            let line_start = start;
+            start += raw.len();
+            let code = raw.split_once(';').map_or(raw, |(code, _)| code);
+            let indent = code.len() - code.trim_start().len();
+            let code = code.trim();
+            Line {
+                text: code,
+                span: Span {
+                    start: line_start + indent,
+                    end: line_start + indent + code.len(),
+                    line: i + 1,
+                    column: raw[..indent].chars().count() + 1,
+                },
+            }
+        })
+        .filter(|line| !line.text.is_empty())
+}
+
+fn is_name(s: &str) -> bool {
+    s.starts_with(|c: char| c.is_ascii_alphabetic() || c == '_')
+        && s.chars().all(|c| c.is_ascii_alphanumeric() || c == '_')
+}
+
+/// The operands of an opcode, remembering how many were used.
+struct Operands<'a, 'b> {
+    items: Vec<&'a str>,
+    used: usize,
+    labels: &'b HashMap<&'a str, usize>,
+    functions: &'b HashMap<&'a str, usize>,
+}
+
+impl<'a> Operands<'a, '_> {
+    fn get(&mut self, i: usize) -> Result<&'a str, String> {
+        self.used = self.used.max(i + 1);
+        self.items
+            .get(i)
+            .copied()
+            .ok_or_else(|| format!("operand {} is missing", i + 1))
+    }
+
+    fn register(&mut self, i: usize) -> Result<Register, String> {
+        match self.get(i)? {
+            "R1" => Ok(Register::R1),
+            "R2" => Ok(Register::R2),
+            "R3" => Ok(Register::R3),
+            "RAX" => Ok(Register::RAX),
+            s => Err(format!("expected a register, found `{}`", s)),
+        }
+    }
+
+    fn int(&mut self, i: usize) -> Result<i64, String> {
+        let s = self.get(i)?;
+        s.parse()
+            .map_err(|_| format!("expected an integer, found `{}`", s))
+    }
+
+    fn float(&mut self, i: usize) -> Result<f64, String> {
+        let s = self.get(i)?;
+        s.parse()
+            .map_err(|_| format!("expected a float, found `{}`", s))
+    }
+
+    fn size(&mut self, i: usize) -> Result<usize, String> {
+        let s = self.get(i)?;
+        s.parse()
+            .map_err(|_| format!("expected a non-negative integer, found `{}`", s))
+    }
+
+    fn target(&mut self, i: usize) -> Result<usize, String> {
+        let s = self.get(i)?;
+        match self.labels.get(s) {
+            Some(&index) => Ok(index),
+            None => s.parse().map_err(|_| format!("undefined label `{}`", s)),
+        }
+    }
+
+    fn function(&mut self, i: usize) -> Result<usize, String> {
+        let s = self.get(i)?;
+        match self.functions.get(s) {
+            Some(&index) => Ok(index),
+            None => s.parse().map_err(|_| format!("undefined function `{}`", s)),
+        }
+    }
+
+    fn unary(&mut self, f: fn(Register, Register) -> OpCode) -> Result<OpCode, String> {
+        Ok(f(self.register(0)?, self.register(1)?))
+    }
+
+    fn binary(&mut self, f: fn(Register, Register, Register) -> OpCode) -> Result<OpCode, String> {
+        Ok(f(self.register(0)?, self.register(1)?, self.register(2)?))
+    }
+
+    fn immediate(&mut self, f: fn(Register, i64, Register) -> OpCode) -> Result<OpCode, String> {
+        Ok(f(self.register(0)?, self.int(1)?, self.register(2)?))
+    }
+
+    fn branch(&mut self, f: fn(Register, i64, usize) -> OpCode) -> Result<OpCode, String> {
+        Ok(f(self.register(0)?, self.int(1)?, self.target(2)?))
+    }
+}
+
+fn parse_opcode(
+    text: &str,
+    labels: &HashMap<&str, usize>,
+    functions: &HashMap<&str, usize>,
+) -> Result<OpCode, String> {
+    let (name, items) = match text.split_once('(') {
+        Some((name, rest)) => {
+            let Some(operands) = rest.strip_suffix(')') else {
+                return Err("expected `)` at the end of the opcode".to_owned());
+            };
+            let items = if operands.trim().is_empty() {
+                Vec::new()
+            } else {
+                operands.split(',').map(str::trim).collect()
+            };
+            (name.trim_end(), items)
+        }
+        None => (text, Vec::new()),
+    };
+    let mut o = Operands {
+        items,
+        used: 0,
+        labels,
+        functions,
+    };
+    let opcode = match name {
+        "Push" => OpCode::Push(o.register(0)?),
+        "LoadInt" => OpCode::LoadInt(o.int(0)?, o.register(1)?),
+        "LoadFloat" => OpCode::LoadFloat(o.float(0)?, o.register(1)?),
+        "Pop" => OpCode::Pop(o.register(0)?),
+        "Mov" => o.unary(OpCode::Mov)?,
+        "LogiNot" => o.unary(OpCode::LogiNot)?,
+        "BitNot" => o.unary(OpCode::BitNot)?,
+        "NegInt" => o.unary(OpCode::NegInt)?,
+        "NegFloat" => o.unary(OpCode::NegFloat)?,
+        "AddFloat" => o.binary(OpCode::AddFloat)?,
+        "AddInt" => o.binary(OpCode::AddInt)?,
+        "SubFloat" => o.binary(OpCode::SubFloat)?,
+        "SubInt" => o.binary(OpCode::SubInt)?,
+        "MulFloat" => o.binary(OpCode::MulFloat)?,
+        "MulInt" => o.binary(OpCode::MulInt)?,
+        "DivFloat" => o.binary(OpCode::DivFloat)?,
+        "DivInt" => o.binary(OpCode::DivInt)?,
+        "RemFloat" => o.binary(OpCode::RemFloat)?,
+        "RemInt" => o.binary(OpCode::RemInt)?,
+        "NeInt" => o.binary(OpCode::NeInt)?,
+        "EqInt" => o.binary(OpCode::EqInt)?,
+        "NeFloat" => o.binary(OpCode::NeFloat)?,
+        "EqFloat" => o.binary(OpCode::EqFloat)?,
+        "And" => o.binary(OpCode::And)?,
+        "Or" => o.binary(OpCode::Or)?,
+        "Xor" => o.binary(OpCode::Xor)?,
+        "Shr" => o.binary(OpCode::Shr)?,
+        "ShrUnsigned" => o.binary(OpCode::ShrUnsigned)?,
+        "Shl" => o.binary(OpCode::Shl)?,
+        "LtInt" => o.binary(OpCode::LtInt)?,
+        "LtFloat" => o.binary(OpCode::LtFloat)?,
+        "LeInt" => o.binary(OpCode::LeInt)?,
+        "LeFloat" => o.binary(OpCode::LeFloat)?,
+        "AddIntImm" => o.immediate(OpCode::AddIntImm)?,
+        "MulIntImm" => o.immediate(OpCode::MulIntImm)?,
+        "DivIntImm" => o.immediate(OpCode::DivIntImm)?,
+        "RemIntImm" => o.immediate(OpCode::RemIntImm)?,
+        "SetVar" => OpCode::SetVar(o.size(0)?, o.register(1)?),
+        "SetVarComputed" => OpCode::SetVarComputed(o.register(0)?, o.size(1)?, o.register(2)?),
+        "GetVar" => OpCode::GetVar(o.size(0)?, o.register(1)?),
+        "AddVarInt" => OpCode::AddVarInt(o.size(0)?, o.int(1)?),
+        "GetVarComputed" => OpCode::GetVarComputed(o.register(0)?, o.size(1)?, o.register(2)?),
+        "GetParam" => OpCode::GetParam(o.size(0)?, o.register(1)?),
+        "FloatToInt" => o.unary(OpCode::FloatToInt)?,
+        "IntToFloat" => o.unary(OpCode::IntToFloat)?,
+        "JmpAddr" => OpCode::JmpAddr(o.target(0)?),
+        "JmpAddrIfZero" => OpCode::JmpAddrIfZero(o.register(0)?, o.target(1)?),
+        "JmpAddrIfNotEqInt" => o.branch(OpCode::JmpAddrIfNotEqInt)?,
+        "JmpAddrIfNotNeInt" => o.branch(OpCode::JmpAddrIfNotNeInt)?,
+        "JmpAddrIfNotLtInt" => o.branch(OpCode::JmpAddrIfNotLtInt)?,
+        "JmpAddrIfNotLeInt" => o.branch(OpCode::JmpAddrIfNotLeInt)?,
+        "JmpAddrIfNotGtInt" => o.branch(OpCode::JmpAddrIfNotGtInt)?,
+        "JmpAddrIfNotGeInt" => o.branch(OpCode::JmpAddrIfNotGeInt)?,
+        "FunctionCall" => OpCode::FunctionCall(o.function(0)?),
+        "TailCall" => OpCode::TailCall(o.size(0)?, o.function(1)?),
+        "PutByte" => OpCode::PutByte(o.register(0)?),
+        "PutBytes" => OpCode::PutBytes(o.register(0)?, o.size(1)?, o.register(2)?),
+        "ReadByteFromStdin" => OpCode::ReadByteFromStdin(o.register(0)?),
+        "Alloc" => OpCode::Alloc(o.size(0)?),
+        "Free" => OpCode::Free(o.size(0)?),
+        "Ret" => OpCode::Ret,
+        "PushRbpAndMovEspToEbp" => OpCode::PushRbpAndMovEspToEbp,
+        "MovEbpToEspAndPopRbp" => OpCode::MovEbpToEspAndPopRbp,
+        "Exit" => OpCode::Exit,
+        _ => return Err(format!("unknown opcode `{}`", name)),
+    };
+    if o.items.len() > o.used {
+        return Err(format!(
+            "`{}` takes {} operand(s), but {} given",
+            name,
+            o.used,
+            o.items.len()
+        ));
+    }
+    Ok(opcode)
+}
+
+/// Parses the textual form written by [`disassemble`] back into codes, reporting every error
+/// found. The codes have no spans.
+pub fn assemble(text: &str) -> Result<Codes, Vec<CompileError>> {
+    let mut errors = Vec::new();
+    let mut entry = None;
+    let mut functions = Vec::new();
+    let mut labels = HashMap::new();
+    let mut opcode_lines = Vec::new();
+
+    // Give labels and functions their indices first, so that they can be used before them.
+    for line in lines(text) {
+        let words: Vec<&str> = line.text.split_whitespace().collect();
+        if words[0] == ".entry" {
+            match words[..] {
+                [_, name] => {
+                    if entry.replace((name, line.span)).is_some() {
+                        errors.push(CompileError::new("`.entry` is given twice", line.span));
+                    }
+                }
+                _ => errors.push(CompileError::new("expected `.entry FUNCTION`", line.span)),
+            }
+        } else if words[0] == ".func" {
+            match words[..] {
+                [".func", name, "params", params, "alloc", alloc] if is_name(name) => {
+                    match (params.parse(), alloc.parse()) {
+                        (Ok(params_size), Ok(alloc_size)) => functions.push(FunctionInfo {
+                            name: name.to_owned(),
+                            start: opcode_lines.len(),
+                            end: 0,
+                            params_size,
+                            alloc_size,
+                        }),
+                        _ => errors.push(CompileError::new(
+                            "the sizes of a function must be non-negative integers",
+                            line.span,
+                        )),
+                    }
+                }
+                _ => errors.push(CompileError::new(
+                    "expected `.func NAME params N alloc M`",
+                    line.span,
+                )),
+            }
+        } else if let Some(label) = line.text.strip_suffix(':') {
+            let label = label.trim_end();
+            if !is_name(label) {
+                errors.push(CompileError::new(
+                    format!("invalid label `{}`", label),
+                    line.span,
+                ));
+            } else if labels.insert(label, opcode_lines.len()).is_some() {
+                errors.push(CompileError::new(
+                    format!("the label `{}` is defined twice", label),
+                    line.span,
+                ));
+            }
+        } else {
+            opcode_lines.push(line);
+        }
+    }
+
+    let ends: Vec<usize> = (functions.iter().skip(1))
+        .map(|f| f.start)
+        .chain([opcode_lines.len()])
+        .collect();
+    for (f, end) in functions.iter_mut().zip(ends) {
+        f.end = end;
+    }
+
+    let mut function_starts = HashMap::new();
+    for f in &functions {
+        if function_starts.insert(f.name.as_str(), f.start).is_some() {
+            errors.push(CompileError::unlocated(format!(
+                "the function `{}` is defined twice",
+                f.name
+            )));
+        }
+    }
+    let opcodes: Vec<OpCode> = (opcode_lines.iter())
+        .filter_map(|line| {
+            parse_opcode(line.text, &labels, &function_starts)
+                .map_err(|message| errors.push(CompileError::new(message, line.span)))
+                .ok()
+        })
+        .collect();
+
+    let entry_point = match entry {
+        Some((name, span)) => match function_starts.get(name) {
+            Some(&start) => Some(start),
+            None => name.parse().ok().or_else(|| {
+                errors.push(CompileError::new(
+                    format!("undefined function `{}`", name),
+                    span,
+                ));
+                None
+            }),
+        },
+        None => function_starts.get("main").copied().or_else(|| {
+            errors.push(CompileError::unlocated(
+                "the entry point is missing; add `.entry FUNCTION` or a `main` function",
+            ));
+            None
+        }),
+    };
+
+    match entry_point {
+        Some(entry_point) if errors.is_empty() => Ok(Codes {
+            entry_point,
+            opcodes,
+            functions,
+            spans: Vec::new(),
+        }),
+        _ => Err(errors),
+    }
+}
@@ -0,0 +1,589 @@
+//! The `.abyc` file format, to run compiled [`Codes`] without compiling their source again.
+//!
+//! Integers are little-endian, and sizes and indices are `u64`:
+//!
+//! | Field            | Encoding                                                                    |
+//! |------------------|-----------------------------------------------------------------------------|
+//! | magic            | `ABYC`                                                                      |
+//! | version          | `u16`, [`VERSION`]                                                          |
+//! | flags            | `u16`, bit 0 set if the debug line table is present                         |
+//! | entry point      | opcode index                                                                |
+//! | function table   | count, then the name length, name, start, end, params and alloc of each    |
+//! | opcodes          | count, then a `u8` tag and the operands of each                             |
+//! | debug line table | start, end, line and column of the source location of each opcode           |
+//!
+//! Registers are `u8`s, integer constants `i64`s and float constants the bits of `f64`s.
+
+use crate::code_generator::{Codes, FunctionInfo, OpCode};
+use crate::error::LoadError;
+use crate::executor::Register;
+use crate::lexer::Span;
+use crate::verifier;
+
+pub const MAGIC: [u8; 4] = *b"ABYC";
+/// The version of the format written by [`encode`], the only one [`decode`] reads.
+pub const VERSION: u16 = 3;
+
+const FLAG_DEBUG_LINES: u16 = 1;
+
+struct Writer(Vec<u8>);
+
+impl Writer {
+    fn u8(&mut self, value: u8) {
+        self.0.push(value);
+    }
+
+    fn u64(&mut self, value: u64) {
+        self.0.extend_from_slice(&value.to_le_bytes());
+    }
+
+    fn usize(&mut self, value: usize) {
+        self.u64(value as u64);
+    }
+
+    fn register(&mut self, register: Register) {
+        self.u8(register as u8);
+    }
+
+    fn registers(&mut self, registers: &[Register]) {
+        for &register in registers {
+            self.register(register);
+        }
+    }
+
+    fn immediate(&mut self, tag: u8, r1: Register, value: i64, r2: Register) {
+        self.u8(tag);
+        self.register(r1);
+        self.u64(value as u64);
+        self.register(r2);
+    }
+
+    fn branch(&mut self, tag: u8, r1: Register, value: i64, target: usize) {
+        self.u8(tag);
+        self.register(r1);
+        self.u64(value as u64);
+        self.usize(target);
+    }
+
+    fn opcode(&mut self, opcode: OpCode) {
+        match opcode {
+            OpCode::Push(r1) => {
+                self.u8(0);
+                self.register(r1);
+            }
+            OpCode::LoadInt(value, r1) => {
+                self.u8(1);
+                self.u64(value as u64);
+                self.register(r1);
+            }
+            OpCode::LoadFloat(value, r1) => {
+                self.u8(2);
+                self.u64(value.to_bits());
+                self.register(r1);
+            }
+            OpCode::Pop(r1) => {
+                self.u8(3);
+                self.register(r1);
+            }
+            OpCode::Mov(r1, r2) => {
+                self.u8(4);
+                self.registers(&[r1, r2]);
+            }
+            OpCode::LogiNot(r1, r2) => {
+                self.u8(5);
+                self.registers(&[r1, r2]);
+            }
+            OpCode::BitNot(r1, r2) => {
+                self.u8(6);
+                self.registers(&[r1, r2]);
+            }
+            OpCode::NegInt(r1, r2) => {
+                self.u8(7);
+                self.registers(&[r1, r2]);
+            }
+            OpCode::NegFloat(r1, r2) => {
+                self.u8(8);
+                self.registers(&[r1, r2]);
+            }
+            OpCode::AddFloat(r1, r2, r3) => {
+                self.u8(9);
+                self.registers(&[r1, r2, r3]);
+            }
+            OpCode::AddInt(r1, r2, r3) => {
+                self.u8(10);
+                self.registers(&[r1, r2, r3]);
+            }
+            OpCode::SubFloat(r1, r2, r3) => {
+                self.u8(11);
+                self.registers(&[r1, r2, r3]);
+            }
+            OpCode::SubInt(r1, r2, r3) => {
+                self.u8(12);
+                self.registers(&[r1, r2, r3]);
+            }
+            OpCode::MulFloat(r1, r2, r3) => {
+                self.u8(13);
+                self.registers(&[r1, r2, r3]);
+            }
+            OpCode::MulInt(r1, r2, r3) => {
+                self.u8(14);
+                self.registers(&[r1, r2, r3]);
+            }
+            OpCode::DivFloat(r1, r2, r3) => {
+                self.u8(15);
+                self.registers(&[r1, r2, r3]);
+            }
+            OpCode::DivInt(r1, r2, r3) => {
+                self.u8(16);
+                self.registers(&[r1, r2, r3]);
+            }
+            OpCode::RemFloat(r1, r2, r3) => {
+                self.u8(17);
+                self.registers(&[r1, r2, r3]);
+            }
+            OpCode::RemInt(r1, r2, r3) => {
+                self.u8(18);
+                self.registers(&[r1, r2, r3]);
+            }
+            OpCode::NeInt(r1, r2, r3) => {
+                self.u8(19);
+                self.registers(&[r1, r2, r3]);
+            }
+            OpCode::EqInt(r1, r2, r3) => {
+                self.u8(20);
+                self.registers(&[r1, r2, r3]);
+            }
+            OpCode::NeFloat(r1, r2, r3) => {
+                self.u8(21);
+                self.registers(&[r1, r2, r3]);
+            }
+            OpCode::EqFloat(r1, r2, r3) => {
+                self.u8(22);
+                self.registers(&[r1, r2, r3]);
+            }
+            OpCode::And(r1, r2, r3) => {
+                self.u8(23);
+                self.registers(&[r1, r2, r3]);
+            }
+            OpCode::Or(r1, r2, r3) => {
+                self.u8(24);
+                self.registers(&[r1, r2, r3]);
+            }
+            OpCode::Xor(r1, r2, r3) => {
+                self.u8(25);
+                self.registers(&[r1, r2, r3]);
+            }
+            OpCode::Shr(r1, r2, r3) => {
+                self.u8(26);
+                self.registers(&[r1, r2, r3]);
+            }
+            OpCode::ShrUnsigned(r1, r2, r3) => {
+                self.u8(27);
+                self.registers(&[r1, r2, r3]);
+            }
+            OpCode::Shl(r1, r2, r3) => {
+                self.u8(28);
+                self.registers(&[r1, r2, r3]);
+            }
+            OpCode::LtInt(r1, r2, r3) => {
+                self.u8(29);
+                self.registers(&[r1, r2, r3]);
+            }
+            OpCode::LtFloat(r1, r2, r3) => {
+                self.u8(30);
+                self.registers(&[r1, r2, r3]);
+            }
+            OpCode::LeInt(r1, r2, r3) => {
+                self.u8(31);
+                self.registers(&[r1, r2, r3]);
+            }
+            OpCode::LeFloat(r1, r2, r3) => {
+                self.u8(32);
+                self.registers(&[r1, r2, r3]);
+            }
+            OpCode::SetVar(index, r1) => {
+                self.u8(33);
+                self.usize(index);
+                self.register(r1);
+            }
+            OpCode::SetVarComputed(r1, alloc_size, r2) => {
+                self.u8(34);
+                self.register(r1);
+                self.usize(alloc_size);
+                self.register(r2);
+            }
+            OpCode::GetVar(index, r1) => {
+                self.u8(35);
+                self.usize(index);
+                self.register(r1);
+            }
+            OpCode::GetVarComputed(r1, alloc_size, r2) => {
+                self.u8(36);
+                self.register(r1);
+                self.usize(alloc_size);
+                self.register(r2);
+            }
+            OpCode::GetParam(index, r1) => {
+                self.u8(37);
+                self.usize(index);
+                self.register(r1);
+            }
+            OpCode::FloatToInt(r1, r2) => {
+                self.u8(38);
+                self.registers(&[r1, r2]);
+            }
+            OpCode::IntToFloat(r1, r2) => {
+                self.u8(39);
+                self.registers(&[r1, r2]);
+            }
+            OpCode::JmpAddr(target) => {
+                self.u8(40);
+                self.usize(target);
+            }
+            OpCode::JmpAddrIfZero(r1, target) => {
+                self.u8(41);
+                self.register(r1);
+                self.usize(target);
+            }
+            OpCode::FunctionCall(target) => {
+                self.u8(42);
+                self.usize(target);
+            }
+            OpCode::PutByte(r1) => {
+                self.u8(43);
+                self.register(r1);
+            }
+            OpCode::PutBytes(r1, alloc_size, r2) => {
+                self.u8(44);
+                self.register(r1);
+                self.usize(alloc_size);
+                self.register(r2);
+            }
+            OpCode::ReadByteFromStdin(r1) => {
+                self.u8(45);
+                self.register(r1);
+            }
+            OpCode::Alloc(size) => {
+                self.u8(46);
+                self.usize(size);
+            }
+            OpCode::Free(size) => {
+                self.u8(47);
+                self.usize(size);
+            }
+            OpCode::Ret => self.u8(48),
+            OpCode::Nop => self.u8(49),
+            OpCode::PushRbpAndMovEspToEbp => self.u8(50),
+            OpCode::MovEbpToEspAndPopRbp => self.u8(51),
+            OpCode::Exit => self.u8(52),
+            OpCode::AddIntImm(r1, value, r2) => self.immediate(53, r1, value, r2),
+            OpCode::MulIntImm(r1, value, r2) => self.immediate(54, r1, value, r2),
+            OpCode::DivIntImm(r1, value, r2) => self.immediate(55, r1, value, r2),
+            OpCode::RemIntImm(r1, value, r2) => self.immediate(56, r1, value, r2),
+            OpCode::AddVarInt(index, value) => {
+                self.u8(57);
+                self.usize(index);
+                self.u64(value as u64);
+            }
+            OpCode::JmpAddrIfNotEqInt(r1, value, target) => self.branch(58, r1, value, target),
+            OpCode::JmpAddrIfNotNeInt(r1, value, target) => self.branch(59, r1, value, target),
+            OpCode::JmpAddrIfNotLtInt(r1, value, target) => self.branch(60, r1, value, target),
+            OpCode::JmpAddrIfNotLeInt(r1, value, target) => self.branch(61, r1, value, target),
+            OpCode::JmpAddrIfNotGtInt(r1, value, target) => self.branch(62, r1, value, target),
+            OpCode::JmpAddrIfNotGeInt(r1, value, target) => self.branch(63, r1, value, target),
+            OpCode::TailCall(params_size, target) => {
+                self.u8(64);
+                self.usize(params_size);
+                self.usize(target);
+            }
+            OpCode::__JL_0515__(_)
+            | OpCode::__JLIZ_2505__(..)
+            | OpCode::__JLNC_3170__(..)
+            | OpCode::__FNCALL_2255__(_)
+            | OpCode::__TLCALL_4096__(..) => {
+                panic!("unresolved label in {:?}", opcode)
+            }
+        }
+    }
+}
+
+/// Encodes `codes` in the `.abyc` format, with the debug line table if `debug_lines` is true and
+/// the codes have spans.
+///
+/// # Panics
+///
+/// Panics if an opcode still refers to a label instead of an index, which [`generate`] never
+/// leaves.
+///
+/// [`generate`]: crate::code_generator::generate
+pub fn encode(codes: &Codes, debug_lines: bool) -> Vec<u8> {
+    let debug_lines = debug_lines && codes.spans.len() == codes.opcodes.len();
+    let mut w = Writer(Vec::new());
+    w.0.extend_from_slice(&MAGIC);
+    w.0.extend_from_slice(&VERSION.to_le_bytes());
+    let flags = if debug_lines { FLAG_DEBUG_LINES } else { 0 };
+    w.0.extend_from_slice(&flags.to_le_bytes());
+    w.usize(codes.entry_point);
+
+    w.usize(codes.functions.len());
+    for f in &codes.functions {
+        w.usize(f.name.len());
+        w.0.extend_from_slice(f.name.as_bytes());
+        for value in [f.start, f.end, f.params_size, f.alloc_size] {
+            w.usize(value);
+        }
+    }
+
+    w.usize(codes.opcodes.len());
+    for &opcode in &codes.opcodes {
+        w.opcode(opcode);
+    }
+
+    if debug_lines {
+        for span in &codes.spans {
+            for value in [span.start, span.end, span.line, span.column] {
+                w.usize(value);
+            }
+        }
+    }
+    w.0
+}
+
+struct Reader<'a> {
+    bytes: &'a [u8],
+    offset: usize,
+}
+
+impl<'a> Reader<'a> {
+    fn error(&self, message: &str) -> LoadError {
+        LoadError::new(format!("{} at byte {}", message, self.offset))
+    }
+
+    fn bytes(&mut self, len: usize) -> Result<&'a [u8], LoadError> {
+        let bytes = (self.bytes.get(self.offset..))
+            .and_then(|rest| rest.get(..len))
+            .ok_or_else(|| self.error("unexpected end of the file"))?;
+        self.offset += len;
+        Ok(bytes)
+    }
+
+    fn u8(&mut self) -> Result<u8, LoadError> {
+        Ok(self.bytes(1)?[0])
+    }
+
+    fn u16(&mut self) -> Result<u16, LoadError> {
+        Ok(u16::from_le_bytes(self.bytes(2)?.try_into().unwrap()))
+    }
+
+    fn u64(&mut self) -> Result<u64, LoadError> {
+        Ok(u64::from_le_bytes(self.bytes(8)?.try_into().unwrap()))
+    }
+
+    fn usize(&mut self) -> Result<usize, LoadError> {
+        let value = self.u64()?;
+        usize::try_from(value).map_err(|_| self.error("size too large"))
+    }
+
+    /// Reads a count of items taking at least `item_size` bytes each, rejecting counts that the
+    /// rest of the file cannot hold before allocating for them.
+    fn count(&mut self, item_size: usize) -> Result<usize, LoadError> {
+        let count = self.usize()?;
+        if count > (self.bytes.len() - self.offset) / item_size {
+            return Err(self.error("unexpected end of the file"));
+        }
+        Ok(count)
+    }
+
+    fn register(&mut self) -> Result<Register, LoadError> {
+        match self.u8()? {
+            0 => Ok(Register::R1),
+            1 => Ok(Register::R2),
+            2 => Ok(Register::R3),
+            3 => Ok(Register::RAX),
+            _ => Err(self.error("invalid register")),
+        }
+    }
+
+    fn unary(&mut self, f: fn(Register, Register) -> OpCode) -> Result<OpCode, LoadError> {
+        Ok(f(self.register()?, self.register()?))
+    }
+
+    fn binary(
+        &mut self,
+        f: fn(Register, Register, Register) -> OpCode,
+    ) -> Result<OpCode, LoadError> {
+        Ok(f(self.register()?, self.register()?, self.register()?))
+    }
+
+    fn immediate(&mut self, f: fn(Register, i64, Register) -> OpCode) -> Result<OpCode, LoadError> {
+        Ok(f(self.register()?, self.u64()? as i64, self.register()?))
+    }
+
+    fn branch(&mut self, f: fn(Register, i64, usize) -> OpCode) -> Result<OpCode, LoadError> {
+        Ok(f(self.register()?, self.u64()? as i64, self.usize()?))
+    }
+
+    fn opcode(&mut self) -> Result<OpCode, LoadError> {
+        Ok(match self.u8()? {
+            0 => OpCode::Push(self.register()?),
+            1 => OpCode::LoadInt(self.u64()? as i64, self.register()?),
+            2 => OpCode::LoadFloat(f64::from_bits(self.u64()?), self.register()?),
+            3 => OpCode::Pop(self.register()?),
+            4 => self.unary(OpCode::Mov)?,
+            5 => self.unary(OpCode::LogiNot)?,
+            6 => self.unary(OpCode::BitNot)?,
+            7 => self.unary(OpCode::NegInt)?,
+            8 => self.unary(OpCode::NegFloat)?,
+            9 => self.binary(OpCode::AddFloat)?,
+            10 => self.binary(OpCode::AddInt)?,
+            11 => self.binary(OpCode::SubFloat)?,
+            12 => self.binary(OpCode::SubInt)?,
+            13 => self.binary(OpCode::MulFloat)?,
+            14 => self.binary(OpCode::MulInt)?,
+            15 => self.binary(OpCode::DivFloat)?,
+            16 => self.binary(OpCode::DivInt)?,
+            17 => self.binary(OpCode::RemFloat)?,
+            18 => self.binary(OpCode::RemInt)?,
+            19 => self.binary(OpCode::NeInt)?,
+            20 => self.binary(OpCode::EqInt)?,
+            21 => self.binary(OpCode::NeFloat)?,
+            22 => self.binary(OpCode::EqFloat)?,
+            23 => self.binary(OpCode::And)?,
+            24 => self.binary(OpCode::Or)?,
+            25 => self.binary(OpCode::Xor)?,
+            26 => self.binary(OpCode::Shr)?,
+            27 => self.binary(OpCode::ShrUnsigned)?,
+            28 => self.binary(OpCode::Shl)?,
+            29 => self.binary(OpCode::LtInt)?,
+            30 => self.binary(OpCode::LtFloat)?,
+            31 => self.binary(OpCode::LeInt)?,
+            32 => self.binary(OpCode::LeFloat)?,
+            33 => OpCode::SetVar(self.usize()?, self.register()?),
+            34 => OpCode::SetVarComputed(self.register()?, self.usize()?, self.register()?),
+            35 => OpCode::GetVar(self.usize()?, self.register()?),
+            36 => OpCode::GetVarComputed(self.register()?, self.usize()?, self.register()?),
+            37 => OpCode::GetParam(self.usize()?, self.register()?),
+            38 => self.unary(OpCode::FloatToInt)?,
+            39 => self.unary(OpCode::IntToFloat)?,
+            40 => OpCode::JmpAddr(self.usize()?),
+            41 => OpCode::JmpAddrIfZero(self.register()?, self.usize()?),
+            42 => OpCode::FunctionCall(self.usize()?),
+            43 => OpCode::PutByte(self.register()?),
+            44 => OpCode::PutBytes(self.register()?, self.usize()?, self.register()?),
+            45 => OpCode::ReadByteFromStdin(self.register()?),
+            46 => OpCode::Alloc(self.usize()?),
+            47 => OpCode::Free(self.usize()?),
+            48 => OpCode::Ret,
+            49 => OpCode::Nop,
+            50 => OpCode::PushRbpAndMovEspToEbp,
+            51 => OpCode::MovEbpToEspAndPopRbp,
+            52 => OpCode::Exit,
+            53 => self.immediate(OpCode::AddIntImm)?,
+            54 => self.immediate(OpCode::MulIntImm)?,
+            55 => self.immediate(OpCode::DivIntImm)?,
+            56 => self.immediate(OpCode::RemIntImm)?,
+            57 => OpCode::AddVarInt(self.usize()?, self.u64()? as i64),
+            58 => self.branch(OpCode::JmpAddrIfNotEqInt)?,
+            59 => self.branch(OpCode::JmpAddrIfNotNeInt)?,
+            60 => self.branch(OpCode::JmpAddrIfNotLtInt)?,
+            61 => self.branch(OpCode::JmpAddrIfNotLeInt)?,
+            62 => self.branch(OpCode::JmpAddrIfNotGtInt)?,
+            63 => self.branch(OpCode::JmpAddrIfNotGeInt)?,
+            64 => OpCode::TailCall(self.usize()?, self.usize()?),
+            _ => {
+                self.offset -= 1;
+                return Err(self.error("invalid opcode"));
+            }
+        })
+    }
+}
+
+/// Decodes codes in the `.abyc` format and validates them: the functions must be in order
+/// without overlapping, and the codes must pass [`verify`](verifier::verify).
+pub fn decode(bytes: &[u8]) -> Result<Codes, LoadError> {
+    let mut r = Reader { bytes, offset: 0 };
+    if r.bytes.get(..MAGIC.len()) != Some(&MAGIC[..]) {
+        return Err(LoadError::new("not an abyssix bytecode file"));
+    }
+    r.offset = MAGIC.len();
+    let version = r.u16()?;
+    if version != VERSION {
+        return Err(LoadError::new(format!(
+            "unsupported bytecode version {} (expected {})",
+            version, VERSION
+        )));
+    }
+    let flags = r.u16()?;
+    if flags & !FLAG_DEBUG_LINES != 0 {
+        return Err(r.error("unknown flags"));
+    }
+    let entry_point = r.usize()?;
+
+    let function_count = r.count(40)?;
+    let mut functions = Vec::with_capacity(function_count);
+    for _ in 0..function_count {
+        let len = r.usize()?;
+        let name = std::str::from_utf8(r.bytes(len)?)
+            .map_err(|_| r.error("invalid function name"))?
+            .to_owned();
+        functions.push(FunctionInfo {
+            name,
+            start: r.usize()?,
+            end: r.usize()?,
+            params_size: r.usize()?,
+            alloc_size: r.usize()?,
+        });
+    }
+
+    let opcode_count = r.count(1)?;
+    let opcodes = (0..opcode_count)
+        .map(|_| r.opcode())
+        .collect::<Result<Vec<_>, _>>()?;
+
+    let mut spans = Vec::new();
+    if flags & FLAG_DEBUG_LINES != 0 {
+        if opcode_count > (r.bytes.len() - r.offset) / 32 {
+            return Err(r.error("unexpected end of the file"));
+        }
+        spans.reserve(opcode_count);
+        for _ in 0..opcode_count {
+            let span = Span {
+                start: r.usize()?,
+                end: r.usize()?,
+                line: r.usize()?,
+                column: r.usize()?,
+            };
+            if span.start > span.end {
+                return Err(r.error("a span ends before it starts"));
+            }
+            spans.push(span);
+        }
+    }
+    if r.offset != bytes.len() {
+        return Err(r.error("unexpected data"));
+    }
+
+    let codes = Codes {
+        entry_point,
+        opcodes,
+        functions,
+        spans,
+    };
+    validate(&codes)?;
+    Ok(codes)
+}
+
+fn validate(codes: &Codes) -> Result<(), LoadError> {
+    let mut previous_end = 0;
+    for f in &codes.functions {
+        if f.start < previous_end || f.end < f.start || f.end > codes.opcodes.len() {
+            return Err(LoadError::new(format!(
+                "the function {}() spans the opcodes {}..{}, which are out of range or \
+                 overlap the previous function",
+                f.name, f.start, f.end
+            )));
+        }
+        previous_end = f.end;
+    }
+    verifier::verify(codes).map_err(|e| LoadError::new(e.to_string()))
+}
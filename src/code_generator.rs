@@ -1,9 +1,14 @@
 #![allow(dead_code)]
 
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 
+use crate::error::CompileError;
 use crate::executor::{Register, Register::*};
-use crate::parser::{Expression, FunctionData, OperandType, Program, Statement};
+use crate::lexer::Span;
+use crate::optimizer::{self, OptLevel};
+use crate::parser::{
+    Expression, ExpressionKind, FunctionData, OperandType, Program, Statement, StatementKind,
+};
 
 #[derive(Debug, Clone, Copy)]
 pub struct Label(usize);
@@ -48,20 +53,35 @@ pub enum OpCode {
     LtFloat          (/** [IN] from0 */             Register,  /** [IN] from1 */     Register,      /** [OUT] to */ Register),
     LeInt            (/** [IN] from0 */             Register,  /** [IN] from1 */     Register,      /** [OUT] to */ Register),
     LeFloat          (/** [IN] from0 */             Register,  /** [IN] from1 */     Register,      /** [OUT] to */ Register),
+    AddIntImm        (/** [IN] from0 */             Register,  /** [CONST] from1 */  i64,           /** [OUT] to */ Register),
+    MulIntImm        (/** [IN] from0 */             Register,  /** [CONST] from1 */  i64,           /** [OUT] to */ Register),
+    DivIntImm        (/** [IN] from0 */             Register,  /** [CONST] from1 */  i64,           /** [OUT] to */ Register),
+    RemIntImm        (/** [IN] from0 */             Register,  /** [CONST] from1 */  i64,           /** [OUT] to */ Register),
     SetVar           (/** [CONST] variable index */ usize,     /** [IN] value */     Register),
-    SetVarComputed   (/** [IN] variable index */    Register,  /** [IN] value */     Register),
+    SetVarComputed   (/** [IN] variable index */    Register,  /** [CONST] alloc size */ usize,  /** [IN] value */ Register),
     GetVar           (/** [CONST] variable index */ usize,     /** [OUT] result */   Register),
-    GetVarComputed   (/** [IN] variable index */    Register,  /** [OUT] result */   Register),
+    AddVarInt        (/** [CONST] variable index */ usize,     /** [CONST] value added */ i64),
+    GetVarComputed   (/** [IN] variable index */    Register,  /** [CONST] alloc size */ usize,  /** [OUT] result */ Register),
     GetParam         (/** [CONST] param index */    usize,     /** [OUT] result */   Register),
     FloatToInt       (/** [IN] original float */    Register,  /** [OUT] result */   Register),
     IntToFloat       (/** [IN] original int */      Register,  /** [OUT] result */   Register),
     __JL_0515__      (/** [LABEL] target */         Label),
     __JLIZ_2505__    (/** [IN] value compared */    Register,  /** [LABEL] target */ Label),
     __FNCALL_2255__  (/** [FUNC_LABEL] target */    FuncLabel),
+    __TLCALL_4096__  (/** [CONST] params size */    usize,     /** [FUNC_LABEL] target */ FuncLabel),
+    __JLNC_3170__    (/** [CTOR] resolved opcode */ fn(Register, i64, usize) -> OpCode, /** [IN] value compared */ Register, /** [CONST] compared with */ i64, /** [LABEL] target */ Label),
     JmpAddr          (/** [CONST] target */         usize),
     JmpAddrIfZero    (/** [IN] value compared */    Register,  /** [CONST] target */ usize),
+    JmpAddrIfNotEqInt(/** [IN] value compared */    Register,  /** [CONST] compared with */ i64, /** [CONST] target */ usize),
+    JmpAddrIfNotNeInt(/** [IN] value compared */    Register,  /** [CONST] compared with */ i64, /** [CONST] target */ usize),
+    JmpAddrIfNotLtInt(/** [IN] value compared */    Register,  /** [CONST] compared with */ i64, /** [CONST] target */ usize),
+    JmpAddrIfNotLeInt(/** [IN] value compared */    Register,  /** [CONST] compared with */ i64, /** [CONST] target */ usize),
+    JmpAddrIfNotGtInt(/** [IN] value compared */    Register,  /** [CONST] compared with */ i64, /** [CONST] target */ usize),
+    JmpAddrIfNotGeInt(/** [IN] value compared */    Register,  /** [CONST] compared with */ i64, /** [CONST] target */ usize),
     FunctionCall     (/** [CONST] target */         usize),
+    TailCall         (/** [CONST] params size */    usize,     /** [CONST] target */ usize),
     PutByte          (/** [IN] value for print */   Register),
+    PutBytes         (/** [IN] first variable index */ Register, /** [CONST] alloc size */ usize,  /** [IN] byte count */ Register),
     ReadByteFromStdin(/** [OUT] value from stdin */ Register),
     Alloc            (/** [CONST] alloc size */     usize),
     Free             (/** [CONST] alloc size */     usize),
@@ -72,263 +92,322 @@ pub enum OpCode {
     Exit,
 }
 
+/// The registers that expressions are evaluated in. `RAX` only holds return values, and is `0`
+/// when a function starts so that falling off its end returns `0`.
+const REGISTERS: [Register; 3] = [R1, R2, R3];
+
+/// Returns the number of registers needed to evaluate `expr` without spilling values to the
+/// stack, its Sethi-Ullman number. A call needs a single register, as the registers in use are
+/// saved on the stack around it anyway.
+fn registers_needed(expr: &Expression) -> usize {
+    if matches!(expr.kind, ExpressionKind::Call(..)) {
+        return 1;
+    }
+    match expr.kind.operands()[..] {
+        [] => 1,
+        [operand] => registers_needed(operand),
+        [left, right] => {
+            let (left, right) = (registers_needed(left), registers_needed(right));
+            if left == right {
+                left + 1
+            } else {
+                left.max(right)
+            }
+        }
+        _ => unreachable!(),
+    }
+}
+
+/// Returns the opcode computing the operation `kind` of `left` and `right` into `out`.
+fn binary_opcode(kind: &ExpressionKind, left: Register, right: Register, out: Register) -> OpCode {
+    use OperandType::{Float, Int};
+    match kind {
+        ExpressionKind::Add(Float, ..) => OpCode::AddFloat(left, right, out),
+        ExpressionKind::Add(Int, ..) => OpCode::AddInt(left, right, out),
+        ExpressionKind::Sub(Float, ..) => OpCode::SubFloat(left, right, out),
+        ExpressionKind::Sub(Int, ..) => OpCode::SubInt(left, right, out),
+        ExpressionKind::Mul(Float, ..) => OpCode::MulFloat(left, right, out),
+        ExpressionKind::Mul(Int, ..) => OpCode::MulInt(left, right, out),
+        ExpressionKind::Div(Float, ..) => OpCode::DivFloat(left, right, out),
+        ExpressionKind::Div(Int, ..) => OpCode::DivInt(left, right, out),
+        ExpressionKind::Rem(Float, ..) => OpCode::RemFloat(left, right, out),
+        ExpressionKind::Rem(Int, ..) => OpCode::RemInt(left, right, out),
+        ExpressionKind::Eq(Float, ..) => OpCode::EqFloat(left, right, out),
+        ExpressionKind::Eq(Int, ..) => OpCode::EqInt(left, right, out),
+        ExpressionKind::Ne(Float, ..) => OpCode::NeFloat(left, right, out),
+        ExpressionKind::Ne(Int, ..) => OpCode::NeInt(left, right, out),
+        ExpressionKind::Lt(Float, ..) => OpCode::LtFloat(left, right, out),
+        ExpressionKind::Lt(Int, ..) => OpCode::LtInt(left, right, out),
+        ExpressionKind::Le(Float, ..) => OpCode::LeFloat(left, right, out),
+        ExpressionKind::Le(Int, ..) => OpCode::LeInt(left, right, out),
+        // `a > b` is `b < a` and `a >= b` is `b <= a`.
+        ExpressionKind::Gt(Float, ..) => OpCode::LtFloat(right, left, out),
+        ExpressionKind::Gt(Int, ..) => OpCode::LtInt(right, left, out),
+        ExpressionKind::Ge(Float, ..) => OpCode::LeFloat(right, left, out),
+        ExpressionKind::Ge(Int, ..) => OpCode::LeInt(right, left, out),
+        ExpressionKind::And(..) => OpCode::And(left, right, out),
+        ExpressionKind::Or(..) => OpCode::Or(left, right, out),
+        ExpressionKind::Xor(..) => OpCode::Xor(left, right, out),
+        ExpressionKind::Shl(..) => OpCode::Shl(left, right, out),
+        ExpressionKind::Shr(..) => OpCode::Shr(left, right, out),
+        ExpressionKind::ShrUnsigned(..) => OpCode::ShrUnsigned(left, right, out),
+        _ => unreachable!(),
+    }
+}
+
+/// An opcode taking a register, a constant and a register or a jump target.
+type ImmediateOpCode<T> = fn(Register, i64, T) -> OpCode;
+
+fn int_literal(expr: &Expression) -> Option<i64> {
+    match expr.kind {
+        ExpressionKind::Int(v) => Some(v),
+        _ => None,
+    }
+}
+
+/// Returns the operand, the constant and the superinstruction computing an integer operation
+/// with a literal operand.
+fn immediate_opcode(
+    kind: &ExpressionKind,
+) -> Option<(&Expression, i64, ImmediateOpCode<Register>)> {
+    use OperandType::Int;
+    match kind {
+        ExpressionKind::Add(Int, left, right) | ExpressionKind::Mul(Int, left, right) => {
+            let opcode: ImmediateOpCode<Register> = match kind {
+                ExpressionKind::Add(..) => OpCode::AddIntImm,
+                _ => OpCode::MulIntImm,
+            };
+            match (int_literal(left), int_literal(right)) {
+                (_, Some(v)) => Some((left, v, opcode)),
+                (Some(v), None) => Some((right, v, opcode)),
+                (None, None) => None,
+            }
+        }
+        ExpressionKind::Sub(Int, left, right) => {
+            int_literal(right).map(|v| (&**left, v.wrapping_neg(), OpCode::AddIntImm as _))
+        }
+        // The division by a literal `0` is left to fault as usual.
+        ExpressionKind::Div(Int, left, right) => (int_literal(right))
+            .filter(|&v| v != 0)
+            .map(|v| (&**left, v, OpCode::DivIntImm as _)),
+        ExpressionKind::Rem(Int, left, right) => (int_literal(right))
+            .filter(|&v| v != 0)
+            .map(|v| (&**left, v, OpCode::RemIntImm as _)),
+        _ => None,
+    }
+}
+
+/// Returns the operand, the constant and the superinstruction jumping when an integer comparison
+/// with a literal operand is false.
+fn branch_opcode(kind: &ExpressionKind) -> Option<(&Expression, i64, ImmediateOpCode<usize>)> {
+    use OperandType::Int;
+    // The opcodes when the literal is the right operand, then when it is the left one.
+    let (left, right, opcodes): (_, _, [ImmediateOpCode<usize>; 2]) = match kind {
+        ExpressionKind::Eq(Int, left, right) => (left, right, [OpCode::JmpAddrIfNotEqInt; 2]),
+        ExpressionKind::Ne(Int, left, right) => (left, right, [OpCode::JmpAddrIfNotNeInt; 2]),
+        ExpressionKind::Lt(Int, left, right) => (
+            left,
+            right,
+            [OpCode::JmpAddrIfNotLtInt, OpCode::JmpAddrIfNotGtInt],
+        ),
+        ExpressionKind::Le(Int, left, right) => (
+            left,
+            right,
+            [OpCode::JmpAddrIfNotLeInt, OpCode::JmpAddrIfNotGeInt],
+        ),
+        ExpressionKind::Gt(Int, left, right) => (
+            left,
+            right,
+            [OpCode::JmpAddrIfNotGtInt, OpCode::JmpAddrIfNotLtInt],
+        ),
+        ExpressionKind::Ge(Int, left, right) => (
+            left,
+            right,
+            [OpCode::JmpAddrIfNotGeInt, OpCode::JmpAddrIfNotLeInt],
+        ),
+        _ => return None,
+    };
+    match (int_literal(left), int_literal(right)) {
+        (_, Some(v)) => Some((left, v, opcodes[0])),
+        (Some(v), None) => Some((right, v, opcodes[1])),
+        (None, None) => None,
+    }
+}
+
+/// Returns the constant added to the variable `index` by `set.index = val`, if `val` only adds a
+/// literal to it.
+fn variable_increment(index: usize, val: &Expression) -> Option<i64> {
+    let is_var = |expr: &Expression| matches!(expr.kind, ExpressionKind::GetWithLiteralIndex(i) if i == index);
+    match &val.kind {
+        ExpressionKind::Add(OperandType::Int, left, right) if is_var(left) => int_literal(right),
+        ExpressionKind::Add(OperandType::Int, left, right) if is_var(right) => int_literal(left),
+        ExpressionKind::Sub(OperandType::Int, left, right) if is_var(left) => {
+            int_literal(right).map(i64::wrapping_neg)
+        }
+        _ => None,
+    }
+}
+
+/// Generates the code jumping to `label` when `cond` is false.
+fn generate_branch_code(
+    cond: &Expression,
+    label: Label,
+    state: &mut State,
+    name_table: &HashMap<String, FuncMeta>,
+) {
+    if state.superinstructions
+        && let Some((operand, v, opcode)) = branch_opcode(&cond.kind)
+    {
+        generate_expression_code(operand, &REGISTERS, state, name_table);
+        state.push(LabeledOpCode::without_label(OpCode::__JLNC_3170__(
+            opcode, R1, v, label,
+        )));
+    } else {
+        generate_expression_code(cond, &REGISTERS, state, name_table);
+        state.push(LabeledOpCode::without_label(OpCode::__JLIZ_2505__(
+            R1, label,
+        )));
+    }
+}
+
+/// Generates the code evaluating `left` and `right` with the registers `regs`, and returns the
+/// registers holding their values.
+///
+/// The operand needing more registers is evaluated first, so that the registers it leaves are
+/// enough for the other one, unless one of them has side effects. The first value is spilled to
+/// the stack when the other operand needs all the registers.
+fn generate_operands_code(
+    left: &Expression,
+    right: &Expression,
+    regs: &[Register],
+    state: &mut State,
+    name_table: &HashMap<String, FuncMeta>,
+) -> (Register, Register) {
+    let swap = registers_needed(right) > registers_needed(left)
+        && (optimizer::is_pure(left) || optimizer::is_pure(right));
+    let (first, second) = if swap { (right, left) } else { (left, right) };
+
+    generate_expression_code(first, regs, state, name_table);
+    let (first, second) = if registers_needed(second) < regs.len() {
+        generate_expression_code(second, &regs[1..], state, name_table);
+        (regs[0], regs[1])
+    } else {
+        state.push(LabeledOpCode::without_label(OpCode::Push(regs[0])));
+        generate_expression_code(second, regs, state, name_table);
+        state.push(LabeledOpCode::without_label(OpCode::Pop(regs[1])));
+        (regs[1], regs[0])
+    };
+    if swap {
+        (second, first)
+    } else {
+        (first, second)
+    }
+}
+
+/// Returns the callee and the arguments of `return f(...)` when the call can reuse the frame of
+/// the current function: the caller frees as many arguments as it pushed for this function, so
+/// `f` must take as many parameters.
+fn tail_call<'e>(
+    val: &'e Expression,
+    state: &State,
+    name_table: &HashMap<String, FuncMeta>,
+) -> Option<(FuncMeta, &'e [Expression])> {
+    let ExpressionKind::Call(funcname, args) = &val.kind else {
+        return None;
+    };
+    let fmeta = name_table[funcname];
+    (state.tail_calls && !state.is_main && fmeta.params_size == state.params_size)
+        .then_some((fmeta, &args[..]))
+}
+
+/// Generates the code evaluating `expr` into `regs[0]`, which may overwrite the other registers
+/// of `regs` but leaves the rest of `REGISTERS` as they are. `regs` is a suffix of `REGISTERS`.
 fn generate_expression_code(
     expr: &Expression,
+    regs: &[Register],
     state: &mut State,
     name_table: &HashMap<String, FuncMeta>,
 ) {
-    match expr {
-        Expression::Call(funcname, args) => {
-            let Some(fmeta) = name_table.get(funcname) else {
-                panic!("The function {}() is not defined", funcname);
-            };
-            let fmeta = *fmeta;
-            assert!(
-                fmeta.params_size == args.len(),
-                "{}() requires {} argument(s), but {} argument(s) passed",
-                funcname,
-                fmeta.params_size,
-                args.len()
-            );
+    let outer_span = core::mem::replace(&mut state.span, expr.span);
+    let out = regs[0];
+    match &expr.kind {
+        ExpressionKind::Call(funcname, args) => {
+            // `check_calls` has rejected the calls to undefined functions.
+            let fmeta = name_table[funcname];
+            // The callee overwrites every register, so the ones in use are saved.
+            let saved = &REGISTERS[..REGISTERS.len() - regs.len()];
+            for &r in saved {
+                state.push(LabeledOpCode::without_label(OpCode::Push(r)));
+            }
             for arg in args {
-                generate_expression_code(arg, state, name_table);
+                generate_expression_code(arg, &REGISTERS, state, name_table);
+                state.push(LabeledOpCode::without_label(OpCode::Push(R1)));
             }
             state.push(LabeledOpCode::without_label(OpCode::__FNCALL_2255__(
                 fmeta.index,
             )));
             state.push(LabeledOpCode::without_label(OpCode::Free(args.len())));
-            state.push(LabeledOpCode::without_label(OpCode::Push(RAX)));
+            state.push(LabeledOpCode::without_label(OpCode::Mov(RAX, out)));
             state.push(LabeledOpCode::without_label(OpCode::LoadInt(0, RAX)));
+            for &r in saved.iter().rev() {
+                state.push(LabeledOpCode::without_label(OpCode::Pop(r)));
+            }
         }
-        Expression::ReadInputByte => {
-            state.push(LabeledOpCode::without_label(OpCode::ReadByteFromStdin(R1)));
-            state.push(LabeledOpCode::without_label(OpCode::Push(R1)));
+        ExpressionKind::ReadInputByte => {
+            state.push(LabeledOpCode::without_label(OpCode::ReadByteFromStdin(out)));
         }
-        Expression::Neg(operand_type, operand) => {
-            generate_expression_code(operand, state, name_table);
-            state.push(LabeledOpCode::without_label(OpCode::Pop(R1)));
-            state.push(LabeledOpCode::without_label(match operand_type {
-                OperandType::Float => OpCode::NegFloat(R1, R2),
-                OperandType::Int => OpCode::NegInt(R1, R2),
-            }));
-            state.push(LabeledOpCode::without_label(OpCode::Push(R2)));
-        }
-        Expression::BitNot(operand) => {
-            generate_expression_code(operand, state, name_table);
-            state.push(LabeledOpCode::without_label(OpCode::Pop(R1)));
-            state.push(LabeledOpCode::without_label(OpCode::BitNot(R1, R2)));
-            state.push(LabeledOpCode::without_label(OpCode::Push(R2)));
-        }
-        Expression::LogiNot(operand) => {
-            generate_expression_code(operand, state, name_table);
-            state.push(LabeledOpCode::without_label(OpCode::Pop(R1)));
-            state.push(LabeledOpCode::without_label(OpCode::LogiNot(R1, R2)));
-            state.push(LabeledOpCode::without_label(OpCode::Push(R2)));
-        }
-        Expression::Itof(operand) => {
-            generate_expression_code(operand, state, name_table);
-            state.push(LabeledOpCode::without_label(OpCode::Pop(R1)));
-            state.push(LabeledOpCode::without_label(OpCode::IntToFloat(R1, R2)));
-            state.push(LabeledOpCode::without_label(OpCode::Push(R2)));
-        }
-        Expression::Ftoi(operand) => {
-            generate_expression_code(operand, state, name_table);
-            state.push(LabeledOpCode::without_label(OpCode::Pop(R1)));
-            state.push(LabeledOpCode::without_label(OpCode::FloatToInt(R1, R2)));
-            state.push(LabeledOpCode::without_label(OpCode::Push(R2)));
-        }
-        Expression::Shl(left, right) => {
-            generate_expression_code(left, state, name_table);
-            generate_expression_code(right, state, name_table);
-            state.push(LabeledOpCode::without_label(OpCode::Pop(R2)));
-            state.push(LabeledOpCode::without_label(OpCode::Pop(R1)));
-            state.push(LabeledOpCode::without_label(OpCode::Shl(R1, R2, R3)));
-            state.push(LabeledOpCode::without_label(OpCode::Push(R3)));
-        }
-        Expression::Shr(left, right) => {
-            generate_expression_code(left, state, name_table);
-            generate_expression_code(right, state, name_table);
-            state.push(LabeledOpCode::without_label(OpCode::Pop(R2)));
-            state.push(LabeledOpCode::without_label(OpCode::Pop(R1)));
-            state.push(LabeledOpCode::without_label(OpCode::Shr(R1, R2, R3)));
-            state.push(LabeledOpCode::without_label(OpCode::Push(R3)));
-        }
-        Expression::ShrUnsigned(left, right) => {
-            generate_expression_code(left, state, name_table);
-            generate_expression_code(right, state, name_table);
-            state.push(LabeledOpCode::without_label(OpCode::Pop(R2)));
-            state.push(LabeledOpCode::without_label(OpCode::Pop(R1)));
-            state.push(LabeledOpCode::without_label(OpCode::ShrUnsigned(R1, R2, R3)));
-            state.push(LabeledOpCode::without_label(OpCode::Push(R3)));
-        }
-        Expression::And(left, right) => {
-            generate_expression_code(left, state, name_table);
-            generate_expression_code(right, state, name_table);
-            state.push(LabeledOpCode::without_label(OpCode::Pop(R2)));
-            state.push(LabeledOpCode::without_label(OpCode::Pop(R1)));
-            state.push(LabeledOpCode::without_label(OpCode::And(R1, R2, R3)));
-            state.push(LabeledOpCode::without_label(OpCode::Push(R3)));
-        }
-        Expression::Or(left, right) => {
-            generate_expression_code(left, state, name_table);
-            generate_expression_code(right, state, name_table);
-            state.push(LabeledOpCode::without_label(OpCode::Pop(R2)));
-            state.push(LabeledOpCode::without_label(OpCode::Pop(R1)));
-            state.push(LabeledOpCode::without_label(OpCode::Or(R1, R2, R3)));
-            state.push(LabeledOpCode::without_label(OpCode::Push(R3)));
-        }
-        Expression::Xor(left, right) => {
-            generate_expression_code(left, state, name_table);
-            generate_expression_code(right, state, name_table);
-            state.push(LabeledOpCode::without_label(OpCode::Pop(R2)));
-            state.push(LabeledOpCode::without_label(OpCode::Pop(R1)));
-            state.push(LabeledOpCode::without_label(OpCode::Xor(R1, R2, R3)));
-            state.push(LabeledOpCode::without_label(OpCode::Push(R3)));
-        }
-        Expression::Lt(operand_type, left, right) => {
-            generate_expression_code(left, state, name_table);
-            generate_expression_code(right, state, name_table);
-            state.push(LabeledOpCode::without_label(OpCode::Pop(R2)));
-            state.push(LabeledOpCode::without_label(OpCode::Pop(R1)));
-            state.push(LabeledOpCode::without_label(match operand_type {
-                OperandType::Float => OpCode::LtFloat(R1, R2, R3),
-                OperandType::Int => OpCode::LtInt(R1, R2, R3),
-            }));
-            state.push(LabeledOpCode::without_label(OpCode::Push(R3)));
-        }
-        Expression::Le(operand_type, left, right) => {
-            generate_expression_code(left, state, name_table);
-            generate_expression_code(right, state, name_table);
-            state.push(LabeledOpCode::without_label(OpCode::Pop(R2)));
-            state.push(LabeledOpCode::without_label(OpCode::Pop(R1)));
-            state.push(LabeledOpCode::without_label(match operand_type {
-                OperandType::Float => OpCode::LeFloat(R1, R2, R3),
-                OperandType::Int => OpCode::LeInt(R1, R2, R3),
-            }));
-            state.push(LabeledOpCode::without_label(OpCode::Push(R3)));
-        }
-        Expression::Gt(operand_type, left, right) => {
-            generate_expression_code(left, state, name_table);
-            generate_expression_code(right, state, name_table);
-            state.push(LabeledOpCode::without_label(OpCode::Pop(R2)));
-            state.push(LabeledOpCode::without_label(OpCode::Pop(R1)));
-            state.push(LabeledOpCode::without_label(match operand_type {
-                OperandType::Float => OpCode::LtFloat(R2, R1, R3),
-                OperandType::Int => OpCode::LtInt(R2, R1, R3),
-            }));
-            state.push(LabeledOpCode::without_label(OpCode::Push(R3)));
-        }
-        Expression::Ge(operand_type, left, right) => {
-            generate_expression_code(left, state, name_table);
-            generate_expression_code(right, state, name_table);
-            state.push(LabeledOpCode::without_label(OpCode::Pop(R2)));
-            state.push(LabeledOpCode::without_label(OpCode::Pop(R1)));
-            state.push(LabeledOpCode::without_label(match operand_type {
-                OperandType::Float => OpCode::LeFloat(R2, R1, R3),
-                OperandType::Int => OpCode::LeInt(R2, R1, R3),
-            }));
-            state.push(LabeledOpCode::without_label(OpCode::Push(R3)));
-        }
-        Expression::Int(val) => {
-            state.push(LabeledOpCode::without_label(OpCode::LoadInt(*val, R1)));
-            state.push(LabeledOpCode::without_label(OpCode::Push(R1)));
-        }
-        Expression::Float(val) => {
-            state.push(LabeledOpCode::without_label(OpCode::LoadFloat(*val, R1)));
-            state.push(LabeledOpCode::without_label(OpCode::Push(R1)));
-        }
-        Expression::GetWithComputedIndex(index) => {
-            generate_expression_code(index, state, name_table);
-            state.push(LabeledOpCode::without_label(OpCode::Pop(R1)));
-            state.push(LabeledOpCode::without_label(OpCode::GetVarComputed(R1, R2)));
-            state.push(LabeledOpCode::without_label(OpCode::Push(R2)));
-        }
-        Expression::GetParam(index) => {
-            state.push(LabeledOpCode::without_label(OpCode::GetParam(*index, R1)));
-            state.push(LabeledOpCode::without_label(OpCode::Push(R1)));
-        }
-        Expression::GetWithLiteralIndex(index) => {
-            state.push(LabeledOpCode::without_label(OpCode::GetVar(*index, R1)));
-            state.push(LabeledOpCode::without_label(OpCode::Push(R1)));
-        }
-        Expression::Eq(operand_type, left, right) => {
-            generate_expression_code(left, state, name_table);
-            generate_expression_code(right, state, name_table);
-            state.push(LabeledOpCode::without_label(OpCode::Pop(R2)));
-            state.push(LabeledOpCode::without_label(OpCode::Pop(R1)));
-            state.push(LabeledOpCode::without_label(match operand_type {
-                OperandType::Float => OpCode::EqFloat(R1, R2, R3),
-                OperandType::Int => OpCode::EqInt(R1, R2, R3),
-            }));
-            state.push(LabeledOpCode::without_label(OpCode::Push(R3)));
-        }
-        Expression::Ne(operand_type, left, right) => {
-            generate_expression_code(left, state, name_table);
-            generate_expression_code(right, state, name_table);
-            state.push(LabeledOpCode::without_label(OpCode::Pop(R2)));
-            state.push(LabeledOpCode::without_label(OpCode::Pop(R1)));
-            state.push(LabeledOpCode::without_label(match operand_type {
-                OperandType::Float => OpCode::NeFloat(R1, R2, R3),
-                OperandType::Int => OpCode::NeInt(R1, R2, R3),
-            }));
-            state.push(LabeledOpCode::without_label(OpCode::Push(R3)));
-        }
-        Expression::Add(operand_type, left, right) => {
-            generate_expression_code(left, state, name_table);
-            generate_expression_code(right, state, name_table);
-            state.push(LabeledOpCode::without_label(OpCode::Pop(R2)));
-            state.push(LabeledOpCode::without_label(OpCode::Pop(R1)));
-            state.push(LabeledOpCode::without_label(match operand_type {
-                OperandType::Float => OpCode::AddFloat(R1, R2, R3),
-                OperandType::Int => OpCode::AddInt(R1, R2, R3),
-            }));
-            state.push(LabeledOpCode::without_label(OpCode::Push(R3)));
-        }
-        Expression::Sub(operand_type, left, right) => {
-            generate_expression_code(left, state, name_table);
-            generate_expression_code(right, state, name_table);
-            state.push(LabeledOpCode::without_label(OpCode::Pop(R2)));
-            state.push(LabeledOpCode::without_label(OpCode::Pop(R1)));
-            state.push(LabeledOpCode::without_label(match operand_type {
-                OperandType::Float => OpCode::SubFloat(R1, R2, R3),
-                OperandType::Int => OpCode::SubInt(R1, R2, R3),
-            }));
-            state.push(LabeledOpCode::without_label(OpCode::Push(R3)));
-        }
-        Expression::Mul(operand_type, left, right) => {
-            generate_expression_code(left, state, name_table);
-            generate_expression_code(right, state, name_table);
-            state.push(LabeledOpCode::without_label(OpCode::Pop(R2)));
-            state.push(LabeledOpCode::without_label(OpCode::Pop(R1)));
-            state.push(LabeledOpCode::without_label(match operand_type {
-                OperandType::Float => OpCode::MulFloat(R1, R2, R3),
-                OperandType::Int => OpCode::MulInt(R1, R2, R3),
-            }));
-            state.push(LabeledOpCode::without_label(OpCode::Push(R3)));
-        }
-        Expression::Div(operand_type, left, right) => {
-            generate_expression_code(left, state, name_table);
-            generate_expression_code(right, state, name_table);
-            state.push(LabeledOpCode::without_label(OpCode::Pop(R2)));
-            state.push(LabeledOpCode::without_label(OpCode::Pop(R1)));
-            state.push(LabeledOpCode::without_label(match operand_type {
-                OperandType::Float => OpCode::DivFloat(R1, R2, R3),
-                OperandType::Int => OpCode::DivInt(R1, R2, R3),
-            }));
-            state.push(LabeledOpCode::without_label(OpCode::Push(R3)));
-        }
-        Expression::Rem(operand_type, left, right) => {
-            generate_expression_code(left, state, name_table);
-            generate_expression_code(right, state, name_table);
-            state.push(LabeledOpCode::without_label(OpCode::Pop(R2)));
-            state.push(LabeledOpCode::without_label(OpCode::Pop(R1)));
-            state.push(LabeledOpCode::without_label(match operand_type {
-                OperandType::Float => OpCode::RemFloat(R1, R2, R3),
-                OperandType::Int => OpCode::RemInt(R1, R2, R3),
+        ExpressionKind::Int(val) => {
+            state.push(LabeledOpCode::without_label(OpCode::LoadInt(*val, out)));
+        }
+        ExpressionKind::Float(val) => {
+            state.push(LabeledOpCode::without_label(OpCode::LoadFloat(*val, out)));
+        }
+        ExpressionKind::GetParam(index) => {
+            state.push(LabeledOpCode::without_label(OpCode::GetParam(*index, out)));
+        }
+        ExpressionKind::GetWithLiteralIndex(index) => {
+            state.push(LabeledOpCode::without_label(OpCode::GetVar(*index, out)));
+        }
+        ExpressionKind::GetWithComputedIndex(index) => {
+            generate_expression_code(index, regs, state, name_table);
+            state.push(LabeledOpCode::without_label(OpCode::GetVarComputed(
+                out,
+                state.alloc_size,
+                out,
+            )));
+        }
+        ExpressionKind::Neg(_, operand)
+        | ExpressionKind::BitNot(operand)
+        | ExpressionKind::LogiNot(operand)
+        | ExpressionKind::Itof(operand)
+        | ExpressionKind::Ftoi(operand) => {
+            generate_expression_code(operand, regs, state, name_table);
+            state.push(LabeledOpCode::without_label(match &expr.kind {
+                ExpressionKind::Neg(OperandType::Float, _) => OpCode::NegFloat(out, out),
+                ExpressionKind::Neg(OperandType::Int, _) => OpCode::NegInt(out, out),
+                ExpressionKind::BitNot(_) => OpCode::BitNot(out, out),
+                ExpressionKind::LogiNot(_) => OpCode::LogiNot(out, out),
+                ExpressionKind::Itof(_) => OpCode::IntToFloat(out, out),
+                _ => OpCode::FloatToInt(out, out),
             }));
-            state.push(LabeledOpCode::without_label(OpCode::Push(R3)));
+        }
+        kind if state.superinstructions
+            && let Some((operand, v, opcode)) = immediate_opcode(kind) =>
+        {
+            generate_expression_code(operand, regs, state, name_table);
+            state.push(LabeledOpCode::without_label(opcode(out, v, out)));
+        }
+        kind => {
+            let [left, right] = kind.operands()[..] else {
+                unreachable!()
+            };
+            let (left, right) = generate_operands_code(left, right, regs, state, name_table);
+            state.push(LabeledOpCode::without_label(binary_opcode(
+                kind, left, right, out,
+            )));
         }
     }
+    state.span = outer_span;
 }
 
 fn generate_statement_code(
@@ -336,53 +415,66 @@ fn generate_statement_code(
     state: &mut State,
     name_table: &HashMap<String, FuncMeta>,
 ) {
-    match stmt {
-        Statement::If {
+    let outer_span = core::mem::replace(&mut state.span, stmt.span);
+    match &stmt.kind {
+        StatementKind::If {
             cond,
             then_branch,
             unless_branch,
         } => {
             let else_label = state.new_label();
-            let end_label = state.new_label();
-            generate_expression_code(cond, state, name_table);
-            state.push(LabeledOpCode::without_label(OpCode::Pop(R1)));
-            state.push(LabeledOpCode::without_label(OpCode::__JLIZ_2505__(
-                R1, else_label,
-            )));
+            generate_branch_code(cond, else_label, state, name_table);
             generate_statement_code(then_branch, state, name_table);
-            state.push(LabeledOpCode::without_label(OpCode::__JL_0515__(end_label)));
-            state.push(LabeledOpCode::with_one_label(else_label, OpCode::Nop));
-            generate_statement_code(unless_branch, state, name_table);
-            state.push(LabeledOpCode::with_one_label(end_label, OpCode::Nop));
+            // Without an `else` branch, the first one just falls through to the end.
+            if let Some(unless_branch) = unless_branch {
+                let end_label = state.new_label();
+                // Nothing jumps to the end if the first branch returns or jumps away.
+                let jumps_to_end =
+                    !(state.dead_code_elimination && optimizer::never_completes(then_branch));
+                if jumps_to_end {
+                    state.push(LabeledOpCode::without_label(OpCode::__JL_0515__(end_label)));
+                }
+                state.push(LabeledOpCode::with_one_label(else_label, OpCode::Nop));
+                generate_statement_code(unless_branch, state, name_table);
+                if jumps_to_end {
+                    state.push(LabeledOpCode::with_one_label(end_label, OpCode::Nop));
+                }
+            } else {
+                state.push(LabeledOpCode::with_one_label(else_label, OpCode::Nop));
+            }
         }
-        Statement::While { cond, body } => {
+        StatementKind::While { cond, body } => {
             let begin_label = state.new_label();
             let end_label = state.new_label();
             state.push(LabeledOpCode::with_one_label(begin_label, OpCode::Nop));
-            generate_expression_code(cond, state, name_table);
-            state.push(LabeledOpCode::without_label(OpCode::Pop(R1)));
-            state.push(LabeledOpCode::without_label(OpCode::__JLIZ_2505__(
-                R1, end_label,
-            )));
+            generate_branch_code(cond, end_label, state, name_table);
+            state.loops.push((begin_label, end_label));
             generate_statement_code(body, state, name_table);
+            state.loops.pop();
             state.push(LabeledOpCode::without_label(OpCode::__JL_0515__(
                 begin_label,
             )));
             state.push(LabeledOpCode::with_one_label(end_label, OpCode::Nop));
         }
-        Statement::SetWithComputedIndex { index, val } => {
-            generate_expression_code(index, state, name_table);
-            generate_expression_code(val, state, name_table);
-            state.push(LabeledOpCode::without_label(OpCode::Pop(R2)));
-            state.push(LabeledOpCode::without_label(OpCode::Pop(R1)));
-            state.push(LabeledOpCode::without_label(OpCode::SetVarComputed(R1, R2)));
+        StatementKind::SetWithComputedIndex { index, val } => {
+            let (index, val) = generate_operands_code(index, val, &REGISTERS, state, name_table);
+            state.push(LabeledOpCode::without_label(OpCode::SetVarComputed(
+                index,
+                state.alloc_size,
+                val,
+            )));
+        }
+        StatementKind::SetWithLiteralIndex { index, val }
+            if state.superinstructions
+                && let Some(v) = variable_increment(*index, val) =>
+        {
+            state.push(LabeledOpCode::without_label(OpCode::AddVarInt(*index, v)));
         }
-        Statement::SetWithLiteralIndex { index, val } => {
-            generate_expression_code(val, state, name_table);
-            state.push(LabeledOpCode::without_label(OpCode::Pop(R1)));
+        StatementKind::SetWithLiteralIndex { index, val } => {
+            generate_expression_code(val, &REGISTERS, state, name_table);
             state.push(LabeledOpCode::without_label(OpCode::SetVar(*index, R1)));
         }
-        Statement::Block { stmts } => {
+        StatementKind::Block { stmts } => {
             for stmt in stmts {
                 generate_statement_code(stmt, state, name_table);
             }
@@ -397,27 +489,65 @@ fn generate_statement_code(
         //     state.push(LabeledOpCode::without_label(OpCode::Pop(R1)));
         //     state.push(LabeledOpCode::without_label(OpCode::PrintInt(R1)));
         // }
-        Statement::PutByte { val } => {
-            generate_expression_code(val, state, name_table);
-            state.push(LabeledOpCode::without_label(OpCode::Pop(R1)));
+        StatementKind::PutByte { val } => {
+            generate_expression_code(val, &REGISTERS, state, name_table);
             state.push(LabeledOpCode::without_label(OpCode::PutByte(R1)));
         }
-        Statement::Return { val } => {
-            generate_expression_code(val, state, name_table);
-            state.push(LabeledOpCode::without_label(OpCode::Pop(RAX)));
-            state.push(LabeledOpCode::without_label(OpCode::MovEbpToEspAndPopRbp));
-            state.push(LabeledOpCode::without_label(OpCode::Ret));
+        StatementKind::PutBytes { start, len } => {
+            let (start, len) = generate_operands_code(start, len, &REGISTERS, state, name_table);
+            state.push(LabeledOpCode::without_label(OpCode::PutBytes(
+                start,
+                state.alloc_size,
+                len,
+            )));
+        }
+        StatementKind::Return { val }
+            if let Some((fmeta, args)) = tail_call(val, state, name_table) =>
+        {
+            // The arguments replace the parameters once they are all evaluated, as they may read
+            // them.
+            for arg in args {
+                generate_expression_code(arg, &REGISTERS, state, name_table);
+                state.push(LabeledOpCode::without_label(OpCode::Push(R1)));
+            }
+            state.push(LabeledOpCode::without_label(OpCode::__TLCALL_4096__(
+                fmeta.params_size,
+                fmeta.index,
+            )));
+        }
+        StatementKind::Return { val } => {
+            generate_expression_code(val, &REGISTERS, state, name_table);
+            state.push(LabeledOpCode::without_label(OpCode::Mov(R1, RAX)));
+            if state.is_main {
+                // main() has no caller to return to.
+                state.push(LabeledOpCode::without_label(OpCode::Exit));
+            } else {
+                state.push(LabeledOpCode::without_label(OpCode::MovEbpToEspAndPopRbp));
+                state.push(LabeledOpCode::without_label(OpCode::Ret));
+            }
+        }
+        StatementKind::Break { depth } => {
+            let (_, end_label) = state.loops[state.loops.len() - 1 - depth];
+            state.push(LabeledOpCode::without_label(OpCode::__JL_0515__(end_label)));
         }
-        Statement::Expr { expr } => {
-            generate_expression_code(expr, state, name_table);
-            state.push(LabeledOpCode::without_label(OpCode::Free(1)));
+        StatementKind::Continue { depth } => {
+            let (begin_label, _) = state.loops[state.loops.len() - 1 - depth];
+            state.push(LabeledOpCode::without_label(OpCode::__JL_0515__(
+                begin_label,
+            )));
+        }
+        StatementKind::Expr { expr } => {
+            generate_expression_code(expr, &REGISTERS, state, name_table);
         }
     }
+    state.span = outer_span;
 }
 
 struct LabeledOpCode {
     labels: Vec<Label>,
     code: OpCode,
+    /// Filled with the span of the node being generated by `State::push`.
+    span: Option<Span>,
 }
 
 impl LabeledOpCode {
@@ -425,16 +555,22 @@ impl LabeledOpCode {
         Self {
             labels: Vec::new(),
             code,
+            span: None,
         }
     }
     fn with_one_label(label: Label, code: OpCode) -> Self {
         Self {
             labels: vec![label],
             code,
+            span: None,
         }
     }
     fn with_labels(labels: Vec<Label>, code: OpCode) -> Self {
-        Self { labels, code }
+        Self {
+            labels,
+            code,
+            span: None,
+        }
     }
 }
 
@@ -447,16 +583,45 @@ struct FuncMeta {
 struct State<'a> {
     codes: Vec<LabeledOpCode>,
     next_label_val: &'a mut usize,
+    is_main: bool,
+    params_size: usize,
+    alloc_size: usize,
+    /// Whether to leave out the jumps and the epilogue after the statements that always return,
+    /// from `-O1`.
+    dead_code_elimination: bool,
+    /// Whether `return f(...)` reuses the frame for the call when `f` takes as many parameters,
+    /// from `-O1`.
+    tail_calls: bool,
+    /// Whether to fuse common opcode sequences into single opcodes, from `-O1`.
+    superinstructions: bool,
+    /// The labels of the condition and of the end of the enclosing loops, innermost last.
+    loops: Vec<(Label, Label)>,
+    /// The span of the innermost node being generated.
+    span: Span,
 }
 
 impl<'a> State<'a> {
-    fn new(next_label_val: &'a mut usize) -> Self {
+    fn new(
+        next_label_val: &'a mut usize,
+        f: &FunctionData,
+        level: OptLevel,
+        superinstructions: bool,
+    ) -> Self {
         Self {
             codes: Vec::new(),
             next_label_val,
+            is_main: f.name == "main",
+            params_size: f.params_size,
+            alloc_size: f.alloc_size,
+            dead_code_elimination: level >= OptLevel::O1,
+            tail_calls: level >= OptLevel::O1,
+            superinstructions: superinstructions && level >= OptLevel::O1,
+            loops: Vec::new(),
+            span: f.span,
         }
     }
-    fn push(&mut self, code: LabeledOpCode) {
+    fn push(&mut self, mut code: LabeledOpCode) {
+        code.span.get_or_insert(self.span);
         self.codes.push(code);
     }
     fn new_label(&mut self) -> Label {
@@ -469,24 +634,54 @@ impl<'a> State<'a> {
     }
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
+pub struct FunctionInfo {
+    pub name: String,
+    /// The index of the first opcode of the function.
+    pub start: usize,
+    /// The index just past the last opcode of the function.
+    pub end: usize,
+    pub params_size: usize,
+    pub alloc_size: usize,
+}
+
+#[derive(Debug, Clone)]
 pub struct Codes {
     pub entry_point: usize,
     pub opcodes: Vec<OpCode>,
+    /// The functions in the order of their opcodes.
+    pub functions: Vec<FunctionInfo>,
+    /// The source location each opcode was generated from. Empty if the codes were not generated
+    /// from source.
+    pub spans: Vec<Span>,
+}
+
+impl Codes {
+    /// Returns the function that contains the opcode at `index`.
+    pub fn function_at(&self, index: usize) -> Option<&FunctionInfo> {
+        let i = self.functions.partition_point(|f| f.start <= index);
+        self.functions[..i].last().filter(|f| index < f.end)
+    }
 }
 
 fn generate_function_code(
     f: &FunctionData,
     next_label_val: &mut usize,
     name_table: &HashMap<String, FuncMeta>,
+    level: OptLevel,
+    superinstructions: bool,
 ) -> Vec<LabeledOpCode> {
-    let mut state = State::new(next_label_val);
+    let mut state = State::new(next_label_val, f, level, superinstructions);
 
     state.push(LabeledOpCode::without_label(OpCode::PushRbpAndMovEspToEbp));
-    state.push(LabeledOpCode::without_label(OpCode::Alloc(f.alloc_size)));
-    generate_statement_code(&f.body, &mut state, &name_table);
+    state.push(LabeledOpCode::without_label(OpCode::Alloc(
+        f.alloc_size + f.inline_alloc_size,
+    )));
+    generate_statement_code(&f.body, &mut state, name_table);
 
-    if f.name == "main" {
+    if state.dead_code_elimination && optimizer::never_completes(&f.body) {
+        // The epilogue would never be executed.
+    } else if state.is_main {
         state.push(LabeledOpCode::without_label(OpCode::Exit));
     } else {
         state.push(LabeledOpCode::without_label(OpCode::MovEbpToEspAndPopRbp));
@@ -522,28 +717,81 @@ fn generate_function_code(
             op.labels.append(&mut label_tmp);
         }
     }
-    opcodes.push(LabeledOpCode::with_labels(label_tmp, OpCode::Nop));
+    let mut last = LabeledOpCode::with_labels(label_tmp, OpCode::Nop);
+    last.span = Some(f.span);
+    opcodes.push(last);
 
     // Remove unlabeled Nop
     let opcodes: Vec<LabeledOpCode> = opcodes
         .into_iter()
         .filter(|op| {
             !(op.labels.is_empty()
-                && match op.code {
-                    OpCode::Nop => true,
-                    OpCode::Alloc(0) => true,
-                    OpCode::Free(0) => true,
-                    _ => false,
-                })
+                && matches!(op.code, OpCode::Nop | OpCode::Alloc(0) | OpCode::Free(0)))
         })
         .collect();
 
     opcodes
 }
 
-pub fn generate(prog: Program) -> Codes {
-    let name_table: HashMap<String, FuncMeta> = prog
-        .funcs
+/// Reports every call in `expr` to an undefined function or with a wrong number of arguments.
+fn check_calls_in_expression(
+    expr: &Expression,
+    name_table: &HashMap<String, FuncMeta>,
+    declared: &HashSet<String>,
+    errors: &mut Vec<CompileError>,
+) {
+    if let ExpressionKind::Call(funcname, args) = &expr.kind {
+        match name_table.get(funcname) {
+            // Its definition has errors already reported.
+            None if declared.contains(funcname) => {}
+            None => errors.push(CompileError::new(
+                format!("the function {}() is not defined", funcname),
+                expr.span,
+            )),
+            Some(fmeta) if fmeta.params_size != args.len() => errors.push(CompileError::new(
+                format!(
+                    "{}() requires {} argument(s), but {} argument(s) passed",
+                    funcname,
+                    fmeta.params_size,
+                    args.len()
+                ),
+                expr.span,
+            )),
+            Some(_) => {}
+        }
+    }
+    for operand in expr.kind.operands() {
+        check_calls_in_expression(operand, name_table, declared, errors);
+    }
+}
+
+fn check_calls_in_statement(
+    stmt: &Statement,
+    name_table: &HashMap<String, FuncMeta>,
+    declared: &HashSet<String>,
+    errors: &mut Vec<CompileError>,
+) {
+    for expr in stmt.kind.expressions() {
+        check_calls_in_expression(expr, name_table, declared, errors);
+    }
+    for stmt in stmt.kind.statements() {
+        check_calls_in_statement(stmt, name_table, declared, errors);
+    }
+}
+
+/// Returns an error for every call in `funcs` to an undefined function or with a wrong number
+/// of arguments, where calling a function of `declared` missing from `funcs` is not an error.
+pub(crate) fn check_calls(funcs: &[FunctionData], declared: &HashSet<String>) -> Vec<CompileError> {
+    let name_table = build_name_table(funcs);
+    let mut errors = Vec::new();
+    for f in funcs {
+        check_calls_in_statement(&f.body, &name_table, declared, &mut errors);
+    }
+    errors
+}
+
+fn build_name_table(funcs: &[FunctionData]) -> HashMap<String, FuncMeta> {
+    funcs
         .iter()
         .enumerate()
         .map(|(index, f)| {
@@ -555,13 +803,48 @@ pub fn generate(prog: Program) -> Codes {
                 },
             )
         })
-        .collect();
+        .collect()
+}
+
+/// Generates the opcodes of the program with the default optimizations.
+pub fn generate(prog: Program) -> Result<Codes, Vec<CompileError>> {
+    generate_with(prog, OptLevel::default())
+}
+
+/// Generates the opcodes of the program optimized at `level`, reporting every undefined function
+/// and argument count mismatch found, even in code that the optimizations remove.
+pub fn generate_with(prog: Program, level: OptLevel) -> Result<Codes, Vec<CompileError>> {
+    generate_with_superinstructions(prog, level, true)
+}
+
+/// Like [`generate_with`], fusing common opcode sequences from `-O1` only if `superinstructions`
+/// is set, to measure what they save.
+pub fn generate_with_superinstructions(
+    prog: Program,
+    level: OptLevel,
+    superinstructions: bool,
+) -> Result<Codes, Vec<CompileError>> {
+    let errors = check_calls(&prog.funcs, &HashSet::new());
+    if !errors.is_empty() {
+        return Err(errors);
+    }
+    // The optimizations may remove functions.
+    let prog = optimizer::optimize(prog, level);
+    let name_table = build_name_table(&prog.funcs);
 
     let mut next_label_val = 0;
     let func_codes: Vec<Vec<LabeledOpCode>> = prog
         .funcs
         .iter()
-        .map(|f| generate_function_code(f, &mut next_label_val, &name_table))
+        .map(|f| {
+            generate_function_code(
+                f,
+                &mut next_label_val,
+                &name_table,
+                level,
+                superinstructions,
+            )
+        })
         .collect();
 
     let func_addrs = func_codes
@@ -573,20 +856,6 @@ pub fn generate(prog: Program) -> Codes {
         })
         .0;
 
-    // /* for debug */ {
-    //     use std::io::Write as _;
-    //     let mut ffffffff = std::fs::File::create("codes.txt").unwrap();
-    //     for (i, fcodes) in func_codes.iter().enumerate() {
-    //         writeln!(ffffffff, "{:?}:", FuncLabel(i)).unwrap();
-    //         for c in fcodes {
-    //             for l in &c.labels {
-    //                 writeln!(ffffffff, "{:?}:", l).unwrap();
-    //             }
-    //             writeln!(ffffffff, "  {:?}", c.code).unwrap();
-    //         }
-    //     }
-    // }
-
     let opcodes: Vec<LabeledOpCode> = func_codes.into_iter().flatten().collect();
 
     // Resolve labels as indices
@@ -598,12 +867,17 @@ pub fn generate(prog: Program) -> Codes {
             label_indices[label.0] = index;
         }
     }
+    let mut spans: Vec<Span> = opcodes.iter().map(|op| op.span.unwrap()).collect();
     let mut opcodes: Vec<OpCode> = opcodes
         .into_iter()
         .map(|op| match op.code {
             OpCode::__JL_0515__(label) => OpCode::JmpAddr(label_indices[label.0]),
             OpCode::__JLIZ_2505__(r1, label) => OpCode::JmpAddrIfZero(r1, label_indices[label.0]),
+            OpCode::__JLNC_3170__(opcode, r1, v, label) => opcode(r1, v, label_indices[label.0]),
             OpCode::__FNCALL_2255__(index) => OpCode::FunctionCall(func_addrs[index.0]),
+            OpCode::__TLCALL_4096__(params_size, index) => {
+                OpCode::TailCall(params_size, func_addrs[index.0])
+            }
             _ => op.code,
         })
         .collect();
@@ -611,12 +885,35 @@ pub fn generate(prog: Program) -> Codes {
     // Remove last Opcode if it's Nop
     if matches!(opcodes.last(), Some(OpCode::Nop)) {
         opcodes.pop();
+        spans.pop();
     }
 
-    let entry_point = func_addrs[name_table["main"].index.0];
+    // A `Program` built by hand may lack the `main` that `parse_program` requires.
+    let Some(main) = name_table.get("main") else {
+        return Err(vec![CompileError::unlocated(
+            "the function main() is missing",
+        )]);
+    };
+    let entry_point = func_addrs[main.index.0];
+
+    let functions = prog
+        .funcs
+        .iter()
+        .zip(&func_addrs)
+        .enumerate()
+        .map(|(i, (f, &start))| FunctionInfo {
+            name: f.name.clone(),
+            start,
+            end: func_addrs.get(i + 1).copied().unwrap_or(opcodes.len()),
+            params_size: f.params_size,
+            alloc_size: f.alloc_size + f.inline_alloc_size,
+        })
+        .collect();
 
-    Codes {
+    Ok(Codes {
         entry_point,
         opcodes,
-    }
+        functions,
+        spans,
+    })
 }
@@ -0,0 +1,317 @@
+//! A source-level debugger driven by line commands.
+//!
+//! The [`Debugger`] is an [`Observer`] that stops before the first line of `main`, at
+//! breakpoints and after stepping, and then reads commands such as `break 12`, `next` or
+//! `print get.0` until one resumes the execution. The `help` command lists them.
+
+use std::io::{BufRead, Write};
+use std::ops::ControlFlow;
+
+use crate::code_generator::{Codes, OpCode};
+use crate::executor::{Machine, Observer, Value};
+
+const HELP: &str = "\
+break LINE | FILE:LINE | FUNCTION   set a breakpoint (b)
+delete N                            delete the breakpoint numbered N (d)
+continue                            run until a breakpoint (c)
+step                                run until another line, entering calls (s)
+next                                run until another line of this or a calling function (n)
+finish                              run until the current function returns (f)
+print get.N | param.N               print a local variable or a parameter (p)
+backtrace                           print the call stack (bt)
+quit                                end the program (q)
+";
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Breakpoint {
+    Line(usize),
+    Function(String),
+}
+
+/// Where to stop next, besides the breakpoints.
+#[derive(Debug, Clone, Copy)]
+enum Resume {
+    Continue,
+    /// At the first opcode on another line or in another frame.
+    Step {
+        line: usize,
+        rbp: usize,
+    },
+    /// At the first opcode on another line in the frame at `rbp` or a calling one.
+    Next {
+        line: usize,
+        rbp: usize,
+    },
+    /// At the first opcode in a frame calling the one at `rbp`.
+    Finish {
+        rbp: usize,
+    },
+}
+
+/// A line and the frame executing it.
+type Location = (usize, usize);
+
+/// Where the debugger reads its commands from, a line at a time.
+pub trait Commands {
+    /// Appends the next line to `buf`, returning the number of bytes read, 0 at the end.
+    fn read_line(&mut self, buf: &mut String) -> std::io::Result<usize>;
+}
+
+impl<R: BufRead> Commands for R {
+    fn read_line(&mut self, buf: &mut String) -> std::io::Result<usize> {
+        BufRead::read_line(self, buf)
+    }
+}
+
+/// The standard input, locked only while a command is read so that `getc` in the debugged
+/// program can read it too.
+#[derive(Debug, Default)]
+pub struct StdinCommands {
+    _private: (),
+}
+
+impl StdinCommands {
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+impl Commands for StdinCommands {
+    fn read_line(&mut self, buf: &mut String) -> std::io::Result<usize> {
+        std::io::stdin().read_line(buf)
+    }
+}
+
+pub struct Debugger<'a, R, W> {
+    source: &'a str,
+    path: &'a str,
+    commands: R,
+    out: W,
+    breakpoints: Vec<Option<Breakpoint>>,
+    resume: Resume,
+    /// The location of the last opcode executed in a frame, to detect entering a line.
+    last: Option<Location>,
+    /// Whether the program has started, to check once that it has line information.
+    started: bool,
+    /// Whether the commands have ended, after which the program runs to its end.
+    detached: bool,
+}
+
+impl<'a, R: Commands, W: Write> Debugger<'a, R, W> {
+    /// Creates a debugger for the program compiled from `source` read at `path`, which reads
+    /// commands from `commands` and writes to `out`.
+    pub fn new(source: &'a str, path: &'a str, commands: R, out: W) -> Self {
+        Self {
+            source,
+            path,
+            commands,
+            out,
+            breakpoints: Vec::new(),
+            // Stops at the first line.
+            resume: Resume::Step {
+                line: 0,
+                rbp: usize::MAX,
+            },
+            last: None,
+            started: false,
+            detached: false,
+        }
+    }
+
+    fn line(codes: &Codes, index: usize) -> Option<usize> {
+        codes.spans.get(index).map(|span| span.line)
+    }
+
+    fn should_stop(&self, machine: &Machine<'_>, line: usize) -> bool {
+        let location = (line, machine.rbp);
+        let entered_line = self.last != Some(location);
+        let hits_breakpoint = self.breakpoints.iter().flatten().any(|b| match b {
+            Breakpoint::Line(l) => entered_line && *l == line,
+            Breakpoint::Function(name) => machine.function().is_some_and(|f| {
+                f.name == *name && first_line_opcode(machine.codes, f.start) == machine.rip
+            }),
+        });
+        hits_breakpoint
+            || match self.resume {
+                Resume::Continue => false,
+                Resume::Step { line: l, rbp } => (l, rbp) != location,
+                Resume::Next { line: l, rbp } => {
+                    machine.rbp < rbp || (machine.rbp == rbp && l != line)
+                }
+                Resume::Finish { rbp } => machine.rbp < rbp,
+            }
+    }
+
+    fn show_location(&mut self, machine: &Machine<'_>, line: usize) -> std::io::Result<()> {
+        let function = machine.function().map_or("?", |f| f.name.as_str());
+        let text = (line.checked_sub(1))
+            .and_then(|index| self.source.lines().nth(index))
+            .unwrap_or("")
+            .trim();
+        writeln!(self.out, "{}() at {}:{}", function, self.path, line)?;
+        writeln!(self.out, "{:>5} | {}", line, text)
+    }
+
+    fn set_breakpoint(&mut self, machine: &Machine<'_>, location: &str) -> String {
+        let target = match location.rsplit_once(':') {
+            Some((path, target)) if path == self.path => target,
+            Some((path, _)) => return format!("no source file `{}`", path),
+            None => location,
+        };
+        let breakpoint = match target.parse::<usize>() {
+            Ok(line) => {
+                let has_code = (0..machine.codes.opcodes.len())
+                    .any(|i| Self::line(machine.codes, i) == Some(line));
+                if !has_code {
+                    return format!("no code at line {}", line);
+                }
+                Breakpoint::Line(line)
+            }
+            Err(_) => {
+                if !machine.codes.functions.iter().any(|f| f.name == target) {
+                    return format!("no function `{}`", target);
+                }
+                Breakpoint::Function(target.to_owned())
+            }
+        };
+        self.breakpoints.push(Some(breakpoint));
+        format!("breakpoint {} at {}", self.breakpoints.len(), location)
+    }
+
+    fn print(&self, machine: &Machine<'_>, what: &str) -> String {
+        let value = |value: Option<Value>| {
+            value.map_or_else(|| "out of range".to_owned(), |v| format!("{:?}", v))
+        };
+        let index = |prefix: &str| what.strip_prefix(prefix)?.parse::<usize>().ok();
+        if let Some(index) = index("get.") {
+            format!("get.{} = {}", index, value(machine.local(index)))
+        } else if let Some(index) = index("param.") {
+            format!("param.{} = {}", index, value(machine.param(index)))
+        } else {
+            "expected `get.N` or `param.N`".to_owned()
+        }
+    }
+
+    /// Reads commands until one resumes the execution.
+    fn prompt(&mut self, machine: &Machine<'_>, line: usize) -> std::io::Result<ControlFlow<()>> {
+        loop {
+            write!(self.out, "(abyssix) ")?;
+            self.out.flush()?;
+            let mut command = String::new();
+            if self.commands.read_line(&mut command)? == 0 {
+                writeln!(self.out)?;
+                self.detached = true;
+                return Ok(ControlFlow::Continue(()));
+            }
+            let mut words = command.split_whitespace();
+            let (Some(name), argument) = (words.next(), words.next()) else {
+                continue;
+            };
+            let frame = machine.rbp;
+            let message = match (name, argument) {
+                ("break" | "b", Some(location)) => self.set_breakpoint(machine, location),
+                ("delete" | "d", Some(number)) => match number
+                    .parse::<usize>()
+                    .ok()
+                    .and_then(|n| self.breakpoints.get_mut(n.checked_sub(1)?))
+                {
+                    Some(breakpoint) if breakpoint.is_some() => {
+                        *breakpoint = None;
+                        format!("deleted breakpoint {}", number)
+                    }
+                    _ => format!("no breakpoint {}", number),
+                },
+                ("continue" | "c", None) => {
+                    self.resume = Resume::Continue;
+                    return Ok(ControlFlow::Continue(()));
+                }
+                ("step" | "s", None) => {
+                    self.resume = Resume::Step { line, rbp: frame };
+                    return Ok(ControlFlow::Continue(()));
+                }
+                ("next" | "n", None) => {
+                    self.resume = Resume::Next { line, rbp: frame };
+                    return Ok(ControlFlow::Continue(()));
+                }
+                ("finish" | "f", None) => {
+                    self.resume = Resume::Finish { rbp: frame };
+                    return Ok(ControlFlow::Continue(()));
+                }
+                ("print" | "p", Some(what)) => self.print(machine, what),
+                ("backtrace" | "bt", None) => machine
+                    .backtrace()
+                    .iter()
+                    .enumerate()
+                    .map(|(depth, frame)| {
+                        let line = frame.span.map_or(0, |span| span.line);
+                        format!("{}: {}() at {}:{}", depth, frame.function, self.path, line)
+                    })
+                    .collect::<Vec<_>>()
+                    .join("\n"),
+                ("quit" | "q", None) => return Ok(ControlFlow::Break(())),
+                ("help" | "h", None) => HELP.trim_end().to_owned(),
+                _ => format!("unknown command `{}`; try `help`", command.trim()),
+            };
+            writeln!(self.out, "{}", message)?;
+        }
+    }
+}
+
+/// Returns the index of the first opcode after the prologue of the function starting at
+/// `start`, where a breakpoint on the function stops.
+fn first_line_opcode(codes: &Codes, start: usize) -> usize {
+    let mut index = start;
+    while let Some(opcode) = codes.opcodes.get(index)
+        && matches!(opcode, OpCode::PushRbpAndMovEspToEbp | OpCode::Alloc(_))
+    {
+        index += 1;
+    }
+    index
+}
+
+impl<R: Commands, W: Write> Observer for Debugger<'_, R, W> {
+    fn before(&mut self, machine: &Machine<'_>) -> ControlFlow<()> {
+        if !self.started {
+            self.started = true;
+            // Assembled code and files compiled with `--no-debug-lines` have no spans.
+            if machine.codes.spans.is_empty() {
+                let warning = writeln!(
+                    self.out,
+                    "warning: {} has no line information, so no breakpoint can be hit",
+                    self.path
+                );
+                if warning.is_err() {
+                    return ControlFlow::Break(());
+                }
+            }
+        }
+        // The epilogue is attributed to the line of the function name, where stopping would be
+        // confusing.
+        if !machine.is_in_frame()
+            || matches!(
+                machine.opcode(),
+                Some(OpCode::MovEbpToEspAndPopRbp | OpCode::Exit)
+            )
+        {
+            return ControlFlow::Continue(());
+        }
+        let Some(line) = Self::line(machine.codes, machine.rip) else {
+            return ControlFlow::Continue(());
+        };
+        if self.detached {
+            return ControlFlow::Continue(());
+        }
+        let flow = if self.should_stop(machine, line) {
+            self.resume = Resume::Continue;
+            let result = self
+                .show_location(machine, line)
+                .and_then(|()| self.prompt(machine, line));
+            // The debugger cannot go on without its input and output.
+            result.unwrap_or(ControlFlow::Break(()))
+        } else {
+            ControlFlow::Continue(())
+        };
+        self.last = Some((line, machine.rbp));
+        flow
+    }
+}
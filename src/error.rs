@@ -0,0 +1,317 @@
+use std::fmt;
+
+use crate::code_generator::Codes;
+use crate::lexer::Span;
+
+/// An error found while lexing, parsing or generating code.
+#[derive(Debug, Clone)]
+pub struct CompileError {
+    pub message: String,
+    /// Where the error was found, or `None` if it concerns the program as a whole.
+    pub span: Option<Span>,
+}
+
+impl CompileError {
+    pub fn new(message: impl Into<String>, span: Span) -> Self {
+        Self {
+            message: message.into(),
+            span: Some(span),
+        }
+    }
+
+    pub fn unlocated(message: impl Into<String>) -> Self {
+        Self {
+            message: message.into(),
+            span: None,
+        }
+    }
+
+    /// Renders the error with the offending source line and a caret underline, e.g.
+    ///
+    /// ```text
+    /// error: `;` is expected, but `}` is found
+    ///  --> main.abys:3:10
+    ///   |
+    /// 3 |   putc 1 }
+    ///   |          ^
+    /// ```
+    pub fn render(&self, source: &str, path: &str) -> String {
+        match self.span {
+            Some(span) => render_excerpt(&format!("error: {}", self.message), source, path, span),
+            None => format!("error: {}\n --> {}\n", self.message, path),
+        }
+    }
+}
+
+/// Renders `title` followed by the source line of `span` with a caret underline, or only its
+/// location if `span` is not in `source`, e.g. for codes loaded without their source.
+fn render_excerpt(title: &str, source: &str, path: &str, span: Span) -> String {
+    let in_source = span.start <= span.end
+        && span.end <= source.len()
+        && source.is_char_boundary(span.start)
+        && source.is_char_boundary(span.end);
+    if !in_source {
+        return format!(
+            "{title}\n --> {path}:{line}:{column}\n",
+            line = span.line,
+            column = span.column,
+        );
+    }
+    let line_start = source[..span.start].rfind('\n').map_or(0, |i| i + 1);
+    let line_end = source[span.start..]
+        .find('\n')
+        .map_or(source.len(), |i| span.start + i);
+    let line_text = source[line_start..line_end].trim_end_matches('\r');
+    let padding: String = source[line_start..span.start]
+        .chars()
+        .map(|c| if c == '\t' { '\t' } else { ' ' })
+        .collect();
+    let underline = "^".repeat(
+        source[span.start..span.end.clamp(span.start, line_end)]
+            .chars()
+            .count()
+            .max(1),
+    );
+    let gutter = " ".repeat(span.line.to_string().len());
+    format!(
+        "{title}\n{gutter}--> {path}:{line}:{column}\n{gutter} |\n{line} | {line_text}\n{gutter} | {padding}{underline}\n",
+        line = span.line,
+        column = span.column,
+    )
+}
+
+impl fmt::Display for CompileError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.span {
+            Some(span) => write!(f, "{}:{}: {}", span.line, span.column, self.message),
+            None => f.write_str(&self.message),
+        }
+    }
+}
+
+impl std::error::Error for CompileError {}
+
+/// A problem found while compiling that does not prevent running the program, such as code that
+/// is never executed.
+#[derive(Debug, Clone)]
+pub struct CompileWarning {
+    pub message: String,
+    pub span: Span,
+}
+
+impl CompileWarning {
+    pub fn new(message: impl Into<String>, span: Span) -> Self {
+        Self {
+            message: message.into(),
+            span,
+        }
+    }
+
+    /// Renders the warning like [`CompileError::render`].
+    pub fn render(&self, source: &str, path: &str) -> String {
+        render_excerpt(
+            &format!("warning: {}", self.message),
+            source,
+            path,
+            self.span,
+        )
+    }
+}
+
+impl fmt::Display for CompileWarning {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{}:{}: {}",
+            self.span.line, self.span.column, self.message
+        )
+    }
+}
+
+/// An error found while loading compiled codes, e.g. from a corrupted file.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LoadError {
+    pub message: String,
+}
+
+impl LoadError {
+    pub fn new(message: impl Into<String>) -> Self {
+        Self {
+            message: message.into(),
+        }
+    }
+
+    /// Renders the error like a [`CompileError`] that has no location in `path`.
+    pub fn render(&self, path: &str) -> String {
+        format!("error: {}\n --> {}\n", self.message, path)
+    }
+}
+
+impl fmt::Display for LoadError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.message)
+    }
+}
+
+impl std::error::Error for LoadError {}
+
+/// A fault found in codes by [`verify`](crate::verifier::verify).
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct VerifyError {
+    pub message: String,
+    /// The index of the faulty opcode.
+    pub opcode_index: usize,
+    /// The name of the function containing it.
+    pub function: String,
+    /// The source location of the faulty opcode, if the codes were generated from source.
+    pub span: Option<Span>,
+}
+
+impl VerifyError {
+    pub(crate) fn new(codes: &Codes, opcode_index: usize, message: impl Into<String>) -> Self {
+        Self {
+            message: message.into(),
+            opcode_index,
+            function: codes
+                .function_at(opcode_index)
+                .map_or_else(|| "?".to_owned(), |f| f.name.clone()),
+            span: codes.spans.get(opcode_index).copied(),
+        }
+    }
+
+    /// Renders the error with the source line of the faulty opcode, like
+    /// [`CompileError::render`].
+    pub fn render(&self, source: &str, path: &str) -> String {
+        let title = format!("error: {}", self);
+        match self.span {
+            Some(span) => render_excerpt(&title, source, path, span),
+            None => format!("{}\n --> {}\n", title, path),
+        }
+    }
+}
+
+impl fmt::Display for VerifyError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} in {}() at opcode {}",
+            self.message, self.function, self.opcode_index
+        )
+    }
+}
+
+impl std::error::Error for VerifyError {}
+
+/// What went wrong at run time.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum RuntimeErrorKind {
+    /// An integer division or remainder by zero.
+    DivisionByZero,
+    /// A local variable or parameter index outside the current frame. `alloc_size` is known for
+    /// the computed indices of `get[...]` and `set[...]`.
+    IndexOutOfRange {
+        index: i64,
+        alloc_size: Option<usize>,
+    },
+    /// A pop, free or return with nothing left on the stack.
+    StackUnderflow,
+    /// A negative byte count passed to `putbuf`.
+    NegativeLength { length: i64 },
+    /// A failure to read standard input or write standard output.
+    Io(Box<str>),
+    /// An opcode that must not remain after code generation, or a jump outside the program.
+    InvalidOpcode,
+}
+
+impl fmt::Display for RuntimeErrorKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            RuntimeErrorKind::DivisionByZero => f.write_str("division by zero"),
+            RuntimeErrorKind::IndexOutOfRange {
+                index,
+                alloc_size: Some(alloc_size),
+            } => write!(
+                f,
+                "the index {} is out of range for `alloc {}`",
+                index, alloc_size
+            ),
+            RuntimeErrorKind::IndexOutOfRange {
+                index,
+                alloc_size: None,
+            } => write!(f, "the index {} is out of range", index),
+            RuntimeErrorKind::StackUnderflow => f.write_str("stack underflow"),
+            RuntimeErrorKind::NegativeLength { length } => {
+                write!(f, "the length {} is negative", length)
+            }
+            RuntimeErrorKind::Io(message) => write!(f, "I/O error: {}", message),
+            RuntimeErrorKind::InvalidOpcode => f.write_str("invalid opcode"),
+        }
+    }
+}
+
+/// A function on the call stack when a [`RuntimeError`] was raised.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct StackFrame {
+    pub function: String,
+    /// The opcode being executed in this frame: the faulting opcode for the innermost frame, the
+    /// call for the others.
+    pub opcode_index: usize,
+    /// The source location of that opcode, if the codes were generated from source.
+    pub span: Option<Span>,
+}
+
+/// An error raised while executing a program.
+#[derive(Debug, Clone)]
+pub struct RuntimeError {
+    pub kind: RuntimeErrorKind,
+    /// The index of the faulting opcode.
+    pub opcode_index: usize,
+    /// The name of the function containing the faulting opcode.
+    pub function: String,
+    /// The source location of the faulting opcode, if the codes were generated from source.
+    pub span: Option<Span>,
+    /// The call stack, innermost frame first.
+    pub backtrace: Vec<StackFrame>,
+}
+
+impl RuntimeError {
+    /// Renders the error with the faulting source line, like [`CompileError::render`], followed
+    /// by the backtrace.
+    pub fn render(&self, source: &str, path: &str) -> String {
+        let title = format!("runtime error: {}", self.kind);
+        let mut rendered = match self.span {
+            Some(span) => render_excerpt(&title, source, path, span),
+            None => format!("{}\n", title),
+        };
+        rendered.push_str("backtrace:\n");
+        for (depth, frame) in self.backtrace.iter().enumerate() {
+            rendered.push_str(&format!("  {}: {}", depth, frame.describe()));
+            if let Some(span) = frame.span {
+                rendered.push_str(&format!(" ({}:{}:{})", path, span.line, span.column));
+            }
+            rendered.push('\n');
+        }
+        rendered
+    }
+}
+
+impl StackFrame {
+    fn describe(&self) -> String {
+        format!("{}() at opcode {}", self.function, self.opcode_index)
+    }
+}
+
+impl fmt::Display for RuntimeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} in {}", self.kind, self.backtrace[0].describe())?;
+        if let Some(span) = self.span {
+            write!(f, " ({}:{})", span.line, span.column)?;
+        }
+        for (depth, frame) in self.backtrace.iter().enumerate() {
+            write!(f, "\n  {}: {}", depth, frame.describe())?;
+        }
+        Ok(())
+    }
+}
+
+impl std::error::Error for RuntimeError {}
@@ -1,5 +1,8 @@
-use crate::code_generator::{Codes, OpCode};
+use crate::code_generator::{Codes, FunctionInfo, OpCode};
+use crate::error::{RuntimeError, RuntimeErrorKind, StackFrame};
+use crate::io::Io;
 use core::num::Wrapping;
+use core::ops::ControlFlow;
 
 #[derive(Clone, Copy)]
 pub union Value {
@@ -33,6 +36,7 @@ impl core::fmt::Debug for Value {
 }
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[allow(clippy::upper_case_acronyms)]
 pub enum Register {
     R1 = 0,
     R2 = 1,
@@ -53,14 +57,14 @@ impl Registers {
 impl core::ops::Index<Register> for Registers {
     type Output = Value;
     #[inline(always)]
-    fn index<'a>(&'a self, r: Register) -> &'a Value {
+    fn index(&self, r: Register) -> &Value {
         unsafe { &*self.0.as_ptr().add(r as usize) }
     }
 }
 
 impl core::ops::IndexMut<Register> for Registers {
     #[inline(always)]
-    fn index_mut<'a>(&'a mut self, r: Register) -> &'a mut Value {
+    fn index_mut(&mut self, r: Register) -> &mut Value {
         unsafe { &mut *self.0.as_mut_ptr().add(r as usize) }
     }
 }
@@ -71,36 +75,249 @@ macro_rules! bool_to_int {
     };
 }
 
-pub fn execute(codes: Codes) {
+/// The state of the VM shown to an [`Observer`].
+pub struct Machine<'a> {
+    pub codes: &'a Codes,
+    /// The index of the opcode about to be executed.
+    pub rip: usize,
+    pub rbp: usize,
+    pub stack: &'a [Value],
+    registers: &'a Registers,
+}
+
+impl Machine<'_> {
+    pub fn register(&self, r: Register) -> Value {
+        self.registers[r]
+    }
+
+    pub fn opcode(&self) -> Option<OpCode> {
+        self.codes.opcodes.get(self.rip).copied()
+    }
+
+    pub fn function(&self) -> Option<&FunctionInfo> {
+        self.codes.function_at(self.rip)
+    }
+
+    /// Whether `rbp` points at the frame of the current function: not during the prologue
+    /// (`PushRbpAndMovEspToEbp` and `Alloc`), nor at the `Ret` of the epilogue.
+    pub fn is_in_frame(&self) -> bool {
+        !matches!(
+            self.opcode(),
+            Some(OpCode::PushRbpAndMovEspToEbp | OpCode::Alloc(_) | OpCode::Ret)
+        )
+    }
+
+    /// Returns `get.index` of the current frame, if it is within `alloc`.
+    pub fn local(&self, index: usize) -> Option<Value> {
+        if index >= self.function()?.alloc_size {
+            return None;
+        }
+        self.stack.get(self.rbp + index).copied()
+    }
+
+    /// Returns `param.index` of the current frame, if it is within `params`.
+    pub fn param(&self, index: usize) -> Option<Value> {
+        let params_size = self.function()?.params_size;
+        if index >= params_size {
+            return None;
+        }
+        // The parameters are pushed in order below the return address and the saved `rbp`.
+        let offset = (self.rbp.checked_sub(2 + params_size))?;
+        self.stack.get(offset + index).copied()
+    }
+
+    /// Returns the call stack, innermost first.
+    pub fn backtrace(&self) -> Vec<StackFrame> {
+        backtrace(self.codes, self.rip, self.rbp, self.stack)
+    }
+}
+
+/// Watches the execution, e.g. to debug, trace or profile a program.
+pub trait Observer {
+    /// Called before the opcode at `machine.rip` is executed. Returning [`ControlFlow::Break`]
+    /// ends the execution without an error.
+    fn before(&mut self, machine: &Machine<'_>) -> ControlFlow<()>;
+}
+
+impl<T: Observer + ?Sized> Observer for &mut T {
+    fn before(&mut self, machine: &Machine<'_>) -> ControlFlow<()> {
+        (**self).before(machine)
+    }
+}
+
+impl<T: Observer> Observer for Option<T> {
+    fn before(&mut self, machine: &Machine<'_>) -> ControlFlow<()> {
+        match self {
+            Some(observer) => observer.before(machine),
+            None => ControlFlow::Continue(()),
+        }
+    }
+}
+
+/// Both observers see every opcode; either one may end the execution.
+impl<A: Observer, B: Observer> Observer for (A, B) {
+    fn before(&mut self, machine: &Machine<'_>) -> ControlFlow<()> {
+        let first = self.0.before(machine);
+        let second = self.1.before(machine);
+        first?;
+        second
+    }
+}
+
+/// The observer of an unobserved execution.
+pub struct NoObserver;
+
+impl Observer for NoObserver {
+    #[inline(always)]
+    fn before(&mut self, _: &Machine<'_>) -> ControlFlow<()> {
+        ControlFlow::Continue(())
+    }
+}
+
+/// Walks the saved `rbp` chain from the opcode at `rip`.
+fn backtrace(codes: &Codes, rip: usize, mut rbp: usize, stack: &[Value]) -> Vec<StackFrame> {
+    let function_name = |index: usize| {
+        codes
+            .function_at(index)
+            .map_or_else(|| "?".to_owned(), |f| f.name.clone())
+    };
+    let mut backtrace = vec![StackFrame {
+        function: function_name(rip),
+        opcode_index: rip,
+        span: codes.spans.get(rip).copied(),
+    }];
+    // Each frame below main's stores the return address at `rbp - 2` and the caller's `rbp` at
+    // `rbp - 1`.
+    while rbp >= 2 && rbp <= stack.len() {
+        let return_address = stack[rbp - 2].as_i64().0 as usize;
+        let Some(call_site) = return_address.checked_sub(1) else {
+            break;
+        };
+        backtrace.push(StackFrame {
+            function: function_name(call_site),
+            opcode_index: call_site,
+            span: codes.spans.get(call_site).copied(),
+        });
+        rbp = stack[rbp - 1].as_i64().0 as usize;
+    }
+    backtrace
+}
+
+/// Builds the error for a fault at `rip`.
+fn runtime_error(
+    kind: RuntimeErrorKind,
+    codes: &Codes,
+    rip: usize,
+    rbp: usize,
+    stack: &[Value],
+) -> RuntimeError {
+    let backtrace = backtrace(codes, rip, rbp, stack);
+    RuntimeError {
+        kind,
+        opcode_index: rip,
+        function: backtrace[0].function.clone(),
+        span: backtrace[0].span,
+        backtrace,
+    }
+}
+
+/// Executes the program from its entry point until `main` exits, reading and writing bytes
+/// through `io`.
+///
+/// Faults are reported as a [`RuntimeError`]. With the `unchecked` feature the checks are
+/// skipped, and a faulty program has undefined behavior.
+pub fn execute(codes: &Codes, io: &mut impl Io) -> Result<(), RuntimeError> {
+    execute_observed(codes, io, &mut [], &mut NoObserver)
+}
+
+/// Like [`execute`], but the first local variables of `main` start with the values of `locals`
+/// instead of zero, and are copied back to `locals` when `main` exits.
+pub fn execute_with_locals(
+    codes: &Codes,
+    io: &mut impl Io,
+    locals: &mut [Value],
+) -> Result<(), RuntimeError> {
+    execute_observed(codes, io, locals, &mut NoObserver)
+}
+
+/// Like [`execute_with_locals`], calling `observer` before each opcode.
+pub fn execute_observed(
+    codes: &Codes,
+    io: &mut impl Io,
+    locals: &mut [Value],
+    observer: &mut impl Observer,
+) -> Result<(), RuntimeError> {
     let mut r = Registers::new();
     let mut stack = Vec::with_capacity(4096);
 
-    #[cfg(debug_assertions)]
-    let mut stdout = std::io::stdout();
-    #[cfg(not(debug_assertions))]
-    let mut stdout = std::io::stdout().lock();
-
-    #[cfg(debug_assertions)]
-    let mut stdin = std::io::stdin();
-    #[cfg(not(debug_assertions))]
-    let mut stdin = std::io::stdin().lock();
+    // Reused by `PutBytes` to write all of its bytes at once.
+    let mut buffer = Vec::new();
 
     let mut rip = codes.entry_point;
     let mut rbp = 0;
 
+    macro_rules! fault {
+        ($kind:expr) => {
+            return Err(runtime_error($kind, codes, rip, rbp, &stack))
+        };
+    }
+
+    // I/O errors are reported even with the `unchecked` feature.
+    macro_rules! try_io {
+        ($result:expr) => {
+            match $result {
+                Ok(value) => value,
+                Err(e) => fault!(RuntimeErrorKind::Io(e.to_string().into())),
+            }
+        };
+    }
+
     loop {
-        match codes.opcodes[rip] {
-            OpCode::Exit => break,
+        #[cfg(not(feature = "unchecked"))]
+        let Some(&opcode) = codes.opcodes.get(rip) else {
+            fault!(RuntimeErrorKind::InvalidOpcode);
+        };
+        #[cfg(feature = "unchecked")]
+        let opcode = unsafe { *codes.opcodes.get_unchecked(rip) };
+
+        let machine = Machine {
+            codes,
+            rip,
+            rbp,
+            stack: &stack,
+            registers: &r,
+        };
+        if observer.before(&machine).is_break() {
+            try_io!(io.flush());
+            return Ok(());
+        }
+
+        match opcode {
+            OpCode::Exit => {
+                let frame = stack.get(rbp..).unwrap_or_default();
+                let len = frame.len().min(locals.len());
+                locals[..len].copy_from_slice(&frame[..len]);
+                try_io!(io.flush());
+                return Ok(());
+            }
             OpCode::Alloc(size) => {
+                // The frame of `main` starts just after the saved `rbp` at the bottom.
+                let is_main_frame = rbp == 1 && stack.len() == 1;
                 stack.resize(stack.len() + size, Value::from_i64(Wrapping(0)));
+                if is_main_frame {
+                    let len = size.min(locals.len());
+                    stack[1..1 + len].copy_from_slice(&locals[..len]);
+                }
             }
             OpCode::Free(size) => {
-                #[cfg(debug_assertions)]
+                #[cfg(not(feature = "unchecked"))]
                 {
-                    debug_assert!(stack.len() >= rbp);
+                    if stack.len() < rbp + size {
+                        fault!(RuntimeErrorKind::StackUnderflow);
+                    }
                     stack.truncate(stack.len() - size);
                 }
-                #[cfg(not(debug_assertions))]
+                #[cfg(feature = "unchecked")]
                 unsafe {
                     stack.set_len(stack.len() - size);
                 }
@@ -110,14 +327,39 @@ pub fn execute(codes: Codes) {
                 rip = faddr;
                 continue;
             }
+            OpCode::TailCall(params_size, faddr) => {
+                // The arguments pushed on the frame replace its parameters, and the frame is torn
+                // down down to the return address, which the callee returns to.
+                #[cfg(not(feature = "unchecked"))]
+                {
+                    if rbp < 2 + params_size || stack.len() < rbp + params_size {
+                        fault!(RuntimeErrorKind::StackUnderflow);
+                    }
+                }
+                let args = stack.len() - params_size;
+                stack.copy_within(args.., rbp - 2 - params_size);
+                stack.truncate(rbp);
+                #[cfg(not(feature = "unchecked"))]
+                {
+                    rbp = stack.pop().unwrap().as_i64().0 as usize;
+                }
+                #[cfg(feature = "unchecked")]
+                unsafe {
+                    rbp = stack.pop().unwrap_unchecked().as_i64().0 as usize;
+                }
+                rip = faddr;
+                continue;
+            }
             OpCode::MovEbpToEspAndPopRbp => {
-                #[cfg(debug_assertions)]
+                #[cfg(not(feature = "unchecked"))]
                 {
-                    debug_assert!(stack.len() >= rbp);
+                    if rbp == 0 || stack.len() < rbp {
+                        fault!(RuntimeErrorKind::StackUnderflow);
+                    }
                     stack.truncate(rbp);
                     rbp = stack.pop().unwrap().as_i64().0 as usize;
                 }
-                #[cfg(not(debug_assertions))]
+                #[cfg(feature = "unchecked")]
                 unsafe {
                     stack.set_len(rbp);
                     rbp = stack.pop().unwrap_unchecked().as_i64().0 as usize;
@@ -128,11 +370,14 @@ pub fn execute(codes: Codes) {
                 rbp = stack.len();
             }
             OpCode::Ret => {
-                #[cfg(debug_assertions)]
+                #[cfg(not(feature = "unchecked"))]
                 {
-                    rip = stack.pop().unwrap().as_i64().0 as usize;
+                    let Some(return_address) = stack.pop() else {
+                        fault!(RuntimeErrorKind::StackUnderflow);
+                    };
+                    rip = return_address.as_i64().0 as usize;
                 }
-                #[cfg(not(debug_assertions))]
+                #[cfg(feature = "unchecked")]
                 {
                     rip = unsafe { stack.pop().unwrap_unchecked() }.as_i64().0 as usize;
                 }
@@ -145,11 +390,14 @@ pub fn execute(codes: Codes) {
                 r[r1] = Value::from_f64(v);
             }
             OpCode::Pop(r1) => {
-                #[cfg(debug_assertions)]
+                #[cfg(not(feature = "unchecked"))]
                 {
-                    r[r1] = stack.pop().unwrap();
+                    let Some(value) = stack.pop() else {
+                        fault!(RuntimeErrorKind::StackUnderflow);
+                    };
+                    r[r1] = value;
                 }
-                #[cfg(not(debug_assertions))]
+                #[cfg(feature = "unchecked")]
                 {
                     r[r1] = unsafe { stack.pop().unwrap_unchecked() };
                 }
@@ -166,46 +414,58 @@ pub fn execute(codes: Codes) {
             //     write!(std::io::stdout(), "{}", r[r1].as_i64().0).unwrap();
             // }
             OpCode::PutByte(r1) => {
-                use std::io::Write as _;
-
-                #[cfg(debug_assertions)]
-                {
-                    stdout
-                        .write_all(&[r[r1].as_i64().0.rem_euclid(0xFF) as u8])
-                        .unwrap();
-                }
-                #[cfg(not(debug_assertions))]
-                {
-                    let _ = stdout.write_all(&[r[r1].as_i64().0.rem_euclid(0xFF) as u8]);
-                }
+                try_io!(io.write_bytes(&[r[r1].as_i64().0.rem_euclid(0x100) as u8]));
+            }
+            OpCode::PutBytes(start, alloc_size, len) => {
+                #[cfg(not(feature = "unchecked"))]
+                let bytes = {
+                    let start = r[start].as_i64().0;
+                    let len = r[len].as_i64().0;
+                    if len < 0 {
+                        fault!(RuntimeErrorKind::NegativeLength { length: len });
+                    }
+                    // Reports the first index of the range outside `alloc`.
+                    let first_out_of_range = if len == 0 {
+                        None
+                    } else if !(0..alloc_size as i64).contains(&start) {
+                        Some(start)
+                    } else if start.saturating_add(len) > alloc_size as i64 {
+                        Some(alloc_size as i64)
+                    } else {
+                        None
+                    };
+                    if let Some(index) = first_out_of_range {
+                        fault!(RuntimeErrorKind::IndexOutOfRange {
+                            index,
+                            alloc_size: Some(alloc_size)
+                        });
+                    }
+                    let begin = rbp + start.max(0) as usize;
+                    let Some(locals) = stack.get(begin..begin + len as usize) else {
+                        fault!(RuntimeErrorKind::IndexOutOfRange {
+                            index: start,
+                            alloc_size: Some(alloc_size)
+                        });
+                    };
+                    locals
+                };
+                #[cfg(feature = "unchecked")]
+                let bytes = unsafe {
+                    let _ = alloc_size;
+                    core::slice::from_raw_parts(
+                        stack.as_ptr().add(rbp + r[start].as_i64().0 as usize),
+                        r[len].as_i64().0 as usize,
+                    )
+                };
+                buffer.clear();
+                buffer.extend(bytes.iter().map(|v| v.as_i64().0.rem_euclid(0x100) as u8));
+                try_io!(io.write_bytes(&buffer));
             }
             OpCode::ReadByteFromStdin(r1) => {
-                use std::io::{Read as _, Write as _};
-
-                #[cfg(debug_assertions)]
-                {
-                    stdout
-                        .write_all(&[r[r1].as_i64().0.rem_euclid(0xFF) as u8])
-                        .unwrap();
-                    let mut buf: [u8; 1] = [0; 1];
-                    stdout.flush().unwrap();
-                    let read_len = stdin
-                        .read(&mut buf)
-                        .unwrap();
-                    debug_assert!(read_len == 1, "failed to read from standard input");
-                    r[r1] = Value::from_i64(Wrapping(buf[0] as i64));
-                }
-                #[cfg(not(debug_assertions))]
-                {
-                    use core::mem::MaybeUninit;
-
-                    let _ = stdout.write_all(&[r[r1].as_i64().0.rem_euclid(0xFF) as u8]);
-                    let mut buf: MaybeUninit<u8> = MaybeUninit::uninit();
-                    let _ = stdout.flush();
-                    let _ = stdin
-                        .read(unsafe { core::slice::from_raw_parts_mut(buf.as_mut_ptr(), 1) });
-                    r[r1] = Value::from_i64(Wrapping(unsafe { buf.assume_init() } as i64));
-                }
+                // Shows a pending prompt before blocking on the input.
+                try_io!(io.flush());
+                let byte = try_io!(io.read_byte()).map_or(-1, i64::from);
+                r[r1] = Value::from_i64(Wrapping(byte));
             }
             OpCode::Mov(r1, r2) => {
                 r[r2] = r[r1];
@@ -289,12 +549,20 @@ pub fn execute(codes: Codes) {
                 r[r3] = Value::from_f64(r[r1].as_f64() / r[r2].as_f64());
             }
             OpCode::DivInt(r1, r2, r3) => {
+                #[cfg(not(feature = "unchecked"))]
+                if r[r2].as_i64().0 == 0 {
+                    fault!(RuntimeErrorKind::DivisionByZero);
+                }
                 r[r3] = Value::from_i64(r[r1].as_i64() / r[r2].as_i64());
             }
             OpCode::RemFloat(r1, r2, r3) => {
                 r[r3] = Value::from_f64(r[r1].as_f64() % r[r2].as_f64());
             }
             OpCode::RemInt(r1, r2, r3) => {
+                #[cfg(not(feature = "unchecked"))]
+                if r[r2].as_i64().0 == 0 {
+                    fault!(RuntimeErrorKind::DivisionByZero);
+                }
                 r[r3] = Value::from_i64(r[r1].as_i64() % r[r2].as_i64());
             }
             OpCode::And(r1, r2, r3) => {
@@ -306,58 +574,134 @@ pub fn execute(codes: Codes) {
             OpCode::Xor(r1, r2, r3) => {
                 r[r3] = Value::from_i64(r[r1].as_i64() ^ r[r2].as_i64());
             }
+            OpCode::AddIntImm(r1, v, r2) => {
+                r[r2] = Value::from_i64(r[r1].as_i64() + Wrapping(v));
+            }
+            OpCode::MulIntImm(r1, v, r2) => {
+                r[r2] = Value::from_i64(r[r1].as_i64() * Wrapping(v));
+            }
+            OpCode::DivIntImm(r1, v, r2) => {
+                #[cfg(not(feature = "unchecked"))]
+                if v == 0 {
+                    fault!(RuntimeErrorKind::DivisionByZero);
+                }
+                r[r2] = Value::from_i64(r[r1].as_i64() / Wrapping(v));
+            }
+            OpCode::RemIntImm(r1, v, r2) => {
+                #[cfg(not(feature = "unchecked"))]
+                if v == 0 {
+                    fault!(RuntimeErrorKind::DivisionByZero);
+                }
+                r[r2] = Value::from_i64(r[r1].as_i64() % Wrapping(v));
+            }
 
             OpCode::GetParam(index, r1) => {
-                #[cfg(debug_assertions)]
+                #[cfg(not(feature = "unchecked"))]
                 {
-                    r[r1] = stack[rbp - 2 - index];
+                    let Some(&value) = rbp.checked_sub(2 + index).and_then(|slot| stack.get(slot))
+                    else {
+                        fault!(RuntimeErrorKind::IndexOutOfRange {
+                            index: index as i64,
+                            alloc_size: None
+                        });
+                    };
+                    r[r1] = value;
                 }
-                #[cfg(not(debug_assertions))]
+                #[cfg(feature = "unchecked")]
                 unsafe {
                     r[r1] = stack.as_ptr().add(rbp - 2 - index).read();
                 }
             }
             OpCode::GetVar(index, r1) => {
-                #[cfg(debug_assertions)]
+                #[cfg(not(feature = "unchecked"))]
                 {
-                    r[r1] = stack[rbp + index];
+                    let Some(&value) = stack.get(rbp + index) else {
+                        fault!(RuntimeErrorKind::IndexOutOfRange {
+                            index: index as i64,
+                            alloc_size: None
+                        });
+                    };
+                    r[r1] = value;
                 }
-                #[cfg(not(debug_assertions))]
+                #[cfg(feature = "unchecked")]
                 unsafe {
                     r[r1] = stack.as_ptr().add(rbp + index).read();
                 }
             }
-            OpCode::GetVarComputed(index, r1) => {
-                #[cfg(debug_assertions)]
+            OpCode::GetVarComputed(index, alloc_size, r1) => {
+                #[cfg(not(feature = "unchecked"))]
                 {
-                    debug_assert!(r[index].as_i64().0 >= 0);
-                    let variables = &mut stack[rbp..];
-                    r[r1] = variables[r[index].as_i64().0 as usize];
+                    let index = r[index].as_i64().0;
+                    let Some(&value) = usize::try_from(index)
+                        .ok()
+                        .filter(|&index| index < alloc_size)
+                        .and_then(|index| stack.get(rbp + index))
+                    else {
+                        fault!(RuntimeErrorKind::IndexOutOfRange {
+                            index,
+                            alloc_size: Some(alloc_size)
+                        });
+                    };
+                    r[r1] = value;
                 }
-                #[cfg(not(debug_assertions))]
+                #[cfg(feature = "unchecked")]
                 unsafe {
+                    let _ = alloc_size;
                     r[r1] = stack.as_ptr().add(rbp + (r[index].as_i64().0 as usize)).read();
                 }
             }
             OpCode::SetVar(index, r1) => {
-                #[cfg(debug_assertions)]
+                #[cfg(not(feature = "unchecked"))]
                 {
-                    stack[rbp + index] = r[r1];
+                    let Some(slot) = stack.get_mut(rbp + index) else {
+                        fault!(RuntimeErrorKind::IndexOutOfRange {
+                            index: index as i64,
+                            alloc_size: None
+                        });
+                    };
+                    *slot = r[r1];
                 }
-                #[cfg(not(debug_assertions))]
+                #[cfg(feature = "unchecked")]
                 unsafe {
                     stack.as_mut_ptr().add(rbp + index).write(r[r1]);
                 }
             }
-            OpCode::SetVarComputed(index, r1) => {
-                #[cfg(debug_assertions)]
+            OpCode::AddVarInt(index, v) => {
+                #[cfg(not(feature = "unchecked"))]
                 {
-                    debug_assert!(r[index].as_i64().0 >= 0);
-                    let variables = &mut stack[rbp..];
-                    variables[r[index].as_i64().0 as usize] = r[r1];
+                    let Some(slot) = stack.get_mut(rbp + index) else {
+                        fault!(RuntimeErrorKind::IndexOutOfRange {
+                            index: index as i64,
+                            alloc_size: None
+                        });
+                    };
+                    *slot = Value::from_i64(slot.as_i64() + Wrapping(v));
                 }
-                #[cfg(not(debug_assertions))]
+                #[cfg(feature = "unchecked")]
                 unsafe {
+                    let slot = stack.as_mut_ptr().add(rbp + index);
+                    slot.write(Value::from_i64(slot.read().as_i64() + Wrapping(v)));
+                }
+            }
+            OpCode::SetVarComputed(index, alloc_size, r1) => {
+                #[cfg(not(feature = "unchecked"))]
+                {
+                    let index = r[index].as_i64().0;
+                    let Some(slot) = usize::try_from(index)
+                        .ok()
+                        .filter(|&index| index < alloc_size)
+                        .and_then(|index| stack.get_mut(rbp + index))
+                    else {
+                        fault!(RuntimeErrorKind::IndexOutOfRange {
+                            index,
+                            alloc_size: Some(alloc_size)
+                        });
+                    };
+                    *slot = r[r1];
+                }
+                #[cfg(feature = "unchecked")]
+                unsafe {
+                    let _ = alloc_size;
                     stack.as_mut_ptr().add(rbp + (r[index].as_i64().0 as usize)).write(r[r1]);
                 }
             }
@@ -371,15 +715,53 @@ pub fn execute(codes: Codes) {
                     continue;
                 }
             }
+            OpCode::JmpAddrIfNotEqInt(c, v, addr) => {
+                if r[c].as_i64().0 != v {
+                    rip = addr;
+                    continue;
+                }
+            }
+            OpCode::JmpAddrIfNotNeInt(c, v, addr) => {
+                if r[c].as_i64().0 == v {
+                    rip = addr;
+                    continue;
+                }
+            }
+            OpCode::JmpAddrIfNotLtInt(c, v, addr) => {
+                if r[c].as_i64().0 >= v {
+                    rip = addr;
+                    continue;
+                }
+            }
+            OpCode::JmpAddrIfNotLeInt(c, v, addr) => {
+                if r[c].as_i64().0 > v {
+                    rip = addr;
+                    continue;
+                }
+            }
+            OpCode::JmpAddrIfNotGtInt(c, v, addr) => {
+                if r[c].as_i64().0 <= v {
+                    rip = addr;
+                    continue;
+                }
+            }
+            OpCode::JmpAddrIfNotGeInt(c, v, addr) => {
+                if r[c].as_i64().0 < v {
+                    rip = addr;
+                    continue;
+                }
+            }
 
             // Expected behavior: removed in code_generator::generate()
             OpCode::Nop
             | OpCode::__FNCALL_2255__(_)
             | OpCode::__JL_0515__(_)
-            | OpCode::__JLIZ_2505__(_, _) => {
-                #[cfg(debug_assertions)]
-                unreachable!();
-                #[cfg(not(debug_assertions))]
+            | OpCode::__JLIZ_2505__(_, _)
+            | OpCode::__JLNC_3170__(..)
+            | OpCode::__TLCALL_4096__(..) => {
+                #[cfg(not(feature = "unchecked"))]
+                fault!(RuntimeErrorKind::InvalidOpcode);
+                #[cfg(feature = "unchecked")]
                 unsafe {
                     core::hint::unreachable_unchecked()
                 };
@@ -0,0 +1,395 @@
+//! Inlining of small functions into their callers, done by the optimizer before folding.
+//!
+//! The statements of the callee are put before the statement containing the call, and the call
+//! is replaced by the value the callee returns. The parameters and variables of the callee become
+//! variables of the caller, after its own ones. A call is only inlined when everything evaluated
+//! before it in its statement is pure, so that the effects happen in the same order.
+
+use std::collections::HashMap;
+
+use crate::lexer::Span;
+use crate::optimizer::{OptLevel, is_pure};
+use crate::parser::{
+    Expression, ExpressionKind, FunctionData, Inlining, Program, Statement, StatementKind,
+};
+
+/// The largest number of statements and expressions of a function inlined without `inline`.
+pub const THRESHOLD: usize = 16;
+
+/// A function whose calls can be inlined: it calls no function, so it is not recursive, it only
+/// returns at its end, and it does not compute the indices of its variables, which are checked
+/// against its own `alloc`.
+struct Callee {
+    params_size: usize,
+    alloc_size: usize,
+    /// The statements before the final `return`.
+    stmts: Vec<Statement>,
+    /// The value returned, `None` if the function ends without `return`.
+    result: Option<Expression>,
+    /// The number of reads of each parameter, indexed like [`ExpressionKind::GetParam`].
+    param_reads: Vec<usize>,
+    /// The variables that may be read before they are set, which are `0` when the function starts.
+    zeroed: Vec<usize>,
+}
+
+fn count_param_reads(expr: &Expression, reads: &mut [usize]) {
+    if let ExpressionKind::GetParam(index) = expr.kind {
+        reads[index] += 1;
+    }
+    for operand in expr.kind.operands() {
+        count_param_reads(operand, reads);
+    }
+}
+
+fn mark_variable_reads(expr: &Expression, read: &mut [bool]) {
+    if let ExpressionKind::GetWithLiteralIndex(index) = expr.kind {
+        read[index] = true;
+    }
+    for operand in expr.kind.operands() {
+        mark_variable_reads(operand, read);
+    }
+}
+
+/// Calls `f` on each expression of `stmt` and of the statements nested in it.
+fn for_each_expression(stmt: &Statement, f: &mut impl FnMut(&Expression)) {
+    for expr in stmt.kind.expressions() {
+        f(expr);
+    }
+    for stmt in stmt.kind.statements() {
+        for_each_expression(stmt, f);
+    }
+}
+
+/// Returns the number of statements and expressions in `stmt`.
+fn size(stmt: &Statement) -> usize {
+    fn expression_size(expr: &Expression) -> usize {
+        1 + (expr.kind.operands().into_iter())
+            .map(expression_size)
+            .sum::<usize>()
+    }
+    let mut count = 1 + stmt.kind.statements().into_iter().map(size).sum::<usize>();
+    for expr in stmt.kind.expressions() {
+        count += expression_size(expr);
+    }
+    count
+}
+
+/// Returns whether `stmt` has a statement or an expression that prevents inlining, `return`
+/// aside.
+fn prevents_inlining(stmt: &Statement) -> bool {
+    fn in_expression(expr: &Expression) -> bool {
+        matches!(
+            expr.kind,
+            ExpressionKind::Call(..) | ExpressionKind::GetWithComputedIndex(_)
+        ) || expr.kind.operands().into_iter().any(in_expression)
+    }
+    matches!(
+        stmt.kind,
+        StatementKind::SetWithComputedIndex { .. } | StatementKind::PutBytes { .. }
+    ) || stmt.kind.expressions().into_iter().any(in_expression)
+        || stmt.kind.statements().into_iter().any(prevents_inlining)
+}
+
+fn contains_return(stmt: &Statement) -> bool {
+    matches!(stmt.kind, StatementKind::Return { .. })
+        || stmt.kind.statements().into_iter().any(contains_return)
+}
+
+impl Callee {
+    fn new(f: &FunctionData, level: OptLevel) -> Option<Self> {
+        let selected = match f.inlining {
+            Inlining::Always => level >= OptLevel::O1,
+            Inlining::Auto => level >= OptLevel::O2 && size(&f.body) <= THRESHOLD,
+            Inlining::Never => false,
+        };
+        // A `return` in `main` exits the program.
+        if !selected || f.name == "main" || prevents_inlining(&f.body) {
+            return None;
+        }
+        let StatementKind::Block { stmts } = &f.body.kind else {
+            return None;
+        };
+        let mut stmts = stmts.clone();
+        let result = match stmts.pop() {
+            Some(Statement {
+                kind: StatementKind::Return { val },
+                ..
+            }) => Some(*val),
+            Some(stmt) => {
+                stmts.push(stmt);
+                None
+            }
+            None => None,
+        };
+        if stmts.iter().any(contains_return) {
+            return None;
+        }
+
+        let mut param_reads = vec![0; f.params_size + 1];
+        let mut read = vec![false; f.alloc_size];
+        let mut set = vec![false; f.alloc_size];
+        let mut zeroed = Vec::new();
+        // A variable is set before it is read if a statement of the body, not one nested in a
+        // `while` or an `if`, sets it before any read.
+        fn record_reads(read: &mut [bool], set: &[bool], zeroed: &mut Vec<usize>) {
+            for (index, read) in read.iter_mut().enumerate() {
+                if std::mem::take(read) && !set[index] && !zeroed.contains(&index) {
+                    zeroed.push(index);
+                }
+            }
+        }
+        for stmt in &stmts {
+            for_each_expression(stmt, &mut |expr| {
+                count_param_reads(expr, &mut param_reads);
+                mark_variable_reads(expr, &mut read);
+            });
+            record_reads(&mut read, &set, &mut zeroed);
+            if let StatementKind::SetWithLiteralIndex { index, .. } = stmt.kind {
+                set[index] = true;
+            }
+        }
+        if let Some(result) = &result {
+            count_param_reads(result, &mut param_reads);
+            mark_variable_reads(result, &mut read);
+            record_reads(&mut read, &set, &mut zeroed);
+        }
+        zeroed.sort_unstable();
+
+        Some(Callee {
+            params_size: f.params_size,
+            alloc_size: f.alloc_size,
+            stmts,
+            result,
+            param_reads,
+            zeroed,
+        })
+    }
+}
+
+/// Moves the parameters and variables of an inlined body into the frame of the caller.
+struct Remap<'a> {
+    /// The caller variable of the callee variable 0.
+    base: usize,
+    /// The value of each parameter, indexed like [`ExpressionKind::GetParam`].
+    params: &'a [Expression],
+}
+
+impl Remap<'_> {
+    fn expression(&self, expr: &mut Expression) {
+        match &mut expr.kind {
+            ExpressionKind::GetParam(index) => *expr = self.params[*index].clone(),
+            ExpressionKind::GetWithLiteralIndex(index) => *index += self.base,
+            kind => {
+                for operand in kind.operands_mut() {
+                    self.expression(operand);
+                }
+            }
+        }
+    }
+
+    fn statement(&self, stmt: &mut Statement) {
+        if let StatementKind::SetWithLiteralIndex { index, .. } = &mut stmt.kind {
+            *index += self.base;
+        }
+        for expr in stmt.kind.expressions_mut() {
+            self.expression(expr);
+        }
+        match &mut stmt.kind {
+            StatementKind::While { body, .. } => self.statement(body),
+            StatementKind::If {
+                then_branch,
+                unless_branch,
+                ..
+            } => {
+                self.statement(then_branch);
+                if let Some(branch) = unless_branch {
+                    self.statement(branch);
+                }
+            }
+            StatementKind::Block { stmts } => {
+                for stmt in stmts {
+                    self.statement(stmt);
+                }
+            }
+            _ => {}
+        }
+    }
+}
+
+struct Inliner<'a> {
+    callees: &'a HashMap<String, Callee>,
+    /// The next free variable of the caller.
+    next_slot: usize,
+}
+
+impl Inliner<'_> {
+    fn new_slot(&mut self, count: usize) -> usize {
+        let slot = self.next_slot;
+        self.next_slot += count;
+        slot
+    }
+
+    /// Returns the statements replacing `stmt`: the inlined bodies, then `stmt` itself.
+    fn statement(&mut self, mut stmt: Statement, out: &mut Vec<Statement>) {
+        match &mut stmt.kind {
+            // The condition is evaluated again at each iteration, so nothing can be put before it.
+            StatementKind::While { body, .. } => self.nested(body),
+            StatementKind::If {
+                cond,
+                then_branch,
+                unless_branch,
+            } => {
+                self.expression(cond, &mut true, out);
+                self.nested(then_branch);
+                if let Some(branch) = unless_branch {
+                    self.nested(branch);
+                }
+            }
+            StatementKind::Block { stmts } => {
+                let mut inlined = Vec::with_capacity(stmts.len());
+                for stmt in std::mem::take(stmts) {
+                    self.statement(stmt, &mut inlined);
+                }
+                *stmts = inlined;
+            }
+            kind => {
+                let mut pure_so_far = true;
+                for expr in kind.expressions_mut() {
+                    self.expression(expr, &mut pure_so_far, out);
+                }
+            }
+        }
+        out.push(stmt);
+    }
+
+    /// Inlines the calls in a statement that is the body of a `while` or a branch of an `if`.
+    fn nested(&mut self, stmt: &mut Box<Statement>) {
+        let span = stmt.span;
+        let placeholder = Statement {
+            kind: StatementKind::Block { stmts: Vec::new() },
+            span,
+        };
+        let mut stmts = Vec::new();
+        self.statement(std::mem::replace(&mut **stmt, placeholder), &mut stmts);
+        **stmt = if stmts.len() == 1 {
+            stmts.pop().unwrap()
+        } else {
+            Statement {
+                kind: StatementKind::Block { stmts },
+                span,
+            }
+        };
+    }
+
+    /// Inlines the calls in `expr` evaluated while `pure_so_far`, i.e. with nothing but pure
+    /// expressions evaluated before them in the statement.
+    fn expression(
+        &mut self,
+        expr: &mut Expression,
+        pure_so_far: &mut bool,
+        out: &mut Vec<Statement>,
+    ) {
+        if *pure_so_far
+            && let ExpressionKind::Call(name, args) = &mut expr.kind
+            && let Some(callee) = self.callees.get(name)
+        {
+            let args = std::mem::take(args);
+            *expr = self.inline(callee, args, expr.span, out);
+            *pure_so_far &= is_pure(expr);
+            return;
+        }
+        for operand in expr.kind.operands_mut() {
+            self.expression(operand, pure_so_far, out);
+        }
+        *pure_so_far &= is_pure(expr);
+    }
+
+    /// Puts the body of `callee` called with `args` in `out`, and returns the value it returns.
+    fn inline(
+        &mut self,
+        callee: &Callee,
+        args: Vec<Expression>,
+        span: Span,
+        out: &mut Vec<Statement>,
+    ) -> Expression {
+        let base = self.new_slot(callee.alloc_size);
+        let mut params =
+            vec![Expression::new(ExpressionKind::Int(0), span); callee.params_size + 1];
+        for (i, arg) in args.into_iter().enumerate() {
+            let index = callee.params_size - i;
+            let arg_span = arg.span;
+            let stmt = match arg.kind {
+                // Reading a literal, a variable or a parameter of the caller again is as cheap as
+                // reading a copy, and the inlined body cannot change them.
+                ExpressionKind::Int(_)
+                | ExpressionKind::Float(_)
+                | ExpressionKind::GetWithLiteralIndex(_)
+                | ExpressionKind::GetParam(_) => {
+                    params[index] = arg;
+                    continue;
+                }
+                _ if callee.param_reads[index] == 0 => StatementKind::Expr {
+                    expr: Box::new(arg),
+                },
+                _ => {
+                    let slot = self.new_slot(1);
+                    params[index] =
+                        Expression::new(ExpressionKind::GetWithLiteralIndex(slot), arg_span);
+                    StatementKind::SetWithLiteralIndex {
+                        index: slot,
+                        val: Box::new(arg),
+                    }
+                }
+            };
+            // The arguments may call functions to inline too.
+            self.statement(Statement::new(stmt, arg_span), out);
+        }
+
+        let remap = Remap {
+            base,
+            params: &params,
+        };
+        for &index in &callee.zeroed {
+            out.push(Statement::new(
+                StatementKind::SetWithLiteralIndex {
+                    index: base + index,
+                    val: Box::new(Expression::new(ExpressionKind::Int(0), span)),
+                },
+                span,
+            ));
+        }
+        for stmt in &callee.stmts {
+            let mut stmt = stmt.clone();
+            remap.statement(&mut stmt);
+            out.push(stmt);
+        }
+        let mut result = (callee.result.clone())
+            .unwrap_or_else(|| Expression::new(ExpressionKind::Int(0), span));
+        remap.expression(&mut result);
+        result
+    }
+}
+
+/// Inlines the calls to the functions selected at `level` into every function of the program.
+pub fn inline(prog: &mut Program, level: OptLevel) {
+    let callees: HashMap<String, Callee> = (prog.funcs.iter())
+        .filter_map(|f| Some((f.name.clone(), Callee::new(f, level)?)))
+        .collect();
+    if callees.is_empty() {
+        return;
+    }
+    for f in &mut prog.funcs {
+        let mut inliner = Inliner {
+            callees: &callees,
+            next_slot: f.alloc_size + f.inline_alloc_size,
+        };
+        let mut stmts = Vec::new();
+        let body = std::mem::replace(
+            &mut f.body,
+            Statement::new(StatementKind::Block { stmts: Vec::new() }, f.span),
+        );
+        inliner.statement(body, &mut stmts);
+        f.body = stmts.pop().unwrap();
+        f.inline_alloc_size = inliner.next_slot - f.alloc_size;
+    }
+}
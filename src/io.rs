@@ -0,0 +1,135 @@
+//! The input and output of a running program.
+//!
+//! The executor reads `getc` bytes from and writes `putc`/`putbuf` bytes to an [`Io`]. [`StdIo`]
+//! is the standard input/output, and [`Streams`] adapts any [`Read`]/[`Write`] pair, such as an
+//! in-memory [`MemoryIo`] or a [`FileIo`].
+
+use std::fs::File;
+use std::io::{self, BufReader, BufWriter, ErrorKind, Read, Write};
+use std::path::Path;
+
+/// A byte source and sink for `getc`, `putc` and `putbuf`.
+pub trait Io {
+    /// Reads one byte, or returns `None` at the end of the input.
+    fn read_byte(&mut self) -> io::Result<Option<u8>>;
+
+    fn write_bytes(&mut self, bytes: &[u8]) -> io::Result<()>;
+
+    /// Flushes the buffered output. Called before each read and when the program exits.
+    fn flush(&mut self) -> io::Result<()>;
+}
+
+impl<T: Io + ?Sized> Io for &mut T {
+    fn read_byte(&mut self) -> io::Result<Option<u8>> {
+        (**self).read_byte()
+    }
+
+    fn write_bytes(&mut self, bytes: &[u8]) -> io::Result<()> {
+        (**self).write_bytes(bytes)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        (**self).flush()
+    }
+}
+
+fn read_byte_from(reader: &mut impl Read) -> io::Result<Option<u8>> {
+    let mut buf = [0; 1];
+    loop {
+        match reader.read(&mut buf) {
+            Ok(0) => return Ok(None),
+            Ok(_) => return Ok(Some(buf[0])),
+            Err(e) if e.kind() == ErrorKind::Interrupted => continue,
+            Err(e) => return Err(e),
+        }
+    }
+}
+
+/// The standard input and output of the process.
+///
+/// The handles are locked for each operation only, so the host may still use them between
+/// opcodes.
+#[derive(Debug, Default)]
+pub struct StdIo {
+    _private: (),
+}
+
+impl StdIo {
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+impl Io for StdIo {
+    fn read_byte(&mut self) -> io::Result<Option<u8>> {
+        read_byte_from(&mut io::stdin().lock())
+    }
+
+    fn write_bytes(&mut self, bytes: &[u8]) -> io::Result<()> {
+        io::stdout().lock().write_all(bytes)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        io::stdout().lock().flush()
+    }
+}
+
+/// An input reader and an output writer.
+#[derive(Debug, Default)]
+pub struct Streams<R, W> {
+    pub input: R,
+    pub output: W,
+}
+
+impl<R: Read, W: Write> Streams<R, W> {
+    pub fn new(input: R, output: W) -> Self {
+        Self { input, output }
+    }
+
+    pub fn into_inner(self) -> (R, W) {
+        (self.input, self.output)
+    }
+}
+
+impl<R: Read, W: Write> Io for Streams<R, W> {
+    fn read_byte(&mut self) -> io::Result<Option<u8>> {
+        read_byte_from(&mut self.input)
+    }
+
+    fn write_bytes(&mut self, bytes: &[u8]) -> io::Result<()> {
+        self.output.write_all(bytes)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.output.flush()
+    }
+}
+
+/// Reads a byte slice and collects the output in memory.
+///
+/// ```
+/// let codes = abyssix::compile("func main { params 0; alloc 0; putc getc; putc 10; }").unwrap();
+/// let mut vm = abyssix::Vm::with_io(codes, abyssix::io::MemoryIo::memory(b"A"));
+/// vm.run().unwrap();
+/// assert_eq!(vm.io().output, b"A\n");
+/// ```
+pub type MemoryIo<'a> = Streams<&'a [u8], Vec<u8>>;
+
+impl<'a> MemoryIo<'a> {
+    pub fn memory(input: &'a [u8]) -> Self {
+        Self::new(input, Vec::new())
+    }
+}
+
+/// Reads from and writes to files, buffered.
+pub type FileIo = Streams<BufReader<File>, BufWriter<File>>;
+
+impl FileIo {
+    /// Opens `input` for reading and creates or truncates `output` for writing.
+    pub fn open(input: impl AsRef<Path>, output: impl AsRef<Path>) -> io::Result<Self> {
+        Ok(Self::new(
+            BufReader::new(File::open(input)?),
+            BufWriter::new(File::create(output)?),
+        ))
+    }
+}
@@ -1,5 +1,44 @@
+use std::fmt;
+
+use crate::error::CompileError;
+
+/// A location in the source: a byte range plus the 1-based line and column where it starts.
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub struct Span {
+    pub start: usize,
+    pub end: usize,
+    pub line: usize,
+    pub column: usize,
+}
+
+impl Span {
+    /// Returns a span covering both `self` and `other`, which must come later in the source.
+    pub fn to(self, other: Span) -> Span {
+        Span {
+            end: other.end,
+            ..self
+        }
+    }
+}
+
+impl fmt::Debug for Span {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{}..{}@{}:{}",
+            self.start, self.end, self.line, self.column
+        )
+    }
+}
+
 #[derive(Debug)]
-pub enum Token {
+pub struct Token {
+    pub kind: TokenKind,
+    pub span: Span,
+}
+
+#[derive(Debug)]
+pub enum TokenKind {
     Plus,
     Minus,
     Asterisk,
@@ -42,13 +81,82 @@ pub enum Token {
     AllocKeyword,
     ElseKeyword,
     PutcKeyword,
+    PutbufKeyword,
     GetcKeyword,
     ParamsKeyword,
     FuncKeyword,
+    InlineKeyword,
+    NoinlineKeyword,
     ReturnKeyword,
+    BreakKeyword,
+    ContinueKeyword,
     Int(i64),
     Float(f64),
     Ident(String),
+    Eof,
+}
+
+impl fmt::Display for TokenKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let text = match self {
+            TokenKind::Plus => "+",
+            TokenKind::Minus => "-",
+            TokenKind::Asterisk => "*",
+            TokenKind::Slash => "/",
+            TokenKind::Percent => "%",
+            TokenKind::DoubleEq => "==",
+            TokenKind::Equal => "=",
+            TokenKind::LessThan => "<",
+            TokenKind::DoubleLt => "<<",
+            TokenKind::LtEq => "<=",
+            TokenKind::GreaterThan => ">",
+            TokenKind::DoubleGt => ">>",
+            TokenKind::TripleGt => ">>>",
+            TokenKind::GtEq => ">=",
+            TokenKind::Ampersand => "&",
+            TokenKind::Pipe => "|",
+            TokenKind::Circumflex => "^",
+            TokenKind::ExclEq => "!=",
+            TokenKind::Excl => "!",
+            TokenKind::Tilde => "~",
+            TokenKind::Semicolon => ";",
+            TokenKind::Dot => ".",
+            TokenKind::Comma => ",",
+            TokenKind::Colon => ":",
+            TokenKind::OpeningBracket => "[",
+            TokenKind::ClosingBracket => "]",
+            TokenKind::OpeningBrace => "{",
+            TokenKind::ClosingBrace => "}",
+            TokenKind::OpeningParens => "(",
+            TokenKind::ClosingParens => ")",
+            TokenKind::FloatKeyword => "float",
+            TokenKind::IntKeyword => "int",
+            TokenKind::IfKeyword => "if",
+            TokenKind::GetKeyword => "get",
+            TokenKind::SetKeyword => "set",
+            TokenKind::ParamKeyword => "param",
+            TokenKind::WhileKeyword => "while",
+            TokenKind::FtoiKeyword => "f_to_i",
+            TokenKind::ItofKeyword => "i_to_f",
+            TokenKind::AllocKeyword => "alloc",
+            TokenKind::ElseKeyword => "else",
+            TokenKind::PutcKeyword => "putc",
+            TokenKind::PutbufKeyword => "putbuf",
+            TokenKind::GetcKeyword => "getc",
+            TokenKind::ParamsKeyword => "params",
+            TokenKind::FuncKeyword => "func",
+            TokenKind::InlineKeyword => "inline",
+            TokenKind::NoinlineKeyword => "noinline",
+            TokenKind::ReturnKeyword => "return",
+            TokenKind::BreakKeyword => "break",
+            TokenKind::ContinueKeyword => "continue",
+            TokenKind::Int(val) => return write!(f, "integer `{}`", val),
+            TokenKind::Float(val) => return write!(f, "decimal `{:?}`", val),
+            TokenKind::Ident(name) => return write!(f, "identifier `{}`", name),
+            TokenKind::Eof => return f.write_str("the end of the input"),
+        };
+        write!(f, "`{}`", text)
+    }
 }
 
 macro_rules! next_if_matches {
@@ -61,134 +169,171 @@ macro_rules! next_if_matches {
     };
 }
 
-pub fn parse(s: &str) -> Vec<Token> {
+/// Splits the source into tokens. The returned tokens always end with [`TokenKind::Eof`].
+pub fn parse(s: &str) -> Result<Vec<Token>, Vec<CompileError>> {
     let mut iter = s.as_bytes().iter();
     let mut tokens = Vec::new();
+    let mut errors = Vec::new();
+    let mut line = 1;
+    let mut line_start = 0;
 
     loop {
+        let begin = s.len() - iter.as_slice().len();
+        let span_to = move |end: usize| Span {
+            start: begin,
+            end,
+            line,
+            column: s[line_start..begin].chars().count() + 1,
+        };
         let Some(next_char) = iter.next().copied() else {
-            return tokens;
+            tokens.push(Token {
+                kind: TokenKind::Eof,
+                span: span_to(begin),
+            });
+            return if errors.is_empty() {
+                Ok(tokens)
+            } else {
+                Err(errors)
+            };
         };
-        match next_char {
-            b'+' => tokens.push(Token::Plus),
-            b'-' => tokens.push(Token::Minus),
-            b'*' => tokens.push(Token::Asterisk),
+        let kind = match next_char {
+            b'+' => TokenKind::Plus,
+            b'-' => TokenKind::Minus,
+            b'*' => TokenKind::Asterisk,
             b'/' => {
                 if next_if_matches!(iter, [b'/']).is_some() {
                     while iter.as_slice().first().is_some_and(|c| *c != b'\n') {
                         iter.next();
                     }
+                    continue;
                 } else {
-                    tokens.push(Token::Slash)
+                    TokenKind::Slash
                 }
             }
-            b'%' => tokens.push(Token::Percent),
-            b'&' => tokens.push(Token::Ampersand),
-            b'|' => tokens.push(Token::Pipe),
-            b'^' => tokens.push(Token::Circumflex),
-            b'~' => tokens.push(Token::Tilde),
-            b';' => tokens.push(Token::Semicolon),
-            b':' => tokens.push(Token::Colon),
-            b',' => tokens.push(Token::Comma),
-            b'.' => tokens.push(Token::Dot),
-            b'[' => tokens.push(Token::OpeningBracket),
-            b']' => tokens.push(Token::ClosingBracket),
-            b'(' => tokens.push(Token::OpeningParens),
-            b')' => tokens.push(Token::ClosingParens),
-            b'{' => tokens.push(Token::OpeningBrace),
-            b'}' => tokens.push(Token::ClosingBrace),
-            b'=' => tokens.push(if next_if_matches!(iter, [b'=']).is_some() {
-                Token::DoubleEq
-            } else {
-                Token::Equal
-            }),
-            b'!' => tokens.push(if next_if_matches!(iter, [b'=']).is_some() {
-                Token::ExclEq
-            } else {
-                Token::Excl
-            }),
-            b'>' => tokens.push(if next_if_matches!(iter, [b'=']).is_some() {
-                Token::GtEq
-            } else if next_if_matches!(iter, [b'>']).is_some() {
-                if next_if_matches!(iter, [b'>']).is_some() {
-                    Token::TripleGt
+            b'%' => TokenKind::Percent,
+            b'&' => TokenKind::Ampersand,
+            b'|' => TokenKind::Pipe,
+            b'^' => TokenKind::Circumflex,
+            b'~' => TokenKind::Tilde,
+            b';' => TokenKind::Semicolon,
+            b':' => TokenKind::Colon,
+            b',' => TokenKind::Comma,
+            b'.' => TokenKind::Dot,
+            b'[' => TokenKind::OpeningBracket,
+            b']' => TokenKind::ClosingBracket,
+            b'(' => TokenKind::OpeningParens,
+            b')' => TokenKind::ClosingParens,
+            b'{' => TokenKind::OpeningBrace,
+            b'}' => TokenKind::ClosingBrace,
+            b'=' => {
+                if next_if_matches!(iter, [b'=']).is_some() {
+                    TokenKind::DoubleEq
                 } else {
-                    Token::DoubleGt
+                    TokenKind::Equal
                 }
-            } else {
-                Token::GreaterThan
-            }),
-            b'<' => tokens.push(if next_if_matches!(iter, [b'=']).is_some() {
-                Token::LtEq
-            } else if next_if_matches!(iter, [b'<']).is_some() {
-                Token::DoubleLt
-            } else {
-                Token::LessThan
-            }),
+            }
+            b'!' => {
+                if next_if_matches!(iter, [b'=']).is_some() {
+                    TokenKind::ExclEq
+                } else {
+                    TokenKind::Excl
+                }
+            }
+            b'>' => {
+                if next_if_matches!(iter, [b'=']).is_some() {
+                    TokenKind::GtEq
+                } else if next_if_matches!(iter, [b'>']).is_some() {
+                    if next_if_matches!(iter, [b'>']).is_some() {
+                        TokenKind::TripleGt
+                    } else {
+                        TokenKind::DoubleGt
+                    }
+                } else {
+                    TokenKind::GreaterThan
+                }
+            }
+            b'<' => {
+                if next_if_matches!(iter, [b'=']).is_some() {
+                    TokenKind::LtEq
+                } else if next_if_matches!(iter, [b'<']).is_some() {
+                    TokenKind::DoubleLt
+                } else {
+                    TokenKind::LessThan
+                }
+            }
             b'A'..=b'Z' | b'_' | b'a'..=b'z' => {
-                let begin_cursor = iter.as_slice().as_ptr() as usize - 1;
                 while next_if_matches!(iter, [b'0'..=b'9' | b'A'..=b'Z' | b'_' | b'a'..=b'z'])
                     .is_some()
                 { /* empty */ }
-                let end_cursor = iter.as_slice().as_ptr() as usize;
-                let ident = unsafe {
-                    core::str::from_utf8_unchecked(core::slice::from_raw_parts(
-                        begin_cursor as *const u8,
-                        end_cursor - begin_cursor,
-                    ))
-                };
-                tokens.push(match ident.as_bytes() {
-                    b"while" => Token::WhileKeyword,
-                    b"if" => Token::IfKeyword,
-                    b"else" => Token::ElseKeyword,
-                    b"set" => Token::SetKeyword,
-                    b"get" => Token::GetKeyword,
-                    b"int" => Token::IntKeyword,
-                    b"float" => Token::FloatKeyword,
-                    b"i_to_f" => Token::ItofKeyword,
-                    b"f_to_i" => Token::FtoiKeyword,
-                    b"alloc" => Token::AllocKeyword,
-                    b"getc" => Token::GetcKeyword,
-                    // b"printfloat" => Token::PrintFloatKeyword,
-                    // b"printint" => Token::PrintIntKeyword,
-                    b"putc" => Token::PutcKeyword,
-                    b"func" => Token::FuncKeyword,
-                    b"params" => Token::ParamsKeyword,
-                    b"param" => Token::ParamKeyword,
-                    b"return" => Token::ReturnKeyword,
-                    _ => Token::Ident(ident.to_owned()),
-                })
+                let ident = &s[begin..s.len() - iter.as_slice().len()];
+                match ident.as_bytes() {
+                    b"while" => TokenKind::WhileKeyword,
+                    b"if" => TokenKind::IfKeyword,
+                    b"else" => TokenKind::ElseKeyword,
+                    b"set" => TokenKind::SetKeyword,
+                    b"get" => TokenKind::GetKeyword,
+                    b"int" => TokenKind::IntKeyword,
+                    b"float" => TokenKind::FloatKeyword,
+                    b"i_to_f" => TokenKind::ItofKeyword,
+                    b"f_to_i" => TokenKind::FtoiKeyword,
+                    b"alloc" => TokenKind::AllocKeyword,
+                    b"getc" => TokenKind::GetcKeyword,
+                    // b"printfloat" => TokenKind::PrintFloatKeyword,
+                    // b"printint" => TokenKind::PrintIntKeyword,
+                    b"putc" => TokenKind::PutcKeyword,
+                    b"putbuf" => TokenKind::PutbufKeyword,
+                    b"func" => TokenKind::FuncKeyword,
+                    b"inline" => TokenKind::InlineKeyword,
+                    b"noinline" => TokenKind::NoinlineKeyword,
+                    b"params" => TokenKind::ParamsKeyword,
+                    b"param" => TokenKind::ParamKeyword,
+                    b"return" => TokenKind::ReturnKeyword,
+                    b"break" => TokenKind::BreakKeyword,
+                    b"continue" => TokenKind::ContinueKeyword,
+                    _ => TokenKind::Ident(ident.to_owned()),
+                }
             }
             b'0'..=b'9' => {
-                let begin_cursor = iter.as_slice().as_ptr() as usize - 1;
                 while next_if_matches!(iter, [b'0'..=b'9']).is_some() { /* empty */ }
                 if next_if_matches!(iter, [b'.', b'0'..=b'9']).is_some() {
                     iter.next();
                     while next_if_matches!(iter, [b'0'..=b'9']).is_some() { /* empty */ }
-                    let end_cursor = iter.as_slice().as_ptr() as usize;
-                    let num_content = unsafe {
-                        core::str::from_utf8_unchecked(core::slice::from_raw_parts(
-                            begin_cursor as *const u8,
-                            end_cursor - begin_cursor,
-                        ))
-                    };
-                    tokens.push(Token::Float(num_content.parse().unwrap()))
+                    let end = s.len() - iter.as_slice().len();
+                    TokenKind::Float(s[begin..end].parse().unwrap())
                 } else {
-                    let end_cursor = iter.as_slice().as_ptr() as usize;
-                    let num_content = unsafe {
-                        core::str::from_utf8_unchecked(core::slice::from_raw_parts(
-                            begin_cursor as *const u8,
-                            end_cursor - begin_cursor,
-                        ))
+                    let end = s.len() - iter.as_slice().len();
+                    let Ok(val) = s[begin..end].parse() else {
+                        errors.push(CompileError::new(
+                            format!("the integer literal {} is too large", &s[begin..end]),
+                            span_to(end),
+                        ));
+                        continue;
                     };
-                    tokens.push(Token::Int(num_content.parse().unwrap()))
+                    TokenKind::Int(val)
                 }
             }
-            b'\n' | b'\t' | b'\r' | b' ' => {}
-            _ => panic!(
-                "Unexpected char {:?}",
-                char::from_u32(next_char.into()).unwrap()
-            ),
-        }
+            b'\n' => {
+                line += 1;
+                line_start = begin + 1;
+                continue;
+            }
+            b'\t' | b'\r' | b' ' => continue,
+            _ => {
+                let unexpected = s[begin..].chars().next().unwrap();
+                errors.push(CompileError::new(
+                    format!("unexpected char {:?}", unexpected),
+                    span_to(begin + unexpected.len_utf8()),
+                ));
+                // Skip the rest of a multi-byte character.
+                for _ in 1..unexpected.len_utf8() {
+                    iter.next();
+                }
+                continue;
+            }
+        };
+        tokens.push(Token {
+            kind,
+            span: span_to(s.len() - iter.as_slice().len()),
+        });
     }
 }
@@ -0,0 +1,109 @@
+//! abyssix, a procedural structured programming language.
+//!
+//! The pipeline is `lexer -> parser -> code_generator -> executor`; [`compile`] runs the first
+//! three stages and [`Vm`] runs the last one.
+//!
+//! ```no_run
+//! let codes = abyssix::compile("func main { params 0; alloc 0; putc 72; putc 10; }")
+//!     .expect("the program has no compile errors");
+//! abyssix::Vm::new(codes).run().unwrap();
+//! ```
+
+pub mod assembly;
+pub mod bytecode;
+pub mod code_generator;
+pub mod debugger;
+pub mod error;
+pub mod executor;
+pub mod inliner;
+pub mod io;
+pub mod lexer;
+pub mod optimizer;
+pub mod parser;
+pub mod profile;
+pub mod repl;
+pub mod trace;
+pub mod verifier;
+
+pub use code_generator::Codes;
+pub use error::{
+    CompileError, CompileWarning, LoadError, RuntimeError, RuntimeErrorKind, VerifyError,
+};
+
+/// Splits the source into tokens.
+pub fn tokenize(source: &str) -> Result<Vec<lexer::Token>, Vec<CompileError>> {
+    lexer::parse(source)
+}
+
+/// Tokenizes and parses the source into a syntax tree.
+pub fn parse(source: &str) -> Result<parser::Program, Vec<CompileError>> {
+    parser::parse_program(tokenize(source)?)
+}
+
+/// Compiles the source into opcodes ready to be run by a [`Vm`].
+///
+/// All the errors found are returned; use [`CompileError::render`] to show each of them with the
+/// offending source line.
+pub fn compile(source: &str) -> Result<Codes, Vec<CompileError>> {
+    compile_with(source, optimizer::OptLevel::default())
+}
+
+/// Like [`compile`], optimizing the program at `level`.
+pub fn compile_with(source: &str, level: optimizer::OptLevel) -> Result<Codes, Vec<CompileError>> {
+    code_generator::generate_with(parse(source)?, level)
+}
+
+/// A virtual machine that executes compiled [`Codes`], doing the program's input and output
+/// through an [`Io`](io::Io).
+pub struct Vm<I = io::StdIo> {
+    codes: Codes,
+    io: I,
+}
+
+impl Vm {
+    /// Creates a VM using the standard input and output.
+    pub fn new(codes: Codes) -> Self {
+        Self::with_io(codes, io::StdIo::new())
+    }
+}
+
+impl<I: io::Io> Vm<I> {
+    pub fn with_io(codes: Codes, io: I) -> Self {
+        Self { codes, io }
+    }
+
+    pub fn codes(&self) -> &Codes {
+        &self.codes
+    }
+
+    pub fn io(&self) -> &I {
+        &self.io
+    }
+
+    pub fn io_mut(&mut self) -> &mut I {
+        &mut self.io
+    }
+
+    pub fn into_io(self) -> I {
+        self.io
+    }
+
+    /// Executes the program from its entry point until `main` exits.
+    pub fn run(&mut self) -> Result<(), RuntimeError> {
+        executor::execute(&self.codes, &mut self.io)
+    }
+
+    /// Like [`Vm::run`], but the local variables of `main` start with `locals`, which receives
+    /// their values when `main` exits.
+    pub fn run_with_locals(&mut self, locals: &mut [executor::Value]) -> Result<(), RuntimeError> {
+        executor::execute_with_locals(&self.codes, &mut self.io, locals)
+    }
+
+    /// Like [`Vm::run`], calling `observer` before each opcode.
+    pub fn run_observed(
+        &mut self,
+        observer: &mut impl executor::Observer,
+    ) -> Result<(), RuntimeError> {
+        executor::execute_observed(&self.codes, &mut self.io, &mut [], observer)
+    }
+}
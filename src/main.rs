@@ -1,11 +1,487 @@
-mod code_generator;
-mod executor;
-mod lexer;
-mod parser;
-
-fn main() {
-    let tokens = lexer::parse(&std::fs::read_to_string("main.abys").unwrap());
-    let ast = parser::parse_program(tokens);
-    let codes = code_generator::generate(ast);
-    executor::execute(codes);
+use std::io::{BufRead as _, IsTerminal as _, Read as _, Write};
+use std::ops::Range;
+use std::process::ExitCode;
+
+use abyssix::Vm;
+use abyssix::debugger::{Debugger, StdinCommands};
+use abyssix::io::{Io, StdIo};
+use abyssix::optimizer::OptLevel;
+use abyssix::profile::Profiler;
+use abyssix::repl::Session;
+use abyssix::trace::Tracer;
+
+const USAGE: &str = "\
+Usage: abyssix <command> <file>
+       abyssix run [options] <file>
+       abyssix compile [-O0|-O1|-O2] [--no-debug-lines] <file> [-o <output>]
+       abyssix repl
+
+Commands:
+  run <file>            compile and execute the program
+  check <file>          lex, parse, generate and verify code without executing
+  dump-tokens <file>    print the tokens produced by the lexer
+  dump-ast <file>       print the syntax tree produced by the parser
+  dump-bytecode <file>  print the generated opcodes
+  disassemble <file>    print the opcodes as assembly, which `.abysm` files contain
+  debug <file>          run the program under the debugger, reading commands from stdin
+  compile <file>        write the opcodes to a `.abyc` file, <file> with that extension by
+                        default, to run it without compiling it again
+  repl                  read and run functions and statements interactively
+
+Options of the commands compiling a <file>:
+  -O0                          generate the code of every statement as written, the default of
+                               `debug`
+  -O1                          fold constant expressions and conditions, remove the code never
+                               executed, use superinstructions and tail calls, and inline the
+                               `inline` functions, the default otherwise
+  -O2                          also inline the small functions not declared `noinline`
+
+Options of `run`:
+  --trace[=FILE]               log each executed opcode to FILE or standard error
+  --trace-function=NAME        only log the opcodes of the function NAME
+  --trace-opcodes=START..END   only log the opcodes with an index in START..END
+  --profile                    print the opcode counts and times per function and line
+  --profile-folded=FILE        also write the call stacks for flamegraph tools to FILE
+
+Use `-` as <file> to read the source from standard input, except for `debug`.
+A <file> ending with `.abysm` is assembled instead of compiled, and one ending with `.abyc` is
+loaded as compiled opcodes.";
+
+// Exit codes follow sysexits(3).
+const EXIT_USAGE: u8 = 64;
+const EXIT_COMPILE: u8 = 65;
+const EXIT_RUNTIME: u8 = 70;
+const EXIT_IO: u8 = 74;
+
+enum Command {
+    Run,
+    Check,
+    DumpTokens,
+    DumpAst,
+    DumpBytecode,
+    Disassemble,
+    Debug,
+    Compile { output: String, debug_lines: bool },
+}
+
+enum Failure {
+    Usage(String),
+    Io(String),
+    Compile(String),
+    Runtime(String),
+}
+
+impl Failure {
+    fn exit_code(&self) -> u8 {
+        match self {
+            Failure::Usage(_) => EXIT_USAGE,
+            Failure::Io(_) => EXIT_IO,
+            Failure::Compile(_) => EXIT_COMPILE,
+            Failure::Runtime(_) => EXIT_RUNTIME,
+        }
+    }
+}
+
+/// Options of the commands taking a <file>, all but the optimization level being for `run`.
+#[derive(Default)]
+struct Options {
+    /// The optimization level, `None` for the default of the command.
+    level: Option<OptLevel>,
+    /// `Some(None)` to trace to standard error, `Some(Some(path))` to trace to a file.
+    trace: Option<Option<String>>,
+    trace_function: Option<String>,
+    trace_opcodes: Option<Range<usize>>,
+    profile: bool,
+    profile_folded: Option<String>,
+}
+
+fn parse_run_options(options: &[String]) -> Result<Options, Failure> {
+    let usage = |message: String| Failure::Usage(format!("{}\n\n{}", message, USAGE));
+    let mut run_options = Options::default();
+    for option in options {
+        match option.split_once('=') {
+            None if option == "--trace" => run_options.trace = Some(None),
+            Some(("--trace", path)) => run_options.trace = Some(Some(path.to_owned())),
+            Some(("--trace-function", name)) => run_options.trace_function = Some(name.to_owned()),
+            Some(("--trace-opcodes", range)) => {
+                let range = range
+                    .split_once("..")
+                    .and_then(|(start, end)| Some(start.parse().ok()?..end.parse().ok()?))
+                    .ok_or_else(|| usage(format!("invalid opcode range `{}`", range)))?;
+                run_options.trace_opcodes = Some(range);
+            }
+            None if option == "--profile" => run_options.profile = true,
+            Some(("--profile-folded", path)) => {
+                run_options.profile = true;
+                run_options.profile_folded = Some(path.to_owned());
+            }
+            _ => return Err(usage(format!("unknown option `{}`", option))),
+        }
+    }
+    if run_options.trace.is_none()
+        && (run_options.trace_function.is_some() || run_options.trace_opcodes.is_some())
+    {
+        return Err(usage(
+            "`--trace-function` and `--trace-opcodes` require `--trace`".to_owned(),
+        ));
+    }
+    Ok(run_options)
+}
+
+/// Parses `[-O0|-O1|-O2] [--no-debug-lines] <file> [-o <output>]`, the options being in any order.
+fn parse_compile_args(args: &[String]) -> Result<(Command, Options, &str), Failure> {
+    let usage = |message: &str| Failure::Usage(format!("{}\n\n{}", message, USAGE));
+    let mut options = Options::default();
+    let mut path = None;
+    let mut output = None;
+    let mut debug_lines = true;
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "-o" => {
+                let Some(arg) = args.next() else {
+                    return Err(usage("`-o` requires an <output>"));
+                };
+                output = Some(arg.clone());
+            }
+            "--no-debug-lines" => debug_lines = false,
+            flag if OptLevel::from_flag(flag).is_some() => {
+                options.level = OptLevel::from_flag(flag)
+            }
+            option if option.starts_with("--") => {
+                return Err(usage(&format!("unknown option `{}`", option)));
+            }
+            _ if path.is_some() => return Err(usage("`compile` takes a single <file>")),
+            _ => path = Some(arg.as_str()),
+        }
+    }
+    let Some(path) = path else {
+        return Err(Failure::Usage(USAGE.to_owned()));
+    };
+    let output = match output {
+        Some(output) => output,
+        None if path == "-" => return Err(usage("`-o` is required to compile standard input")),
+        None => std::path::Path::new(path)
+            .with_extension("abyc")
+            .to_string_lossy()
+            .into_owned(),
+    };
+    let command = Command::Compile {
+        output,
+        debug_lines,
+    };
+    Ok((command, options, path))
+}
+
+fn parse_args(args: &[String]) -> Result<(Command, Options, &str), Failure> {
+    if let [command, args @ ..] = args
+        && command == "compile"
+    {
+        return parse_compile_args(args);
+    }
+    let [command, options @ .., path] = args else {
+        return Err(Failure::Usage(USAGE.to_owned()));
+    };
+    let mut level = None;
+    let options: Vec<String> = (options.iter())
+        .filter(|option| match OptLevel::from_flag(option) {
+            Some(flag_level) => {
+                level = Some(flag_level);
+                false
+            }
+            None => true,
+        })
+        .cloned()
+        .collect();
+    let mut options = if command == "run" {
+        parse_run_options(&options)?
+    } else if options.is_empty() {
+        Options::default()
+    } else {
+        return Err(Failure::Usage(USAGE.to_owned()));
+    };
+    options.level = level;
+    if command == "repl" {
+        return Err(Failure::Usage(format!(
+            "`repl` takes no <file>\n\n{}",
+            USAGE
+        )));
+    }
+    if command == "debug" && path == "-" {
+        return Err(Failure::Usage(format!(
+            "`debug` reads its commands from standard input, so <file> cannot be `-`\n\n{}",
+            USAGE
+        )));
+    }
+    let command = match command.as_str() {
+        "run" => Command::Run,
+        "check" => Command::Check,
+        "dump-tokens" => Command::DumpTokens,
+        "dump-ast" => Command::DumpAst,
+        "dump-bytecode" => Command::DumpBytecode,
+        "disassemble" => Command::Disassemble,
+        "debug" => Command::Debug,
+        _ => {
+            return Err(Failure::Usage(format!(
+                "unknown command `{}`\n\n{}",
+                command, USAGE
+            )));
+        }
+    };
+    Ok((command, options, path))
+}
+
+fn read_input(path: &str) -> Result<Vec<u8>, Failure> {
+    if path == "-" {
+        let mut input = Vec::new();
+        std::io::stdin()
+            .read_to_end(&mut input)
+            .map_err(|e| Failure::Io(format!("failed to read standard input: {}", e)))?;
+        Ok(input)
+    } else {
+        std::fs::read(path).map_err(|e| Failure::Io(format!("failed to read {}: {}", path, e)))
+    }
+}
+
+fn run(command: Command, options: Options, path: &str, input: &[u8]) -> Result<(), Failure> {
+    let path = if path == "-" { "<stdin>" } else { path };
+    // Compiled opcodes come without their source.
+    let bytecode = path.ends_with(".abyc");
+    let source = if bytecode {
+        ""
+    } else {
+        std::str::from_utf8(input)
+            .map_err(|e| Failure::Io(format!("failed to read {}: {}", path, e)))?
+    };
+    let compile_failure = |errors: Vec<abyssix::CompileError>| {
+        let rendered: Vec<String> = errors.iter().map(|e| e.render(source, path)).collect();
+        Failure::Compile(rendered.join("\n"))
+    };
+    // The debugger steps through the statements as written.
+    let level = options.level.unwrap_or(match command {
+        Command::Debug => OptLevel::O0,
+        _ => OptLevel::default(),
+    });
+    let compile = || {
+        if bytecode {
+            return abyssix::bytecode::decode(input).map_err(|e| Failure::Compile(e.render(path)));
+        }
+        if path.ends_with(".abysm") {
+            // Hand-written opcodes are verified like decoded ones before anything uses them.
+            let codes = abyssix::assembly::assemble(source).map_err(compile_failure)?;
+            abyssix::verifier::verify(&codes)
+                .map_err(|e| Failure::Compile(e.render(source, path)))?;
+            return Ok(codes);
+        }
+        let prog = abyssix::parse(source).map_err(compile_failure)?;
+        for warning in abyssix::optimizer::warnings(&prog) {
+            eprint!("{}", warning.render(source, path));
+        }
+        abyssix::code_generator::generate_with(prog, level).map_err(compile_failure)
+    };
+
+    match command {
+        Command::DumpTokens | Command::DumpAst if bytecode => {
+            return Err(Failure::Usage(format!(
+                "`{}` has no source to dump\n\n{}",
+                path, USAGE
+            )));
+        }
+        Command::DumpTokens => {
+            for token in abyssix::tokenize(source).map_err(compile_failure)? {
+                println!("{:?}", token);
+            }
+        }
+        Command::DumpAst => {
+            println!("{:#?}", abyssix::parse(source).map_err(compile_failure)?);
+        }
+        Command::DumpBytecode => {
+            let codes = compile()?;
+            println!("entry point: {}", codes.entry_point);
+            for (index, opcode) in codes.opcodes.iter().enumerate() {
+                println!("{:>6}  {:?}", index, opcode);
+            }
+        }
+        Command::Disassemble => {
+            print!("{}", abyssix::assembly::disassemble(&compile()?));
+        }
+        Command::Check => {
+            let codes = compile()?;
+            abyssix::verifier::verify(&codes)
+                .map_err(|e| Failure::Compile(e.render(source, path)))?;
+        }
+        Command::Compile {
+            output,
+            debug_lines,
+        } => {
+            let bytes = abyssix::bytecode::encode(&compile()?, debug_lines);
+            std::fs::write(&output, bytes)
+                .map_err(|e| Failure::Io(format!("failed to write {}: {}", output, e)))?;
+        }
+        Command::Debug => {
+            let codes = compile()?;
+            let mut debugger = Debugger::new(source, path, StdinCommands::new(), std::io::stdout());
+            Vm::new(codes)
+                .run_observed(&mut debugger)
+                .map_err(|e| Failure::Runtime(e.render(source, path)))?;
+        }
+        Command::Run => {
+            let codes = compile()?;
+            execute(Vm::new(codes), options, source, path)?;
+        }
+    }
+    Ok(())
+}
+
+/// Standard input/output remembering whether the program left the cursor at the start of a
+/// line, so that a value printed by the REPL gets a line of its own.
+struct ReplIo {
+    stdio: StdIo,
+    at_line_start: bool,
+}
+
+impl Io for ReplIo {
+    fn read_byte(&mut self) -> std::io::Result<Option<u8>> {
+        self.stdio.read_byte()
+    }
+
+    fn write_bytes(&mut self, bytes: &[u8]) -> std::io::Result<()> {
+        if let Some(&last) = bytes.last() {
+            self.at_line_start = last == b'\n';
+        }
+        self.stdio.write_bytes(bytes)
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        self.stdio.flush()
+    }
+}
+
+fn repl() -> Result<(), Failure> {
+    const PATH: &str = "<repl>";
+    let stdin = std::io::stdin();
+    let interactive = stdin.is_terminal();
+    let io_failure = |e: std::io::Error| Failure::Io(format!("failed to run the REPL: {}", e));
+
+    let mut session = Session::new();
+    let mut io = ReplIo {
+        stdio: StdIo::new(),
+        at_line_start: true,
+    };
+    let mut input = String::new();
+    loop {
+        if interactive {
+            print!("{}", if input.is_empty() { "> " } else { "... " });
+            std::io::stdout().flush().map_err(io_failure)?;
+        }
+        let mut line = String::new();
+        let at_eof = stdin.lock().read_line(&mut line).map_err(io_failure)? == 0;
+        input.push_str(&line);
+        if input.trim().is_empty() {
+            input.clear();
+        } else if at_eof || !Session::is_incomplete(&input) {
+            let result = session.eval(&input, &mut io);
+            if !matches!(result, Ok(None)) && !io.at_line_start {
+                println!();
+                io.at_line_start = true;
+            }
+            match result {
+                Ok(Some(value)) => println!("{}", value),
+                Ok(None) => {}
+                Err(e) => eprint!("{}", e.render(session.source(), PATH)),
+            }
+            input.clear();
+        }
+        if at_eof {
+            if interactive {
+                println!();
+            }
+            return Ok(());
+        }
+    }
+}
+
+/// Runs the program, tracing and profiling it as requested.
+fn execute(mut vm: Vm, options: Options, source: &str, path: &str) -> Result<(), Failure> {
+    let runtime_failure = |e: abyssix::RuntimeError| Failure::Runtime(e.render(source, path));
+    if options.trace.is_none() && !options.profile {
+        return vm.run().map_err(runtime_failure);
+    }
+
+    let tracer = match &options.trace {
+        Some(trace_path) => {
+            let out: Box<dyn Write> = match trace_path {
+                Some(trace_path) => {
+                    Box::new(std::fs::File::create(trace_path).map_err(|e| {
+                        Failure::Io(format!("failed to create {}: {}", trace_path, e))
+                    })?)
+                }
+                None => Box::new(std::io::stderr()),
+            };
+            let mut tracer = Tracer::new(std::io::BufWriter::new(out));
+            if let Some(name) = options.trace_function {
+                tracer = tracer.function(name);
+            }
+            if let Some(range) = options.trace_opcodes {
+                tracer = tracer.opcodes(range);
+            }
+            Some(tracer)
+        }
+        None => None,
+    };
+    let profiler = options.profile.then(Profiler::new);
+
+    let mut observer = (tracer, profiler);
+    let result = vm.run_observed(&mut observer);
+    let (tracer, profiler) = observer;
+
+    if let Some(tracer) = tracer {
+        tracer.finish().map_err(|e| {
+            let trace_path = options.trace.flatten();
+            let trace_path = trace_path.as_deref().unwrap_or("standard error");
+            Failure::Io(format!(
+                "failed to write the trace to {}: {}",
+                trace_path, e
+            ))
+        })?;
+    }
+    if let Some(profiler) = profiler {
+        let profile = profiler.finish();
+        let mut table = Vec::new();
+        profile
+            .write_table(&mut table, source, path)
+            .and_then(|()| std::io::stderr().write_all(&table))
+            .map_err(|e| Failure::Io(format!("failed to write the profile: {}", e)))?;
+        if let Some(folded_path) = &options.profile_folded {
+            let mut folded = Vec::new();
+            profile
+                .write_folded(&mut folded)
+                .and_then(|()| std::fs::write(folded_path, folded))
+                .map_err(|e| Failure::Io(format!("failed to write {}: {}", folded_path, e)))?;
+        }
+    }
+    result.map_err(runtime_failure)
+}
+
+fn main() -> ExitCode {
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    let result = if matches!(&args[..], [command] if command == "repl") {
+        repl()
+    } else {
+        parse_args(&args)
+            .and_then(|(command, options, path)| run(command, options, path, &read_input(path)?))
+    };
+
+    match result {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(failure) => {
+            match &failure {
+                Failure::Usage(message) => eprintln!("{}", message),
+                Failure::Io(message) => eprintln!("I/O error: {}", message),
+                Failure::Compile(message) => eprint!("{}", message),
+                Failure::Runtime(message) => eprint!("{}", message),
+            }
+            ExitCode::from(failure.exit_code())
+        }
+    }
 }
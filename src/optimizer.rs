@@ -0,0 +1,422 @@
+//! Optimizations of the syntax tree, done between parsing and code generation.
+//!
+//! Constant operations are computed as the executor would compute them: integers wrap around and
+//! floats are IEEE 754 doubles, so an optimized program prints exactly what the unoptimized one
+//! prints. Operations that fault, such as an integer division by zero, are left for the executor
+//! to report.
+
+use core::num::Wrapping;
+use std::collections::HashSet;
+
+use crate::error::CompileWarning;
+use crate::executor::Value;
+use crate::inliner;
+use crate::lexer::Span;
+use crate::parser::{Expression, ExpressionKind, OperandType, Program, Statement, StatementKind};
+
+/// How much the program is optimized before its code is generated.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Default)]
+pub enum OptLevel {
+    /// Generates the code of every statement as written, which keeps each opcode tied to the
+    /// source it came from.
+    O0,
+    /// Folds constant expressions and conditions, removes identities such as `x int.+ 0`,
+    /// statements without effects or never executed and the functions `main` never calls, fuses
+    /// common opcode sequences into superinstructions, makes `return f(...)` reuse the frame when
+    /// `f` takes as many parameters, and inlines the functions declared `inline`.
+    #[default]
+    O1,
+    /// Also inlines the functions small enough that are not declared `noinline`.
+    O2,
+}
+
+impl OptLevel {
+    /// Parses the `-O0`, `-O1` and `-O2` command line options.
+    pub fn from_flag(flag: &str) -> Option<Self> {
+        match flag {
+            "-O0" => Some(OptLevel::O0),
+            "-O1" => Some(OptLevel::O1),
+            "-O2" => Some(OptLevel::O2),
+            _ => None,
+        }
+    }
+}
+
+/// Optimizes every function of the program at `level`.
+pub fn optimize(mut prog: Program, level: OptLevel) -> Program {
+    if level >= OptLevel::O1 {
+        // The functions are folded before they are inlined, so that their size is measured
+        // without the code that folding removes, and after, to fold their arguments into them.
+        for f in &mut prog.funcs {
+            fold_statement(&mut f.body, f.alloc_size);
+        }
+        inliner::inline(&mut prog, level);
+        for f in &mut prog.funcs {
+            fold_statement(&mut f.body, f.alloc_size);
+        }
+        // Inlining may leave functions without calls.
+        let reachable: HashSet<String> = reachable_functions(&prog)
+            .into_iter()
+            .map(str::to_owned)
+            .collect();
+        prog.funcs.retain(|f| reachable.contains(&f.name));
+    }
+    prog
+}
+
+/// Returns whether the statement returns, breaks or continues whichever path is taken through
+/// it, so that the statements after it are never executed.
+pub(crate) fn never_completes(stmt: &Statement) -> bool {
+    match &stmt.kind {
+        StatementKind::Return { .. }
+        | StatementKind::Break { .. }
+        | StatementKind::Continue { .. } => true,
+        StatementKind::Block { stmts } => stmts.iter().any(never_completes),
+        StatementKind::If {
+            then_branch,
+            unless_branch,
+            ..
+        } => never_completes(then_branch) && unless_branch.as_deref().is_some_and(never_completes),
+        _ => false,
+    }
+}
+
+fn calls_in_expression<'a>(expr: &'a Expression, calls: &mut Vec<&'a str>) {
+    if let ExpressionKind::Call(name, _) = &expr.kind {
+        calls.push(name);
+    }
+    for operand in expr.kind.operands() {
+        calls_in_expression(operand, calls);
+    }
+}
+
+/// Returns the statements of a block up to the first that never completes, the ones executed.
+fn live_statements(stmts: &[Statement]) -> &[Statement] {
+    match stmts.iter().position(never_completes) {
+        Some(i) => &stmts[..=i],
+        None => stmts,
+    }
+}
+
+/// Collects the functions called by `stmt`, leaving out the statements never executed.
+fn calls_in_statement<'a>(stmt: &'a Statement, calls: &mut Vec<&'a str>) {
+    for expr in stmt.kind.expressions() {
+        calls_in_expression(expr, calls);
+    }
+    let nested = match &stmt.kind {
+        StatementKind::Block { stmts } => live_statements(stmts).iter().collect(),
+        kind => kind.statements(),
+    };
+    for stmt in nested {
+        calls_in_statement(stmt, calls);
+    }
+}
+
+/// Returns the functions that `main` calls, directly or not.
+fn reachable_functions(prog: &Program) -> HashSet<&str> {
+    let mut reachable = HashSet::from(["main"]);
+    let mut pending = vec!["main"];
+    while let Some(name) = pending.pop() {
+        let Some(f) = prog.funcs.iter().find(|f| f.name == name) else {
+            continue;
+        };
+        let mut calls = Vec::new();
+        calls_in_statement(&f.body, &mut calls);
+        for callee in calls {
+            if reachable.insert(callee) {
+                pending.push(callee);
+            }
+        }
+    }
+    reachable
+}
+
+fn dead_code_warnings(stmt: &Statement, warnings: &mut Vec<CompileWarning>) {
+    let StatementKind::Block { stmts } = &stmt.kind else {
+        for stmt in stmt.kind.statements() {
+            dead_code_warnings(stmt, warnings);
+        }
+        return;
+    };
+    let live = live_statements(stmts);
+    for stmt in live {
+        dead_code_warnings(stmt, warnings);
+    }
+    if let [first, .., last] | [first @ last] = &stmts[live.len()..] {
+        warnings.push(CompileWarning::new(
+            "this code is never executed, as the statement before it always returns or jumps",
+            first.span.to(last.span),
+        ));
+    }
+}
+
+/// Returns warnings about the functions that `main` never calls and the statements after a
+/// `return`, a `break` or a `continue`, which `-O1` removes.
+pub fn warnings(prog: &Program) -> Vec<CompileWarning> {
+    let reachable = reachable_functions(prog);
+    let mut warnings = Vec::new();
+    for f in &prog.funcs {
+        if !reachable.contains(f.name.as_str()) {
+            warnings.push(CompileWarning::new(
+                format!("the function {}() is never called from main()", f.name),
+                f.span,
+            ));
+        }
+        dead_code_warnings(&f.body, &mut warnings);
+    }
+    warnings
+}
+
+/// Returns the value of a literal, as the executor holds it in a register.
+fn constant(expr: &Expression) -> Option<Value> {
+    match expr.kind {
+        ExpressionKind::Int(v) => Some(Value::from_i64(Wrapping(v))),
+        ExpressionKind::Float(v) => Some(Value::from_f64(v)),
+        _ => None,
+    }
+}
+
+/// Returns whether `expr` is a literal whose bits are the integer `v`.
+fn is_int(expr: &Expression, v: i64) -> bool {
+    constant(expr).is_some_and(|c| c.as_i64().0 == v)
+}
+
+/// Returns whether evaluating `expr` has no effect besides its value: no call, no input read and
+/// nothing that can fault.
+pub(crate) fn is_pure(expr: &Expression) -> bool {
+    match &expr.kind {
+        ExpressionKind::Call(..)
+        | ExpressionKind::ReadInputByte
+        | ExpressionKind::GetWithComputedIndex(_) => false,
+        ExpressionKind::Div(OperandType::Int, left, right)
+        | ExpressionKind::Rem(OperandType::Int, left, right) => {
+            !is_int(right, 0) && constant(right).is_some() && is_pure(left)
+        }
+        kind => kind.operands().into_iter().all(is_pure),
+    }
+}
+
+fn int(v: Wrapping<i64>) -> ExpressionKind {
+    ExpressionKind::Int(v.0)
+}
+
+fn boolean(v: bool) -> ExpressionKind {
+    ExpressionKind::Int(v as i64)
+}
+
+/// Computes an operation whose operands are all literals.
+fn fold_constants(kind: &ExpressionKind) -> Option<ExpressionKind> {
+    use ExpressionKind as E;
+    use OperandType::{Float as F, Int as I};
+
+    let both = |left: &Expression, right: &Expression| Some((constant(left)?, constant(right)?));
+    Some(match kind {
+        E::Add(t, l, r) | E::Sub(t, l, r) | E::Mul(t, l, r) | E::Div(t, l, r) | E::Rem(t, l, r) => {
+            let (a, b) = both(l, r)?;
+            match (t, kind) {
+                (I, E::Add(..)) => int(a.as_i64() + b.as_i64()),
+                (I, E::Sub(..)) => int(a.as_i64() - b.as_i64()),
+                (I, E::Mul(..)) => int(a.as_i64() * b.as_i64()),
+                (I, E::Div(..) | E::Rem(..)) if b.as_i64().0 == 0 => return None,
+                (I, E::Div(..)) => int(a.as_i64() / b.as_i64()),
+                (I, _) => int(a.as_i64() % b.as_i64()),
+                (F, E::Add(..)) => E::Float(a.as_f64() + b.as_f64()),
+                (F, E::Sub(..)) => E::Float(a.as_f64() - b.as_f64()),
+                (F, E::Mul(..)) => E::Float(a.as_f64() * b.as_f64()),
+                (F, E::Div(..)) => E::Float(a.as_f64() / b.as_f64()),
+                (F, _) => E::Float(a.as_f64() % b.as_f64()),
+            }
+        }
+        E::Eq(t, l, r)
+        | E::Ne(t, l, r)
+        | E::Lt(t, l, r)
+        | E::Gt(t, l, r)
+        | E::Le(t, l, r)
+        | E::Ge(t, l, r) => {
+            let (a, b) = both(l, r)?;
+            let ordering = match t {
+                I => Some(a.as_i64().cmp(&b.as_i64())),
+                F => a.as_f64().partial_cmp(&b.as_f64()),
+            };
+            boolean(match kind {
+                E::Eq(..) => ordering.is_some_and(|o| o.is_eq()),
+                E::Ne(..) => !ordering.is_some_and(|o| o.is_eq()),
+                E::Lt(..) => ordering.is_some_and(|o| o.is_lt()),
+                E::Gt(..) => ordering.is_some_and(|o| o.is_gt()),
+                E::Le(..) => ordering.is_some_and(|o| o.is_le()),
+                _ => ordering.is_some_and(|o| o.is_ge()),
+            })
+        }
+        E::And(l, r)
+        | E::Or(l, r)
+        | E::Xor(l, r)
+        | E::Shl(l, r)
+        | E::Shr(l, r)
+        | E::ShrUnsigned(l, r) => {
+            let (a, b) = both(l, r)?;
+            let (a, b) = (a.as_i64(), b.as_i64());
+            int(match kind {
+                E::And(..) => a & b,
+                E::Or(..) => a | b,
+                E::Xor(..) => a ^ b,
+                E::Shl(..) => a << (b.0 as usize),
+                E::Shr(..) => a >> (b.0 as usize),
+                _ => Wrapping((Wrapping(a.0 as u64) >> (b.0 as usize)).0 as i64),
+            })
+        }
+        E::Itof(operand) => E::Float(constant(operand)?.as_i64().0 as f64),
+        E::Ftoi(operand) => E::Int(constant(operand)?.as_f64() as i64),
+        E::Neg(I, operand) => int(-constant(operand)?.as_i64()),
+        E::Neg(F, operand) => E::Float(-constant(operand)?.as_f64()),
+        E::BitNot(operand) => int(!constant(operand)?.as_i64()),
+        E::LogiNot(operand) => boolean(constant(operand)?.as_i64().0 == 0),
+        _ => return None,
+    })
+}
+
+/// Removes the integer operations that leave an operand unchanged. Float operations are kept, as
+/// even `x float.+ 0.` changes the sign of `-0.`.
+fn simplify(kind: ExpressionKind, alloc_size: usize) -> ExpressionKind {
+    use ExpressionKind as E;
+    use OperandType::Int as I;
+
+    match kind {
+        E::Add(I, l, r) | E::Or(l, r) | E::Xor(l, r) if is_int(&l, 0) => r.kind,
+        E::Add(I, l, r)
+        | E::Sub(I, l, r)
+        | E::Or(l, r)
+        | E::Xor(l, r)
+        | E::Shl(l, r)
+        | E::Shr(l, r)
+        | E::ShrUnsigned(l, r)
+            if is_int(&r, 0) =>
+        {
+            l.kind
+        }
+        E::Mul(I, l, r) | E::And(l, r)
+            if (is_int(&l, 0) && is_pure(&r)) || (is_int(&r, 0) && is_pure(&l)) =>
+        {
+            E::Int(0)
+        }
+        E::Mul(I, l, r) if is_int(&l, 1) => r.kind,
+        E::And(l, r) if is_int(&l, -1) => r.kind,
+        E::Mul(I, l, r) | E::Div(I, l, r) if is_int(&r, 1) => l.kind,
+        E::And(l, r) if is_int(&r, -1) => l.kind,
+        E::Neg(I, operand) if matches!(operand.kind, E::Neg(I, _)) => match operand.kind {
+            E::Neg(_, inner) => inner.kind,
+            _ => unreachable!(),
+        },
+        E::BitNot(operand) if matches!(operand.kind, E::BitNot(_)) => match operand.kind {
+            E::BitNot(inner) => inner.kind,
+            _ => unreachable!(),
+        },
+        E::GetWithComputedIndex(index) => match literal_index(&index, alloc_size) {
+            Some(i) => E::GetWithLiteralIndex(i),
+            None => E::GetWithComputedIndex(index),
+        },
+        kind => kind,
+    }
+}
+
+/// Returns the index of a computed variable access whose index is a literal within the
+/// variables, which needs no range check.
+fn literal_index(index: &Expression, alloc_size: usize) -> Option<usize> {
+    let i = constant(index)?.as_i64().0;
+    usize::try_from(i).ok().filter(|&i| i < alloc_size)
+}
+
+fn fold_expression(expr: &mut Expression, alloc_size: usize) {
+    for operand in expr.kind.operands_mut() {
+        fold_expression(operand, alloc_size);
+    }
+    expr.kind = match fold_constants(&expr.kind) {
+        Some(kind) => kind,
+        None => {
+            let kind = core::mem::replace(&mut expr.kind, ExpressionKind::Int(0));
+            simplify(kind, alloc_size)
+        }
+    };
+}
+
+fn empty_block(span: Span) -> Statement {
+    Statement {
+        kind: StatementKind::Block { stmts: Vec::new() },
+        span,
+    }
+}
+
+fn is_empty(stmt: &Statement) -> bool {
+    match &stmt.kind {
+        StatementKind::Block { stmts } => stmts.is_empty(),
+        StatementKind::Expr { expr } => is_pure(expr),
+        _ => false,
+    }
+}
+
+fn fold_statement(stmt: &mut Statement, alloc_size: usize) {
+    match &mut stmt.kind {
+        StatementKind::While { cond, body } => {
+            fold_expression(cond, alloc_size);
+            fold_statement(body, alloc_size);
+            if is_int(cond, 0) {
+                *stmt = empty_block(stmt.span);
+            }
+        }
+        StatementKind::If {
+            cond,
+            then_branch,
+            unless_branch,
+        } => {
+            fold_expression(cond, alloc_size);
+            fold_statement(then_branch, alloc_size);
+            if let Some(branch) = unless_branch {
+                fold_statement(branch, alloc_size);
+                // An empty `else` branch is the same as none, and spares a jump.
+                if is_empty(branch) {
+                    *unless_branch = None;
+                }
+            }
+            if let Some(c) = constant(cond) {
+                let branch = if c.as_i64().0 != 0 {
+                    Some(then_branch)
+                } else {
+                    unless_branch.as_mut()
+                };
+                *stmt = match branch {
+                    Some(branch) => core::mem::replace(branch, empty_block(stmt.span)),
+                    None => empty_block(stmt.span),
+                };
+            }
+        }
+        StatementKind::SetWithComputedIndex { index, val } => {
+            fold_expression(index, alloc_size);
+            fold_expression(val, alloc_size);
+            if let Some(i) = literal_index(index, alloc_size) {
+                let val = core::mem::replace(
+                    val,
+                    Box::new(Expression {
+                        kind: ExpressionKind::Int(0),
+                        span: stmt.span,
+                    }),
+                );
+                stmt.kind = StatementKind::SetWithLiteralIndex { index: i, val };
+            }
+        }
+        StatementKind::Block { stmts } => {
+            for stmt in stmts.iter_mut() {
+                fold_statement(stmt, alloc_size);
+            }
+            stmts.retain(|stmt| !is_empty(stmt));
+            stmts.truncate(live_statements(stmts).len());
+        }
+        StatementKind::PutBytes { start, len } => {
+            fold_expression(start, alloc_size);
+            fold_expression(len, alloc_size);
+        }
+        StatementKind::SetWithLiteralIndex { val, .. }
+        | StatementKind::PutByte { val }
+        | StatementKind::Return { val } => fold_expression(val, alloc_size),
+        StatementKind::Expr { expr } => fold_expression(expr, alloc_size),
+        StatementKind::Break { .. } | StatementKind::Continue { .. } => {}
+    }
+}
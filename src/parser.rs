@@ -1,110 +1,153 @@
 #![allow(dead_code)]
 
-use crate::lexer::Token;
+use std::collections::HashSet;
+
+use crate::code_generator;
+use crate::error::CompileError;
+use crate::lexer::{Span, Token, TokenKind};
+
+// The token stream is reversed, so `last()` is the next token. It always ends with
+// `TokenKind::Eof`, which is never consumed, so peeking never fails.
+fn peek(tokens: &[Token]) -> &Token {
+    tokens.last().expect("the token stream must end with Eof")
+}
+
+fn unexpected(token: &Token, expected: &str) -> CompileError {
+    CompileError::new(
+        format!("{} is expected, but {} is found", expected, token.kind),
+        token.span,
+    )
+}
 
 macro_rules! expect_token {
     ($tokens:expr, $expected:ident) => {{
-        let Some(next_token) = $tokens.pop() else {
-            panic!(
-                "The keyword '{:?}' is expected, but the end of the input is found",
-                Token::$expected
-            );
-        };
-        if !matches!(next_token, Token::$expected) {
-            panic!(
-                "The keyword '{:?}' is expected, but other token ({:?}) is found",
-                Token::$expected,
-                next_token
-            );
+        let next_token = peek($tokens);
+        if !matches!(next_token.kind, TokenKind::$expected) {
+            return Err(unexpected(next_token, &TokenKind::$expected.to_string()));
         };
+        $tokens.pop().unwrap().span
     }};
 }
 
-fn expect_int(tokens: &mut Vec<Token>) -> i64 {
-    let Some(next_token) = tokens.pop() else {
-        panic!("Int literal is expected, but the end of the input is found");
-    };
-    let Token::Int(val) = next_token else {
-        panic!(
-            "An integer is expected, but other token ({:?}) is found",
-            next_token
-        );
+fn expect_int(tokens: &mut Vec<Token>) -> Result<(i64, Span), CompileError> {
+    let next_token = peek(tokens);
+    let TokenKind::Int(val) = next_token.kind else {
+        return Err(unexpected(next_token, "an integer"));
     };
-    val
+    Ok((val, tokens.pop().unwrap().span))
 }
 
-fn expect_ident(tokens: &mut Vec<Token>) -> String {
-    let Some(next_token) = tokens.pop() else {
-        panic!("Int literal is expected, but the end of the input is found");
+fn expect_ident(tokens: &mut Vec<Token>) -> Result<(String, Span), CompileError> {
+    let next_token = peek(tokens);
+    let TokenKind::Ident(val) = &next_token.kind else {
+        return Err(unexpected(next_token, "an identifier"));
     };
-    let Token::Ident(val) = next_token else {
-        panic!(
-            "An identifier is expected, but other token ({:?}) is found",
-            next_token
-        );
-    };
-    val
+    let val = val.clone();
+    Ok((val, tokens.pop().unwrap().span))
+}
+
+/// Checks that a literal index is within `0..size`, where `size` was declared by `what`.
+fn expect_index(
+    (index, span): (i64, Span),
+    size: usize,
+    what: &str,
+) -> Result<usize, CompileError> {
+    match usize::try_from(index) {
+        Ok(index) if index < size => Ok(index),
+        _ => Err(CompileError::new(
+            format!(
+                "the index {} is out of range for `{} {}`",
+                index, what, size
+            ),
+            span,
+        )),
+    }
 }
 
 macro_rules! consume_token {
     ($tokens:expr, $expected:ident) => {{
-        if let Some(next_token) = $tokens.last() {
-            if matches!(next_token, Token::$expected) {
-                $tokens.pop();
-                Some(())
-            } else {
-                None
-            }
+        if matches!(peek($tokens).kind, TokenKind::$expected) {
+            Some($tokens.pop().unwrap().span)
         } else {
             None
         }
     }};
 }
 
-fn consume_int(tokens: &mut Vec<Token>) -> Option<i64> {
-    let Some(next_token) = tokens.last() else {
+fn consume_int(tokens: &mut Vec<Token>) -> Option<(i64, Span)> {
+    let TokenKind::Int(val) = peek(tokens).kind else {
         return None;
     };
-    let Token::Int(val) = next_token else {
-        return None;
-    };
-    let val = *val;
-    tokens.pop();
-    Some(val)
+    Some((val, tokens.pop().unwrap().span))
 }
 
-fn consume_float(tokens: &mut Vec<Token>) -> Option<f64> {
-    let Some(next_token) = tokens.last() else {
+fn consume_float(tokens: &mut Vec<Token>) -> Option<(f64, Span)> {
+    let TokenKind::Float(val) = peek(tokens).kind else {
         return None;
     };
-    let Token::Float(val) = next_token else {
-        return None;
-    };
-    let val = *val;
-    tokens.pop();
-    Some(val)
+    Some((val, tokens.pop().unwrap().span))
 }
 
-fn consume_ident(tokens: &mut Vec<Token>) -> Option<String> {
-    let Some(next_token) = tokens.last() else {
-        return None;
-    };
-    let Token::Ident(val) = next_token else {
+fn consume_ident(tokens: &mut Vec<Token>) -> Option<(String, Span)> {
+    let TokenKind::Ident(val) = &peek(tokens).kind else {
         return None;
     };
     let val = val.clone();
-    tokens.pop();
-    Some(val)
+    Some((val, tokens.pop().unwrap().span))
 }
 
-#[derive(Debug)]
+/// Checks whether the next tokens form a typed operator such as `int.+`.
+fn is_typed_operator(tokens: &[Token], is_operator: fn(&TokenKind) -> bool) -> bool {
+    matches!(
+        tokens,
+        [
+            ..,
+            op,
+            Token {
+                kind: TokenKind::Dot,
+                ..
+            },
+            Token {
+                kind: TokenKind::IntKeyword | TokenKind::FloatKeyword,
+                ..
+            }
+        ] if is_operator(&op.kind)
+    )
+}
+
+/// Consumes the `int.` or `float.` prefix of a typed operator.
+fn expect_operand_type(tokens: &mut Vec<Token>) -> Result<OperandType, CompileError> {
+    let operand_type = if consume_token!(tokens, IntKeyword).is_some() {
+        OperandType::Int
+    } else if consume_token!(tokens, FloatKeyword).is_some() {
+        OperandType::Float
+    } else {
+        unreachable!()
+    };
+    expect_token!(tokens, Dot);
+    Ok(operand_type)
+}
+
+#[derive(Debug, Clone, Copy)]
 pub enum OperandType {
     Float,
     Int,
 }
 
-#[derive(Debug)]
-pub enum Expression {
+#[derive(Debug, Clone)]
+pub struct Expression {
+    pub kind: ExpressionKind,
+    pub span: Span,
+}
+
+impl Expression {
+    pub(crate) fn new(kind: ExpressionKind, span: Span) -> Self {
+        Self { kind, span }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub enum ExpressionKind {
     Int(i64),
     Float(f64),
     GetWithLiteralIndex(usize),
@@ -136,353 +179,371 @@ pub enum Expression {
     ReadInputByte,
 }
 
-fn parse_primary(tokens: &mut Vec<Token>, d: &FuncSizeData) -> Expression {
-    if consume_token!(tokens, GetKeyword).is_some() {
+impl ExpressionKind {
+    /// Returns the operands of the expression in the order they are evaluated.
+    pub fn operands(&self) -> Vec<&Expression> {
+        match self {
+            ExpressionKind::Int(_)
+            | ExpressionKind::Float(_)
+            | ExpressionKind::GetWithLiteralIndex(_)
+            | ExpressionKind::GetParam(_)
+            | ExpressionKind::ReadInputByte => Vec::new(),
+            ExpressionKind::Call(_, args) => args.iter().collect(),
+            ExpressionKind::GetWithComputedIndex(operand)
+            | ExpressionKind::Itof(operand)
+            | ExpressionKind::Ftoi(operand)
+            | ExpressionKind::Neg(_, operand)
+            | ExpressionKind::BitNot(operand)
+            | ExpressionKind::LogiNot(operand) => vec![operand],
+            ExpressionKind::Add(_, left, right)
+            | ExpressionKind::Sub(_, left, right)
+            | ExpressionKind::Mul(_, left, right)
+            | ExpressionKind::Div(_, left, right)
+            | ExpressionKind::Rem(_, left, right)
+            | ExpressionKind::Eq(_, left, right)
+            | ExpressionKind::Ne(_, left, right)
+            | ExpressionKind::Lt(_, left, right)
+            | ExpressionKind::Gt(_, left, right)
+            | ExpressionKind::Le(_, left, right)
+            | ExpressionKind::Ge(_, left, right)
+            | ExpressionKind::And(left, right)
+            | ExpressionKind::Or(left, right)
+            | ExpressionKind::Xor(left, right)
+            | ExpressionKind::Shl(left, right)
+            | ExpressionKind::Shr(left, right)
+            | ExpressionKind::ShrUnsigned(left, right) => vec![left, right],
+        }
+    }
+
+    /// Like [`ExpressionKind::operands`], but mutable.
+    pub fn operands_mut(&mut self) -> Vec<&mut Expression> {
+        match self {
+            ExpressionKind::Int(_)
+            | ExpressionKind::Float(_)
+            | ExpressionKind::GetWithLiteralIndex(_)
+            | ExpressionKind::GetParam(_)
+            | ExpressionKind::ReadInputByte => Vec::new(),
+            ExpressionKind::Call(_, args) => args.iter_mut().collect(),
+            ExpressionKind::GetWithComputedIndex(operand)
+            | ExpressionKind::Itof(operand)
+            | ExpressionKind::Ftoi(operand)
+            | ExpressionKind::Neg(_, operand)
+            | ExpressionKind::BitNot(operand)
+            | ExpressionKind::LogiNot(operand) => vec![operand],
+            ExpressionKind::Add(_, left, right)
+            | ExpressionKind::Sub(_, left, right)
+            | ExpressionKind::Mul(_, left, right)
+            | ExpressionKind::Div(_, left, right)
+            | ExpressionKind::Rem(_, left, right)
+            | ExpressionKind::Eq(_, left, right)
+            | ExpressionKind::Ne(_, left, right)
+            | ExpressionKind::Lt(_, left, right)
+            | ExpressionKind::Gt(_, left, right)
+            | ExpressionKind::Le(_, left, right)
+            | ExpressionKind::Ge(_, left, right)
+            | ExpressionKind::And(left, right)
+            | ExpressionKind::Or(left, right)
+            | ExpressionKind::Xor(left, right)
+            | ExpressionKind::Shl(left, right)
+            | ExpressionKind::Shr(left, right)
+            | ExpressionKind::ShrUnsigned(left, right) => vec![left, right],
+        }
+    }
+}
+
+fn parse_primary(tokens: &mut Vec<Token>, d: &FuncSizeData) -> Result<Expression, CompileError> {
+    if let Some(begin) = consume_token!(tokens, GetKeyword) {
         if consume_token!(tokens, Dot).is_some() {
-            let index = expect_int(tokens);
-            assert!(index >= 0);
-            let index: usize = index.try_into().unwrap();
-            assert!(index < d.alloc_size);
-            Expression::GetWithLiteralIndex(index)
+            let (index, span) = expect_int(tokens)?;
+            let index = expect_index((index, span), d.alloc_size, "alloc")?;
+            Ok(Expression::new(
+                ExpressionKind::GetWithLiteralIndex(index),
+                begin.to(span),
+            ))
         } else if consume_token!(tokens, OpeningBracket).is_some() {
-            let index = parse_expr(tokens, d);
-            expect_token!(tokens, ClosingBracket);
-            Expression::GetWithComputedIndex(Box::new(index))
-        } else if tokens.is_empty() {
-            panic!("'.' or '[' is expected, but the end of the input is found");
+            let index = parse_expr(tokens, d)?;
+            let end = expect_token!(tokens, ClosingBracket);
+            Ok(Expression::new(
+                ExpressionKind::GetWithComputedIndex(Box::new(index)),
+                begin.to(end),
+            ))
         } else {
-            panic!(
-                "'.' or '[' is expected, but other token ({:?}) is found",
-                tokens.pop().unwrap()
-            );
+            Err(unexpected(peek(tokens), "`.` or `[`"))
         }
-    } else if consume_token!(tokens, ParamKeyword).is_some() {
+    } else if let Some(begin) = consume_token!(tokens, ParamKeyword) {
         expect_token!(tokens, Dot);
-        let index = expect_int(tokens);
-        assert!(index >= 0);
-        let index: usize = index.try_into().unwrap();
-        assert!(index < d.params_size);
-        Expression::GetParam(d.params_size - index)
-    } else if let Some(val) = consume_int(tokens) {
-        Expression::Int(val)
-    } else if let Some(val) = consume_float(tokens) {
-        Expression::Float(val)
-    } else if let Some(fname) = consume_ident(tokens) {
+        let (index, span) = expect_int(tokens)?;
+        let index = expect_index((index, span), d.params_size, "params")?;
+        Ok(Expression::new(
+            ExpressionKind::GetParam(d.params_size - index),
+            begin.to(span),
+        ))
+    } else if let Some((val, span)) = consume_int(tokens) {
+        Ok(Expression::new(ExpressionKind::Int(val), span))
+    } else if let Some((val, span)) = consume_float(tokens) {
+        Ok(Expression::new(ExpressionKind::Float(val), span))
+    } else if let Some((fname, begin)) = consume_ident(tokens) {
         expect_token!(tokens, OpeningParens);
-        let args = if consume_token!(tokens, ClosingParens).is_some() {
-            Vec::new()
+        let (args, end) = if let Some(end) = consume_token!(tokens, ClosingParens) {
+            (Vec::new(), end)
         } else {
-            let mut args = vec![parse_expr(tokens, d)];
+            let mut args = vec![parse_expr(tokens, d)?];
             while consume_token!(tokens, Comma).is_some() {
-                args.push(parse_expr(tokens, d));
+                args.push(parse_expr(tokens, d)?);
             }
-            expect_token!(tokens, ClosingParens);
-            args
+            let end = expect_token!(tokens, ClosingParens);
+            (args, end)
         };
-        Expression::Call(fname, args)
-    } else if consume_token!(tokens, GetcKeyword).is_some() {
-        Expression::ReadInputByte
-    } else if consume_token!(tokens, OpeningParens).is_some() {
-        let node = parse_expr(tokens, d);
-        expect_token!(tokens, ClosingParens);
-        node
-    } else if tokens.is_empty() {
-        panic!(
-            "integer, decimal, identifier, 'getc', 'get' or '(' is expected, but the end of the input is found"
-        );
+        Ok(Expression::new(
+            ExpressionKind::Call(fname, args),
+            begin.to(end),
+        ))
+    } else if let Some(span) = consume_token!(tokens, GetcKeyword) {
+        Ok(Expression::new(ExpressionKind::ReadInputByte, span))
+    } else if let Some(begin) = consume_token!(tokens, OpeningParens) {
+        let mut node = parse_expr(tokens, d)?;
+        let end = expect_token!(tokens, ClosingParens);
+        node.span = begin.to(end);
+        Ok(node)
     } else {
-        panic!(
-            "integer, decimal, identifier, 'getc', 'get' or '(' is expected, but other token ({:?}) is found",
-            tokens.pop().unwrap()
-        );
-    }
-}
-
-fn parse_unary(tokens: &mut Vec<Token>, d: &FuncSizeData) -> Expression {
-    if consume_token!(tokens, Excl).is_some() {
-        return Expression::LogiNot(Box::new(parse_unary(tokens, d)));
-    }
-    if consume_token!(tokens, Tilde).is_some() {
-        return Expression::BitNot(Box::new(parse_unary(tokens, d)));
-    }
-    if consume_token!(tokens, FtoiKeyword).is_some() {
-        return Expression::Ftoi(Box::new(parse_unary(tokens, d)));
-    }
-    if consume_token!(tokens, ItofKeyword).is_some() {
-        return Expression::Itof(Box::new(parse_unary(tokens, d)));
-    }
-    if tokens.len() >= 3
-        && matches!(
-            &tokens[tokens.len() - 3..],
-            [
-                Token::Minus,
-                Token::Dot,
-                Token::IntKeyword | Token::FloatKeyword
-            ]
-        )
-    {
-        let operand_type = if consume_token!(tokens, IntKeyword).is_some() {
-            OperandType::Int
-        } else if consume_token!(tokens, FloatKeyword).is_some() {
-            OperandType::Float
-        } else {
-            unreachable!()
-        };
-        expect_token!(tokens, Dot);
+        Err(unexpected(
+            peek(tokens),
+            "an integer, a decimal, an identifier, `getc`, `get`, `param` or `(`",
+        ))
+    }
+}
+
+fn parse_unary(tokens: &mut Vec<Token>, d: &FuncSizeData) -> Result<Expression, CompileError> {
+    let unary = |kind: fn(Box<Expression>) -> ExpressionKind,
+                 begin: Span,
+                 tokens: &mut Vec<Token>|
+     -> Result<Expression, CompileError> {
+        let operand = parse_unary(tokens, d)?;
+        let span = begin.to(operand.span);
+        Ok(Expression::new(kind(Box::new(operand)), span))
+    };
+    if let Some(begin) = consume_token!(tokens, Excl) {
+        return unary(ExpressionKind::LogiNot, begin, tokens);
+    }
+    if let Some(begin) = consume_token!(tokens, Tilde) {
+        return unary(ExpressionKind::BitNot, begin, tokens);
+    }
+    if let Some(begin) = consume_token!(tokens, FtoiKeyword) {
+        return unary(ExpressionKind::Ftoi, begin, tokens);
+    }
+    if let Some(begin) = consume_token!(tokens, ItofKeyword) {
+        return unary(ExpressionKind::Itof, begin, tokens);
+    }
+    if is_typed_operator(tokens, |k| matches!(k, TokenKind::Minus)) {
+        let begin = peek(tokens).span;
+        let operand_type = expect_operand_type(tokens)?;
         expect_token!(tokens, Minus);
-        return Expression::Neg(operand_type, Box::new(parse_unary(tokens, d)));
+        let operand = parse_unary(tokens, d)?;
+        let span = begin.to(operand.span);
+        return Ok(Expression::new(
+            ExpressionKind::Neg(operand_type, Box::new(operand)),
+            span,
+        ));
     }
-    return parse_primary(tokens, d);
+    parse_primary(tokens, d)
+}
+
+type BinaryKind = fn(OperandType, Box<Expression>, Box<Expression>) -> ExpressionKind;
+
+fn typed_binary(
+    kind: BinaryKind,
+    operand_type: OperandType,
+    left: Expression,
+    right: Expression,
+) -> Expression {
+    let span = left.span.to(right.span);
+    Expression::new(kind(operand_type, Box::new(left), Box::new(right)), span)
 }
 
-fn parse_multiplicative(tokens: &mut Vec<Token>, d: &FuncSizeData) -> Expression {
-    let mut node = parse_unary(tokens, d);
+fn binary(
+    kind: fn(Box<Expression>, Box<Expression>) -> ExpressionKind,
+    left: Expression,
+    right: Expression,
+) -> Expression {
+    let span = left.span.to(right.span);
+    Expression::new(kind(Box::new(left), Box::new(right)), span)
+}
+
+fn parse_multiplicative(
+    tokens: &mut Vec<Token>,
+    d: &FuncSizeData,
+) -> Result<Expression, CompileError> {
+    let mut node = parse_unary(tokens, d)?;
     loop {
-        if tokens.len() >= 3
-            && matches!(
-                &tokens[tokens.len() - 3..],
-                [
-                    Token::Asterisk | Token::Slash | Token::Percent,
-                    Token::Dot,
-                    Token::IntKeyword | Token::FloatKeyword
-                ]
+        if is_typed_operator(tokens, |k| {
+            matches!(
+                k,
+                TokenKind::Asterisk | TokenKind::Slash | TokenKind::Percent
             )
-        {
+        }) {
             // e.g.) int.*
         } else {
-            return node;
+            return Ok(node);
         }
-        let operand_type = if consume_token!(tokens, IntKeyword).is_some() {
-            OperandType::Int
-        } else if consume_token!(tokens, FloatKeyword).is_some() {
-            OperandType::Float
-        } else {
-            unreachable!()
-        };
-        expect_token!(tokens, Dot);
-        if consume_token!(tokens, Asterisk).is_some() {
-            node = Expression::Mul(
-                operand_type,
-                Box::new(node),
-                Box::new(parse_unary(tokens, d)),
-            );
+        let operand_type = expect_operand_type(tokens)?;
+        let kind: BinaryKind = if consume_token!(tokens, Asterisk).is_some() {
+            ExpressionKind::Mul
         } else if consume_token!(tokens, Slash).is_some() {
-            node = Expression::Div(
-                operand_type,
-                Box::new(node),
-                Box::new(parse_unary(tokens, d)),
-            );
+            ExpressionKind::Div
         } else if consume_token!(tokens, Percent).is_some() {
-            node = Expression::Rem(
-                operand_type,
-                Box::new(node),
-                Box::new(parse_unary(tokens, d)),
-            );
+            ExpressionKind::Rem
         } else {
             unreachable!();
-        }
+        };
+        node = typed_binary(kind, operand_type, node, parse_unary(tokens, d)?);
     }
 }
 
-fn parse_additive(tokens: &mut Vec<Token>, d: &FuncSizeData) -> Expression {
-    let mut node = parse_multiplicative(tokens, d);
+fn parse_additive(tokens: &mut Vec<Token>, d: &FuncSizeData) -> Result<Expression, CompileError> {
+    let mut node = parse_multiplicative(tokens, d)?;
     loop {
-        if tokens.len() >= 3
-            && matches!(
-                &tokens[tokens.len() - 3..],
-                [
-                    Token::Plus | Token::Minus,
-                    Token::Dot,
-                    Token::IntKeyword | Token::FloatKeyword
-                ]
-            )
-        {
+        if is_typed_operator(tokens, |k| matches!(k, TokenKind::Plus | TokenKind::Minus)) {
             // e.g.) int.+
         } else {
-            return node;
+            return Ok(node);
         }
-        let operand_type = if consume_token!(tokens, IntKeyword).is_some() {
-            OperandType::Int
-        } else if consume_token!(tokens, FloatKeyword).is_some() {
-            OperandType::Float
-        } else {
-            unreachable!()
-        };
-        expect_token!(tokens, Dot);
-        if consume_token!(tokens, Plus).is_some() {
-            node = Expression::Add(
-                operand_type,
-                Box::new(node),
-                Box::new(parse_multiplicative(tokens, d)),
-            );
+        let operand_type = expect_operand_type(tokens)?;
+        let kind: BinaryKind = if consume_token!(tokens, Plus).is_some() {
+            ExpressionKind::Add
         } else if consume_token!(tokens, Minus).is_some() {
-            node = Expression::Sub(
-                operand_type,
-                Box::new(node),
-                Box::new(parse_multiplicative(tokens, d)),
-            );
+            ExpressionKind::Sub
         } else {
             unreachable!();
-        }
+        };
+        node = typed_binary(kind, operand_type, node, parse_multiplicative(tokens, d)?);
     }
 }
 
-fn parse_bitshift(tokens: &mut Vec<Token>, d: &FuncSizeData) -> Expression {
-    let mut node = parse_additive(tokens, d);
+fn parse_bitshift(tokens: &mut Vec<Token>, d: &FuncSizeData) -> Result<Expression, CompileError> {
+    let mut node = parse_additive(tokens, d)?;
     loop {
         if consume_token!(tokens, DoubleLt).is_some() {
-            node = Expression::Shl(Box::new(node), Box::new(parse_additive(tokens, d)));
+            node = binary(ExpressionKind::Shl, node, parse_additive(tokens, d)?);
         } else if consume_token!(tokens, DoubleGt).is_some() {
-            node = Expression::Shr(Box::new(node), Box::new(parse_additive(tokens, d)));
+            node = binary(ExpressionKind::Shr, node, parse_additive(tokens, d)?);
         } else if consume_token!(tokens, TripleGt).is_some() {
-            node = Expression::ShrUnsigned(Box::new(node), Box::new(parse_additive(tokens, d)));
+            node = binary(
+                ExpressionKind::ShrUnsigned,
+                node,
+                parse_additive(tokens, d)?,
+            );
         } else {
-            return node;
+            return Ok(node);
         }
     }
 }
 
-fn parse_bit_and(tokens: &mut Vec<Token>, d: &FuncSizeData) -> Expression {
-    let mut node = parse_bitshift(tokens, d);
+fn parse_bit_and(tokens: &mut Vec<Token>, d: &FuncSizeData) -> Result<Expression, CompileError> {
+    let mut node = parse_bitshift(tokens, d)?;
     loop {
         if consume_token!(tokens, Ampersand).is_some() {
-            let right = parse_bitshift(tokens, d);
-            node = Expression::And(Box::new(node), Box::new(right))
+            let right = parse_bitshift(tokens, d)?;
+            node = binary(ExpressionKind::And, node, right)
         } else {
-            return node;
+            return Ok(node);
         }
     }
 }
-fn parse_bit_xor(tokens: &mut Vec<Token>, d: &FuncSizeData) -> Expression {
-    let mut node = parse_bit_and(tokens, d);
+fn parse_bit_xor(tokens: &mut Vec<Token>, d: &FuncSizeData) -> Result<Expression, CompileError> {
+    let mut node = parse_bit_and(tokens, d)?;
     loop {
         if consume_token!(tokens, Circumflex).is_some() {
-            let right = parse_bit_and(tokens, d);
-            node = Expression::Xor(Box::new(node), Box::new(right))
+            let right = parse_bit_and(tokens, d)?;
+            node = binary(ExpressionKind::Xor, node, right)
         } else {
-            return node;
+            return Ok(node);
         }
     }
 }
-fn parse_bit_or(tokens: &mut Vec<Token>, d: &FuncSizeData) -> Expression {
-    let mut node = parse_bit_xor(tokens, d);
+fn parse_bit_or(tokens: &mut Vec<Token>, d: &FuncSizeData) -> Result<Expression, CompileError> {
+    let mut node = parse_bit_xor(tokens, d)?;
     loop {
         if consume_token!(tokens, Pipe).is_some() {
-            let right = parse_bit_xor(tokens, d);
-            node = Expression::Or(Box::new(node), Box::new(right))
+            let right = parse_bit_xor(tokens, d)?;
+            node = binary(ExpressionKind::Or, node, right)
         } else {
-            return node;
+            return Ok(node);
         }
     }
 }
 
-fn parse_relational(tokens: &mut Vec<Token>, d: &FuncSizeData) -> Expression {
-    let mut node = parse_bit_or(tokens, d);
+fn parse_relational(tokens: &mut Vec<Token>, d: &FuncSizeData) -> Result<Expression, CompileError> {
+    let mut node = parse_bit_or(tokens, d)?;
 
     loop {
-        if tokens.len() >= 3
-            && matches!(
-                &tokens[tokens.len() - 3..],
-                [
-                    Token::GreaterThan | Token::LessThan | Token::GtEq | Token::LtEq,
-                    Token::Dot,
-                    Token::IntKeyword | Token::FloatKeyword
-                ]
+        if is_typed_operator(tokens, |k| {
+            matches!(
+                k,
+                TokenKind::GreaterThan | TokenKind::LessThan | TokenKind::GtEq | TokenKind::LtEq
             )
-        {
+        }) {
             // e.g.) int.>=
         } else {
-            return node;
+            return Ok(node);
         }
-        let operand_type = if consume_token!(tokens, IntKeyword).is_some() {
-            OperandType::Int
-        } else if consume_token!(tokens, FloatKeyword).is_some() {
-            OperandType::Float
-        } else {
-            unreachable!()
-        };
-        expect_token!(tokens, Dot);
-        if consume_token!(tokens, GreaterThan).is_some() {
-            node = Expression::Gt(
-                operand_type,
-                Box::new(node),
-                Box::new(parse_bit_or(tokens, d)),
-            );
+        let operand_type = expect_operand_type(tokens)?;
+        let kind: BinaryKind = if consume_token!(tokens, GreaterThan).is_some() {
+            ExpressionKind::Gt
         } else if consume_token!(tokens, LessThan).is_some() {
-            node = Expression::Lt(
-                operand_type,
-                Box::new(node),
-                Box::new(parse_bit_or(tokens, d)),
-            );
+            ExpressionKind::Lt
         } else if consume_token!(tokens, GtEq).is_some() {
-            node = Expression::Ge(
-                operand_type,
-                Box::new(node),
-                Box::new(parse_bit_or(tokens, d)),
-            );
+            ExpressionKind::Ge
         } else if consume_token!(tokens, LtEq).is_some() {
-            node = Expression::Le(
-                operand_type,
-                Box::new(node),
-                Box::new(parse_bit_or(tokens, d)),
-            );
+            ExpressionKind::Le
         } else {
             unreachable!();
-        }
+        };
+        node = typed_binary(kind, operand_type, node, parse_bit_or(tokens, d)?);
     }
 }
 
-fn parse_equality(tokens: &mut Vec<Token>, d: &FuncSizeData) -> Expression {
-    let mut node = parse_relational(tokens, d);
+fn parse_equality(tokens: &mut Vec<Token>, d: &FuncSizeData) -> Result<Expression, CompileError> {
+    let mut node = parse_relational(tokens, d)?;
 
     loop {
-        if tokens.len() >= 3
-            && matches!(
-                &tokens[tokens.len() - 3..],
-                [
-                    Token::DoubleEq | Token::ExclEq,
-                    Token::Dot,
-                    Token::IntKeyword | Token::FloatKeyword
-                ]
-            )
-        {
+        if is_typed_operator(tokens, |k| {
+            matches!(k, TokenKind::DoubleEq | TokenKind::ExclEq)
+        }) {
             // e.g.) int.==
         } else {
-            return node;
+            return Ok(node);
         }
-        let operand_type = if consume_token!(tokens, IntKeyword).is_some() {
-            OperandType::Int
-        } else if consume_token!(tokens, FloatKeyword).is_some() {
-            OperandType::Float
-        } else {
-            unreachable!()
-        };
-        expect_token!(tokens, Dot);
-        if consume_token!(tokens, DoubleEq).is_some() {
-            node = Expression::Eq(
-                operand_type,
-                Box::new(node),
-                Box::new(parse_relational(tokens, d)),
-            );
+        let operand_type = expect_operand_type(tokens)?;
+        let kind: BinaryKind = if consume_token!(tokens, DoubleEq).is_some() {
+            ExpressionKind::Eq
         } else if consume_token!(tokens, ExclEq).is_some() {
-            node = Expression::Ne(
-                operand_type,
-                Box::new(node),
-                Box::new(parse_relational(tokens, d)),
-            );
+            ExpressionKind::Ne
         } else {
             unreachable!();
-        }
+        };
+        node = typed_binary(kind, operand_type, node, parse_relational(tokens, d)?);
     }
 }
 
 #[inline(always)]
-fn parse_expr(tokens: &mut Vec<Token>, d: &FuncSizeData) -> Expression {
+fn parse_expr(tokens: &mut Vec<Token>, d: &FuncSizeData) -> Result<Expression, CompileError> {
     parse_equality(tokens, d)
 }
 
-#[derive(Debug)]
-pub enum Statement {
+#[derive(Debug, Clone)]
+pub struct Statement {
+    pub kind: StatementKind,
+    pub span: Span,
+}
+
+impl Statement {
+    pub(crate) fn new(kind: StatementKind, span: Span) -> Self {
+        Self { kind, span }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub enum StatementKind {
     While {
         cond: Box<Expression>,
         body: Box<Statement>,
@@ -490,7 +551,8 @@ pub enum Statement {
     If {
         cond: Box<Expression>,
         then_branch: Box<Statement>,
-        unless_branch: Box<Statement>,
+        /// `None` if the `else` branch is omitted.
+        unless_branch: Option<Box<Statement>>,
     },
     SetWithLiteralIndex {
         index: usize,
@@ -506,75 +568,214 @@ pub enum Statement {
     PutByte {
         val: Box<Expression>,
     },
+    /// Writes the locals `start..start + len` as bytes.
+    PutBytes {
+        start: Box<Expression>,
+        len: Box<Expression>,
+    },
     Return {
         val: Box<Expression>,
     },
+    /// Exits the `while` enclosing the statement, `depth` loops out from the innermost one.
+    Break {
+        depth: usize,
+    },
+    /// Jumps to the condition of the `while` enclosing the statement, `depth` loops out from the
+    /// innermost one.
+    Continue {
+        depth: usize,
+    },
     Expr {
         expr: Box<Expression>,
     },
 }
 
-fn parse_stmt(tokens: &mut Vec<Token>, d: &FuncSizeData) -> Statement {
-    if consume_token!(tokens, WhileKeyword).is_some() {
-        let cond = parse_expr(tokens, d);
-        expect_token!(tokens, Colon);
-        let body = parse_stmt(tokens, d);
-        Statement::While {
-            cond: Box::new(cond),
-            body: Box::new(body),
+impl StatementKind {
+    /// Returns the expressions directly in the statement, in the order they are evaluated.
+    pub fn expressions(&self) -> Vec<&Expression> {
+        match self {
+            StatementKind::While { cond, .. } | StatementKind::If { cond, .. } => vec![cond],
+            StatementKind::SetWithLiteralIndex { val, .. }
+            | StatementKind::PutByte { val }
+            | StatementKind::Return { val } => vec![val],
+            StatementKind::SetWithComputedIndex { index, val } => vec![index, val],
+            StatementKind::PutBytes { start, len } => vec![start, len],
+            StatementKind::Expr { expr } => vec![expr],
+            StatementKind::Block { .. }
+            | StatementKind::Break { .. }
+            | StatementKind::Continue { .. } => Vec::new(),
         }
-    } else if consume_token!(tokens, SetKeyword).is_some() {
+    }
+
+    /// Like [`StatementKind::expressions`], but mutable.
+    pub fn expressions_mut(&mut self) -> Vec<&mut Expression> {
+        match self {
+            StatementKind::While { cond, .. } | StatementKind::If { cond, .. } => vec![cond],
+            StatementKind::SetWithLiteralIndex { val, .. }
+            | StatementKind::PutByte { val }
+            | StatementKind::Return { val } => vec![val],
+            StatementKind::SetWithComputedIndex { index, val } => vec![index, val],
+            StatementKind::PutBytes { start, len } => vec![start, len],
+            StatementKind::Expr { expr } => vec![expr],
+            StatementKind::Block { .. }
+            | StatementKind::Break { .. }
+            | StatementKind::Continue { .. } => Vec::new(),
+        }
+    }
+
+    /// Returns the statements nested in the statement.
+    pub fn statements(&self) -> Vec<&Statement> {
+        match self {
+            StatementKind::While { body, .. } => vec![body],
+            StatementKind::If {
+                then_branch,
+                unless_branch,
+                ..
+            } => std::iter::once(then_branch)
+                .chain(unless_branch)
+                .map(|stmt| &**stmt)
+                .collect(),
+            StatementKind::Block { stmts } => stmts.iter().collect(),
+            _ => Vec::new(),
+        }
+    }
+}
+
+/// Returns how many loops out from the innermost one a `break` or a `continue` with `label` jumps
+/// out of, where `loops` holds the labels of the enclosing loops, innermost last.
+fn loop_depth(
+    loops: &[Option<String>],
+    label: Option<(String, Span)>,
+    keyword: &str,
+    span: Span,
+) -> Result<usize, CompileError> {
+    match label {
+        None if loops.is_empty() => Err(CompileError::new(
+            format!("`{}` is used outside of a `while`", keyword),
+            span,
+        )),
+        None => Ok(0),
+        Some((name, label_span)) => (loops.iter().rev())
+            .position(|l| l.as_deref() == Some(name.as_str()))
+            .ok_or_else(|| {
+                CompileError::new(
+                    format!("no enclosing `while` is labelled `{}`", name),
+                    label_span,
+                )
+            }),
+    }
+}
+
+/// Parses a statement, `loops` holding the labels of the enclosing loops, innermost last.
+fn parse_stmt(
+    tokens: &mut Vec<Token>,
+    d: &FuncSizeData,
+    loops: &mut Vec<Option<String>>,
+    errors: &mut Vec<CompileError>,
+) -> Result<Statement, CompileError> {
+    // e.g.) outer: while ...
+    let label = match &tokens[..] {
+        [
+            ..,
+            Token {
+                kind: TokenKind::Colon,
+                ..
+            },
+            Token {
+                kind: TokenKind::Ident(_),
+                ..
+            },
+        ] => {
+            let label = consume_ident(tokens);
+            tokens.pop();
+            label
+        }
+        _ => None,
+    };
+    if label.is_some() || matches!(peek(tokens).kind, TokenKind::WhileKeyword) {
+        let while_span = expect_token!(tokens, WhileKeyword);
+        let begin = label.as_ref().map_or(while_span, |(_, span)| *span);
+        let cond = parse_expr(tokens, d)?;
+        expect_token!(tokens, Colon);
+        loops.push(label.map(|(name, _)| name));
+        let body = parse_stmt(tokens, d, loops, errors);
+        loops.pop();
+        let body = body?;
+        let span = begin.to(body.span);
+        Ok(Statement::new(
+            StatementKind::While {
+                cond: Box::new(cond),
+                body: Box::new(body),
+            },
+            span,
+        ))
+    } else if let Some(begin) = consume_token!(tokens, SetKeyword) {
         if consume_token!(tokens, Dot).is_some() {
-            let index = expect_int(tokens);
-            assert!(index >= 0);
-            let index: usize = index.try_into().unwrap();
-            assert!(index < d.alloc_size);
+            let index = expect_index(expect_int(tokens)?, d.alloc_size, "alloc")?;
             expect_token!(tokens, Equal);
-            let val = parse_expr(tokens, d);
-            expect_token!(tokens, Semicolon);
-            Statement::SetWithLiteralIndex {
-                index,
-                val: Box::new(val),
-            }
+            let val = parse_expr(tokens, d)?;
+            let end = expect_token!(tokens, Semicolon);
+            Ok(Statement::new(
+                StatementKind::SetWithLiteralIndex {
+                    index,
+                    val: Box::new(val),
+                },
+                begin.to(end),
+            ))
         } else if consume_token!(tokens, OpeningBracket).is_some() {
-            let index = parse_expr(tokens, d);
+            let index = parse_expr(tokens, d)?;
             expect_token!(tokens, ClosingBracket);
             expect_token!(tokens, Equal);
-            let val = parse_expr(tokens, d);
-            expect_token!(tokens, Semicolon);
-            Statement::SetWithComputedIndex {
-                index: Box::new(index),
-                val: Box::new(val),
-            }
-        } else if tokens.is_empty() {
-            panic!("'.' or '[' is expected, but the end of the input is found");
+            let val = parse_expr(tokens, d)?;
+            let end = expect_token!(tokens, Semicolon);
+            Ok(Statement::new(
+                StatementKind::SetWithComputedIndex {
+                    index: Box::new(index),
+                    val: Box::new(val),
+                },
+                begin.to(end),
+            ))
         } else {
-            panic!(
-                "'.' or '[' is expected, but other token ({:?}) is found",
-                tokens.pop().unwrap()
-            );
+            Err(unexpected(peek(tokens), "`.` or `[`"))
         }
-    } else if consume_token!(tokens, IfKeyword).is_some() {
-        let cond = parse_expr(tokens, d);
+    } else if let Some(begin) = consume_token!(tokens, IfKeyword) {
+        let cond = parse_expr(tokens, d)?;
         expect_token!(tokens, Colon);
-        let then_branch = parse_stmt(tokens, d);
-        expect_token!(tokens, ElseKeyword);
-        let unless_branch = parse_stmt(tokens, d);
-        Statement::If {
-            cond: Box::new(cond),
-            then_branch: Box::new(then_branch),
-            unless_branch: Box::new(unless_branch),
-        }
-    } else if consume_token!(tokens, OpeningBrace).is_some() {
-        let mut stmts = Vec::new();
-        while consume_token!(tokens, ClosingBrace).is_none() {
-            stmts.push(parse_stmt(tokens, d));
-        }
-        Statement::Block { stmts }
-    } else if consume_token!(tokens, PutcKeyword).is_some() {
-        let val = parse_expr(tokens, d);
-        expect_token!(tokens, Semicolon);
-        Statement::PutByte { val: Box::new(val) }
+        let then_branch = parse_stmt(tokens, d, loops, errors)?;
+        let unless_branch = match consume_token!(tokens, ElseKeyword) {
+            Some(_) => Some(Box::new(parse_stmt(tokens, d, loops, errors)?)),
+            None => None,
+        };
+        let span = begin.to(unless_branch.as_ref().map_or(then_branch.span, |s| s.span));
+        Ok(Statement::new(
+            StatementKind::If {
+                cond: Box::new(cond),
+                then_branch: Box::new(then_branch),
+                unless_branch,
+            },
+            span,
+        ))
+    } else if let Some(begin) = consume_token!(tokens, OpeningBrace) {
+        parse_block(tokens, d, loops, errors, begin)
+    } else if let Some(begin) = consume_token!(tokens, PutcKeyword) {
+        let val = parse_expr(tokens, d)?;
+        let end = expect_token!(tokens, Semicolon);
+        Ok(Statement::new(
+            StatementKind::PutByte { val: Box::new(val) },
+            begin.to(end),
+        ))
+    } else if let Some(begin) = consume_token!(tokens, PutbufKeyword) {
+        let start = parse_expr(tokens, d)?;
+        expect_token!(tokens, Comma);
+        let len = parse_expr(tokens, d)?;
+        let end = expect_token!(tokens, Semicolon);
+        Ok(Statement::new(
+            StatementKind::PutBytes {
+                start: Box::new(start),
+                len: Box::new(len),
+            },
+            begin.to(end),
+        ))
     // } else if consume_token!(tokens, PrintFloatKeyword).is_some() {
     //     let val = parse_expr(tokens, d);
     //     expect_token!(tokens, Semicolon);
@@ -583,25 +784,153 @@ fn parse_stmt(tokens: &mut Vec<Token>, d: &FuncSizeData) -> Statement {
     //     let val = parse_expr(tokens, d);
     //     expect_token!(tokens, Semicolon);
     //     Statement::PrintInt { val: Box::new(val) }
-    } else if consume_token!(tokens, ReturnKeyword).is_some() {
-        let val = parse_expr(tokens, d);
-        expect_token!(tokens, Semicolon);
-        Statement::Return { val: Box::new(val) }
+    } else if let Some(begin) = consume_token!(tokens, BreakKeyword) {
+        let label = consume_ident(tokens);
+        let span = begin.to(expect_token!(tokens, Semicolon));
+        let kind = match loop_depth(loops, label, "break", span) {
+            Ok(depth) => StatementKind::Break { depth },
+            // The statement is complete, so the parsing goes on after it.
+            Err(e) => {
+                report(errors, e);
+                StatementKind::Block { stmts: Vec::new() }
+            }
+        };
+        Ok(Statement::new(kind, span))
+    } else if let Some(begin) = consume_token!(tokens, ContinueKeyword) {
+        let label = consume_ident(tokens);
+        let span = begin.to(expect_token!(tokens, Semicolon));
+        let kind = match loop_depth(loops, label, "continue", span) {
+            Ok(depth) => StatementKind::Continue { depth },
+            Err(e) => {
+                report(errors, e);
+                StatementKind::Block { stmts: Vec::new() }
+            }
+        };
+        Ok(Statement::new(kind, span))
+    } else if let Some(begin) = consume_token!(tokens, ReturnKeyword) {
+        let val = parse_expr(tokens, d)?;
+        let end = expect_token!(tokens, Semicolon);
+        Ok(Statement::new(
+            StatementKind::Return { val: Box::new(val) },
+            begin.to(end),
+        ))
     } else {
-        let val = parse_expr(tokens, d);
-        expect_token!(tokens, Semicolon);
-        Statement::Expr {
-            expr: Box::new(val),
+        let val = parse_expr(tokens, d)?;
+        let end = expect_token!(tokens, Semicolon);
+        let span = val.span.to(end);
+        Ok(Statement::new(
+            StatementKind::Expr {
+                expr: Box::new(val),
+            },
+            span,
+        ))
+    }
+}
+
+/// Returns whether a function definition, or the end of the input, starts with the token.
+fn starts_function(kind: &TokenKind) -> bool {
+    matches!(
+        kind,
+        TokenKind::FuncKeyword
+            | TokenKind::InlineKeyword
+            | TokenKind::NoinlineKeyword
+            | TokenKind::Eof
+    )
+}
+
+/// Records an error unless it is a repetition of the last one, which happens when an unclosed
+/// block is reported by each enclosing block.
+fn report(errors: &mut Vec<CompileError>, error: CompileError) {
+    if errors.last().is_none_or(|last| last.span != error.span) {
+        errors.push(error);
+    }
+}
+
+/// Skips tokens after an error until parsing can resume: just after a `;` or a `{ ... }` block
+/// (with its `else` branches), or just before a `}` or `func`.
+fn synchronize(tokens: &mut Vec<Token>) {
+    let mut depth = 0usize;
+    loop {
+        match &peek(tokens).kind {
+            kind if starts_function(kind) => return,
+            TokenKind::Semicolon if depth == 0 => {
+                tokens.pop();
+                return;
+            }
+            TokenKind::ClosingBrace if depth == 0 => return,
+            TokenKind::ClosingBrace => {
+                tokens.pop();
+                depth -= 1;
+                if depth == 0 && !matches!(peek(tokens).kind, TokenKind::ElseKeyword) {
+                    return;
+                }
+            }
+            TokenKind::OpeningBrace => {
+                tokens.pop();
+                depth += 1;
+            }
+            _ => {
+                tokens.pop();
+            }
         }
     }
 }
 
-#[derive(Debug)]
+/// Parses the statements of a block whose `{` has been consumed, recovering from errors in
+/// each statement.
+fn parse_block(
+    tokens: &mut Vec<Token>,
+    d: &FuncSizeData,
+    loops: &mut Vec<Option<String>>,
+    errors: &mut Vec<CompileError>,
+    begin: Span,
+) -> Result<Statement, CompileError> {
+    let mut stmts = Vec::new();
+    let end = loop {
+        if let Some(end) = consume_token!(tokens, ClosingBrace) {
+            break end;
+        }
+        if starts_function(&peek(tokens).kind) {
+            return Err(unexpected(peek(tokens), "`}`"));
+        }
+        match parse_stmt(tokens, d, loops, errors) {
+            Ok(stmt) => stmts.push(stmt),
+            Err(e) => {
+                report(errors, e);
+                synchronize(tokens);
+            }
+        }
+    };
+    Ok(Statement::new(
+        StatementKind::Block { stmts },
+        begin.to(end),
+    ))
+}
+
+/// Whether the calls to a function are inlined, set by the `inline` or `noinline` before `func`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Inlining {
+    /// Inlined from `-O2` if the function is small enough.
+    #[default]
+    Auto,
+    /// `inline`: inlined from `-O1` whatever its size.
+    Always,
+    /// `noinline`: never inlined.
+    Never,
+}
+
+#[derive(Debug, Clone)]
 pub struct FunctionData {
     pub body: Statement,
     pub name: String,
     pub params_size: usize,
     pub alloc_size: usize,
+    /// The variables after the `alloc` ones that hold the parameters and variables of the
+    /// functions inlined into this one. `get[]` and `set[]` cannot reach them.
+    pub inline_alloc_size: usize,
+    pub inlining: Inlining,
+    /// The span of the function name.
+    pub span: Span,
 }
 
 struct FuncSizeData {
@@ -609,61 +938,157 @@ struct FuncSizeData {
     alloc_size: usize,
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct Program {
     pub funcs: Vec<FunctionData>,
 }
 
-fn parse_func(tokens: &mut Vec<Token>) -> FunctionData {
+fn expect_size(tokens: &mut Vec<Token>) -> Result<usize, CompileError> {
+    let (size, span) = expect_int(tokens)?;
+    usize::try_from(size)
+        .map_err(|_| CompileError::new(format!("the size {} is negative", size), span))
+}
+
+fn parse_func(
+    tokens: &mut Vec<Token>,
+    errors: &mut Vec<CompileError>,
+) -> Result<FunctionData, CompileError> {
+    let inlining = if consume_token!(tokens, InlineKeyword).is_some() {
+        Inlining::Always
+    } else if consume_token!(tokens, NoinlineKeyword).is_some() {
+        Inlining::Never
+    } else {
+        Inlining::Auto
+    };
     expect_token!(tokens, FuncKeyword);
-    let funcname = expect_ident(tokens);
-    expect_token!(tokens, OpeningBrace);
+    let (funcname, span) = expect_ident(tokens)?;
+    let begin = expect_token!(tokens, OpeningBrace);
     expect_token!(tokens, ParamsKeyword);
-    let params_size = expect_int(tokens);
-    assert!(params_size >= 0);
-    let params_size: usize = params_size.try_into().unwrap();
+    let params_size = expect_size(tokens)?;
     expect_token!(tokens, Semicolon);
     expect_token!(tokens, AllocKeyword);
-    let alloc_size = expect_int(tokens);
-    assert!(alloc_size >= 0);
-    let alloc_size: usize = alloc_size.try_into().unwrap();
+    let alloc_size = expect_size(tokens)?;
     expect_token!(tokens, Semicolon);
     let d = FuncSizeData {
         params_size,
         alloc_size,
     };
-    let body = {
-        let mut stmts = Vec::new();
-        while consume_token!(tokens, ClosingBrace).is_none() {
-            stmts.push(parse_stmt(tokens, &d));
-        }
-        Statement::Block { stmts }
-    };
-    FunctionData {
+    let body = parse_block(tokens, &d, &mut Vec::new(), errors, begin)?;
+    Ok(FunctionData {
         body,
         name: funcname,
         params_size,
         alloc_size,
+        inline_alloc_size: 0,
+        inlining,
+        span,
+    })
+}
+
+/// Parses a sequence of function definitions, reporting every syntax error found.
+pub fn parse_functions(tokens: Vec<Token>) -> Result<Vec<FunctionData>, Vec<CompileError>> {
+    let (funcs, errors) = recover_functions(tokens);
+    if errors.is_empty() {
+        Ok(funcs)
+    } else {
+        Err(errors)
     }
 }
 
-pub fn parse_program(mut tokens: Vec<Token>) -> Program {
+/// Parses a sequence of function definitions, returning the ones parsed despite the syntax
+/// errors along with the errors.
+fn recover_functions(mut tokens: Vec<Token>) -> (Vec<FunctionData>, Vec<CompileError>) {
     tokens.reverse();
     let tokens = &mut tokens;
 
+    let mut errors = Vec::new();
     let mut funcs: Vec<FunctionData> = Vec::new();
-    while !tokens.is_empty() {
-        let parsed_func = parse_func(tokens);
-        assert!(
-            funcs.iter().all(|f| f.name != parsed_func.name),
-            "The function {}() is defined twice or more",
-            parsed_func.name
-        );
+    while !matches!(peek(tokens).kind, TokenKind::Eof) {
+        let remaining = tokens.len();
+        let parsed_func = match parse_func(tokens, &mut errors) {
+            Ok(parsed_func) => parsed_func,
+            Err(e) => {
+                report(&mut errors, e);
+                // Resume at the next function definition.
+                if tokens.len() == remaining {
+                    tokens.pop();
+                }
+                while !starts_function(&peek(tokens).kind) {
+                    tokens.pop();
+                }
+                continue;
+            }
+        };
+        if funcs.iter().any(|f| f.name == parsed_func.name) {
+            errors.push(CompileError::new(
+                format!(
+                    "the function {}() is defined twice or more",
+                    parsed_func.name
+                ),
+                parsed_func.span,
+            ));
+        }
         funcs.push(parsed_func);
     }
-    assert!(
-        funcs.iter().any(|f| f.name == "main"),
-        "The function main() is missing"
-    );
-    Program { funcs }
+    (funcs, errors)
+}
+
+/// Parses the tokens produced by [`crate::lexer::parse`], reporting every syntax error found.
+pub fn parse_program(tokens: Vec<Token>) -> Result<Program, Vec<CompileError>> {
+    // The functions whose definition has an error are declared all the same.
+    let declared: HashSet<String> = (tokens.windows(2))
+        .filter_map(|pair| match (&pair[0].kind, &pair[1].kind) {
+            (TokenKind::FuncKeyword, TokenKind::Ident(name)) => Some(name.clone()),
+            _ => None,
+        })
+        .collect();
+    let (funcs, mut errors) = recover_functions(tokens);
+    if !errors.is_empty() {
+        // The calls are checked in what could be parsed, to report their errors in the same pass.
+        errors.extend(code_generator::check_calls(&funcs, &declared));
+        errors.sort_by_key(|e| e.span.map(|span| span.start));
+        return Err(errors);
+    }
+    if !funcs.iter().any(|f| f.name == "main") {
+        return Err(vec![CompileError::unlocated(
+            "the function main() is missing",
+        )]);
+    }
+    Ok(Program { funcs })
+}
+
+/// Parses a sequence of statements as the body of a function with no parameters and
+/// `alloc_size` local variables, reporting every syntax error found.
+pub fn parse_statements(
+    mut tokens: Vec<Token>,
+    alloc_size: usize,
+) -> Result<Vec<Statement>, Vec<CompileError>> {
+    tokens.reverse();
+    let tokens = &mut tokens;
+    let d = FuncSizeData {
+        params_size: 0,
+        alloc_size,
+    };
+
+    let mut errors = Vec::new();
+    let mut stmts = Vec::new();
+    while !matches!(peek(tokens).kind, TokenKind::Eof) {
+        let remaining = tokens.len();
+        match parse_stmt(tokens, &d, &mut Vec::new(), &mut errors) {
+            Ok(stmt) => stmts.push(stmt),
+            Err(e) => {
+                report(&mut errors, e);
+                synchronize(tokens);
+                // `synchronize` stops before a stray `}` or `func`.
+                if tokens.len() == remaining {
+                    tokens.pop();
+                }
+            }
+        }
+    }
+    if errors.is_empty() {
+        Ok(stmts)
+    } else {
+        Err(errors)
+    }
 }
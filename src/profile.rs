@@ -0,0 +1,251 @@
+//! Instruction counts and timings of an execution.
+
+use std::collections::HashMap;
+use std::io::{self, Write};
+use std::ops::ControlFlow;
+use std::time::{Duration, Instant};
+
+use crate::code_generator::OpCode;
+use crate::executor::{Machine, Observer};
+
+/// A function being executed, on the profiler's copy of the call stack.
+struct Frame {
+    function: usize,
+    entered: Instant,
+    /// The time spent in the functions it called so far.
+    callees: Duration,
+}
+
+/// An [`Observer`] counting the opcodes executed per function and per source line and the calls
+/// per function, and timing the functions from their calls to their returns.
+#[derive(Default)]
+pub struct Profiler {
+    names: Vec<String>,
+    /// The index of the function and the source line of each opcode.
+    function_of: Vec<usize>,
+    line_of: Vec<usize>,
+    calls: Vec<u64>,
+    opcodes: Vec<u64>,
+    inclusive: Vec<Duration>,
+    exclusive: Vec<Duration>,
+    lines: Vec<u64>,
+    frames: Vec<Frame>,
+    /// The opcodes executed per call stack, and those executed since it last changed.
+    stacks: HashMap<Vec<usize>, u64>,
+    pending: u64,
+}
+
+/// The measures of a function.
+#[derive(Debug, Clone)]
+pub struct FunctionProfile {
+    pub name: String,
+    pub calls: u64,
+    /// The opcodes executed in the function itself.
+    pub opcodes: u64,
+    /// The time from the calls to the returns of the function, excluding recursive calls.
+    pub inclusive: Duration,
+    /// The inclusive time minus the time spent in the functions it called.
+    pub exclusive: Duration,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct Profile {
+    /// Sorted by decreasing opcode count.
+    pub functions: Vec<FunctionProfile>,
+    /// The source lines and their opcode counts, sorted by decreasing count.
+    pub lines: Vec<(usize, u64)>,
+    /// The call stacks, outermost function first, and the opcodes executed on top of them.
+    pub stacks: Vec<(Vec<String>, u64)>,
+}
+
+impl Profiler {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    fn start(&mut self, machine: &Machine<'_>) {
+        let codes = machine.codes;
+        let count = codes.functions.len();
+        self.names = codes.functions.iter().map(|f| f.name.clone()).collect();
+        self.function_of = (0..codes.opcodes.len())
+            .map(|i| {
+                codes
+                    .functions
+                    .partition_point(|f| f.start <= i)
+                    .saturating_sub(1)
+            })
+            .collect();
+        self.line_of = (0..codes.opcodes.len())
+            .map(|i| codes.spans.get(i).map_or(0, |span| span.line))
+            .collect();
+        self.calls = vec![0; count];
+        self.opcodes = vec![0; count];
+        self.inclusive = vec![Duration::ZERO; count];
+        self.exclusive = vec![Duration::ZERO; count];
+        self.lines = vec![0; self.line_of.iter().max().map_or(0, |&max| max + 1)];
+        self.enter(self.function_of[machine.rip], Instant::now());
+    }
+
+    fn flush_stack(&mut self) {
+        if self.pending > 0 {
+            let stack = self.frames.iter().map(|frame| frame.function).collect();
+            *self.stacks.entry(stack).or_default() += self.pending;
+            self.pending = 0;
+        }
+    }
+
+    fn enter(&mut self, function: usize, now: Instant) {
+        self.flush_stack();
+        self.calls[function] += 1;
+        self.frames.push(Frame {
+            function,
+            entered: now,
+            callees: Duration::ZERO,
+        });
+    }
+
+    fn leave(&mut self, now: Instant) {
+        self.flush_stack();
+        let Some(frame) = self.frames.pop() else {
+            return;
+        };
+        let elapsed = now - frame.entered;
+        // The outermost call of a recursion accounts for the inner ones.
+        if !self.frames.iter().any(|f| f.function == frame.function) {
+            self.inclusive[frame.function] += elapsed;
+        }
+        self.exclusive[frame.function] += elapsed.saturating_sub(frame.callees);
+        if let Some(caller) = self.frames.last_mut() {
+            caller.callees += elapsed;
+        }
+    }
+
+    /// Ends the functions still running, e.g. `main`, and returns the measures.
+    pub fn finish(mut self) -> Profile {
+        let now = Instant::now();
+        while !self.frames.is_empty() {
+            self.leave(now);
+        }
+
+        let mut functions: Vec<FunctionProfile> = (self.names.iter().enumerate())
+            .map(|(i, name)| FunctionProfile {
+                name: name.clone(),
+                calls: self.calls[i],
+                opcodes: self.opcodes[i],
+                inclusive: self.inclusive[i],
+                exclusive: self.exclusive[i],
+            })
+            .filter(|f| f.calls > 0)
+            .collect();
+        functions.sort_by(|a, b| b.opcodes.cmp(&a.opcodes).then(a.name.cmp(&b.name)));
+
+        let mut lines: Vec<(usize, u64)> = (self.lines.iter().copied().enumerate())
+            .filter(|&(line, count)| line > 0 && count > 0)
+            .collect();
+        lines.sort_by(|a, b| b.1.cmp(&a.1).then(a.0.cmp(&b.0)));
+
+        let mut stacks: Vec<(Vec<String>, u64)> = (self.stacks.into_iter())
+            .map(|(stack, count)| {
+                let names = stack.iter().map(|&f| self.names[f].clone()).collect();
+                (names, count)
+            })
+            .collect();
+        stacks.sort();
+
+        Profile {
+            functions,
+            lines,
+            stacks,
+        }
+    }
+}
+
+impl Observer for Profiler {
+    fn before(&mut self, machine: &Machine<'_>) -> ControlFlow<()> {
+        if self.function_of.is_empty() {
+            self.start(machine);
+        }
+        let Some(&function) = self.function_of.get(machine.rip) else {
+            return ControlFlow::Continue(());
+        };
+        self.opcodes[function] += 1;
+        self.lines[self.line_of[machine.rip]] += 1;
+        self.pending += 1;
+        match machine.opcode() {
+            Some(OpCode::FunctionCall(target)) => {
+                if let Some(&callee) = self.function_of.get(target) {
+                    self.enter(callee, Instant::now());
+                }
+            }
+            Some(OpCode::TailCall(_, target)) => {
+                let now = Instant::now();
+                self.leave(now);
+                if let Some(&callee) = self.function_of.get(target) {
+                    self.enter(callee, now);
+                }
+            }
+            Some(OpCode::Ret) => self.leave(Instant::now()),
+            _ => {}
+        }
+        ControlFlow::Continue(())
+    }
+}
+
+impl Profile {
+    /// Writes the functions and the 20 busiest lines of `source`, read at `path`, as tables.
+    pub fn write_table(&self, out: &mut impl Write, source: &str, path: &str) -> io::Result<()> {
+        let total: u64 = self.functions.iter().map(|f| f.opcodes).sum();
+        let percent = |count: u64| 100.0 * count as f64 / total.max(1) as f64;
+
+        writeln!(
+            out,
+            "{:<20} {:>10} {:>14} {:>7} {:>12} {:>12}",
+            "function", "calls", "opcodes", "%", "inclusive", "exclusive"
+        )?;
+        for f in &self.functions {
+            writeln!(
+                out,
+                "{:<20} {:>10} {:>14} {:>6.2}% {:>12.3?} {:>12.3?}",
+                f.name,
+                f.calls,
+                f.opcodes,
+                percent(f.opcodes),
+                f.inclusive,
+                f.exclusive
+            )?;
+        }
+
+        let lines = &self.lines[..self.lines.len().min(20)];
+        let locations: Vec<String> = (lines.iter())
+            .map(|(line, _)| format!("{}:{}", path, line))
+            .collect();
+        let width = locations.iter().map(String::len).max().unwrap_or(0).max(20);
+        writeln!(out)?;
+        writeln!(
+            out,
+            "{:<width$} {:>14} {:>7}  source",
+            "line", "opcodes", "%"
+        )?;
+        for (&(line, count), location) in lines.iter().zip(&locations) {
+            let text = source.lines().nth(line - 1).unwrap_or("").trim();
+            writeln!(
+                out,
+                "{:<width$} {:>14} {:>6.2}%  {}",
+                location,
+                count,
+                percent(count),
+                text
+            )?;
+        }
+        Ok(())
+    }
+
+    /// Writes the call stacks in the folded format of flamegraph tools: a line per stack with
+    /// the functions separated by `;` and the opcodes executed on top of it.
+    pub fn write_folded(&self, out: &mut impl Write) -> io::Result<()> {
+        for (stack, count) in &self.stacks {
+            writeln!(out, "{} {}", stack.join(";"), count)?;
+        }
+        Ok(())
+    }
+}
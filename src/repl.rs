@@ -0,0 +1,262 @@
+//! An interactive session that compiles and runs one input at a time.
+//!
+//! Function definitions are kept for the following inputs. Statements run in a scratch `main`
+//! whose local variables persist from one input to the next, and the value of a bare expression
+//! statement is returned to be printed.
+
+use std::fmt;
+
+use crate::Vm;
+use crate::code_generator;
+use crate::error::{CompileError, RuntimeError};
+use crate::executor::Value;
+use crate::io::Io;
+use crate::lexer::{self, Span, Token, TokenKind};
+use crate::parser::{
+    self, Expression, ExpressionKind, FunctionData, Inlining, OperandType, Program, Statement,
+    StatementKind,
+};
+
+/// The number of local variables of the scratch `main`, `get.0` to `get.255`.
+pub const SCRATCH_SIZE: usize = 256;
+
+/// The hidden local variable of the scratch `main` receiving the value of a bare expression.
+///
+/// It comes after the declared ones, like the variables of inlined functions, so that computed
+/// indices cannot reach it.
+const RESULT_INDEX: usize = SCRATCH_SIZE;
+
+/// An error in an input, whose spans refer to [`Session::source`].
+#[derive(Debug)]
+pub enum ReplError {
+    Compile(Vec<CompileError>),
+    Runtime(RuntimeError),
+}
+
+impl ReplError {
+    /// Renders the error like the `run` command does.
+    pub fn render(&self, source: &str, path: &str) -> String {
+        match self {
+            ReplError::Compile(errors) => {
+                let rendered: Vec<String> = errors.iter().map(|e| e.render(source, path)).collect();
+                rendered.join("\n")
+            }
+            ReplError::Runtime(e) => e.render(source, path),
+        }
+    }
+}
+
+/// The value of a bare expression, typed after its outermost operator.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Evaluated {
+    Int(i64),
+    Float(f64),
+}
+
+impl fmt::Display for Evaluated {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Evaluated::Int(val) => write!(f, "{}", val),
+            Evaluated::Float(val) => write!(f, "{:?}", val),
+        }
+    }
+}
+
+/// Whether the expression evaluates to a float. `get` and calls are assumed to be integers.
+fn is_float(expr: &Expression) -> bool {
+    matches!(
+        expr.kind,
+        ExpressionKind::Float(_)
+            | ExpressionKind::Itof(_)
+            | ExpressionKind::Neg(OperandType::Float, _)
+            | ExpressionKind::Add(OperandType::Float, ..)
+            | ExpressionKind::Sub(OperandType::Float, ..)
+            | ExpressionKind::Mul(OperandType::Float, ..)
+            | ExpressionKind::Div(OperandType::Float, ..)
+            | ExpressionKind::Rem(OperandType::Float, ..)
+    )
+}
+
+pub struct Session {
+    functions: Vec<FunctionData>,
+    locals: Vec<Value>,
+    source: String,
+}
+
+impl Default for Session {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Session {
+    pub fn new() -> Self {
+        Self {
+            functions: Vec::new(),
+            locals: vec![Value::from_i64(core::num::Wrapping(0)); SCRATCH_SIZE + 1],
+            source: String::new(),
+        }
+    }
+
+    /// All the inputs so far, one after another. The spans of the errors refer to it.
+    pub fn source(&self) -> &str {
+        &self.source
+    }
+
+    /// Whether the input has unclosed braces and continues on the next line.
+    pub fn is_incomplete(input: &str) -> bool {
+        let Ok(tokens) = lexer::parse(input) else {
+            // Lexical errors are reported once the input is evaluated.
+            return false;
+        };
+        let depth: isize = tokens
+            .iter()
+            .map(|token| match token.kind {
+                TokenKind::OpeningBrace => 1,
+                TokenKind::ClosingBrace => -1,
+                _ => 0,
+            })
+            .sum();
+        depth > 0
+    }
+
+    /// Compiles and runs an input: either function definitions, which replace the previous
+    /// definitions of the same names, or statements. A missing `;` after the last statement is
+    /// inserted.
+    ///
+    /// Returns the value of the last bare expression statement, if any.
+    pub fn eval(&mut self, input: &str, io: &mut impl Io) -> Result<Option<Evaluated>, ReplError> {
+        let offset = self.source.len();
+        let lines = self.source.matches('\n').count();
+        self.source.push_str(input);
+        if !input.ends_with('\n') {
+            self.source.push('\n');
+        }
+        // Makes the spans relative to `self.source`.
+        let shift = |span: Span| Span {
+            start: span.start + offset,
+            end: span.end + offset,
+            line: span.line + lines,
+            ..span
+        };
+
+        let mut tokens = lexer::parse(input).map_err(|errors| {
+            ReplError::Compile(
+                errors
+                    .into_iter()
+                    .map(|e| CompileError {
+                        span: e.span.map(shift),
+                        ..e
+                    })
+                    .collect(),
+            )
+        })?;
+        for token in &mut tokens {
+            token.span = shift(token.span);
+        }
+
+        if matches!(
+            tokens[0].kind,
+            TokenKind::FuncKeyword | TokenKind::InlineKeyword | TokenKind::NoinlineKeyword
+        ) {
+            self.define(tokens).map_err(ReplError::Compile)?;
+            return Ok(None);
+        }
+        if let [.., last, eof] = &tokens[..]
+            && !matches!(last.kind, TokenKind::Semicolon | TokenKind::ClosingBrace)
+        {
+            let semicolon = Token {
+                kind: TokenKind::Semicolon,
+                span: Span {
+                    start: last.span.end,
+                    ..eof.span
+                },
+            };
+            tokens.insert(tokens.len() - 1, semicolon);
+        }
+        self.execute(tokens, io)
+    }
+
+    fn define(&mut self, tokens: Vec<Token>) -> Result<(), Vec<CompileError>> {
+        let span = tokens[0].span;
+        let defined = parser::parse_functions(tokens)?;
+        if let Some(main) = defined.iter().find(|f| f.name == "main") {
+            return Err(vec![CompileError::new(
+                "the function main() is reserved for the statements entered in the REPL",
+                main.span,
+            )]);
+        }
+        let mut functions = self.functions.clone();
+        for function in defined {
+            functions.retain(|f| f.name != function.name);
+            functions.push(function);
+        }
+        // Rejects the definitions unless the whole program still compiles.
+        self.compile(functions.clone(), Vec::new(), span)?;
+        self.functions = functions;
+        Ok(())
+    }
+
+    fn execute(
+        &mut self,
+        tokens: Vec<Token>,
+        io: &mut impl Io,
+    ) -> Result<Option<Evaluated>, ReplError> {
+        let span = tokens[0].span;
+        let mut stmts =
+            parser::parse_statements(tokens, SCRATCH_SIZE).map_err(ReplError::Compile)?;
+        let mut result = None;
+        for stmt in &mut stmts {
+            if let StatementKind::Expr { expr } = &mut stmt.kind {
+                result = Some(is_float(expr));
+                let placeholder = Expression {
+                    kind: ExpressionKind::Int(0),
+                    span,
+                };
+                let val = core::mem::replace(expr, Box::new(placeholder));
+                stmt.kind = StatementKind::SetWithLiteralIndex {
+                    index: RESULT_INDEX,
+                    val,
+                };
+            }
+        }
+
+        let codes = self
+            .compile(self.functions.clone(), stmts, span)
+            .map_err(ReplError::Compile)?;
+        Vm::with_io(codes, io)
+            .run_with_locals(&mut self.locals)
+            .map_err(ReplError::Runtime)?;
+
+        let value = self.locals[RESULT_INDEX];
+        Ok(result.map(|is_float| {
+            if is_float {
+                Evaluated::Float(value.as_f64())
+            } else {
+                Evaluated::Int(value.as_i64().0)
+            }
+        }))
+    }
+
+    /// Generates the code of the functions with a scratch `main` running `stmts`.
+    fn compile(
+        &self,
+        mut functions: Vec<FunctionData>,
+        stmts: Vec<Statement>,
+        span: Span,
+    ) -> Result<code_generator::Codes, Vec<CompileError>> {
+        functions.push(FunctionData {
+            body: Statement {
+                kind: StatementKind::Block { stmts },
+                span,
+            },
+            name: "main".to_owned(),
+            params_size: 0,
+            alloc_size: SCRATCH_SIZE,
+            inline_alloc_size: 1,
+            inlining: Inlining::Auto,
+            span,
+        });
+        code_generator::generate(Program { funcs: functions })
+    }
+}
@@ -0,0 +1,92 @@
+//! Logging of every executed opcode.
+
+use std::io::{self, Write};
+use std::ops::{ControlFlow, Range};
+
+use crate::executor::{Machine, Observer, Register};
+
+/// An [`Observer`] writing a line per executed opcode: its index, the function containing it,
+/// the opcode, and the registers and the top of the stack before it is executed, e.g.
+///
+/// ```text
+///     42 fibo: AddInt(R1, R2, R3)  R1=1(5e-324) R2=2(1e-323) R3=0(0.0) RAX=0(0.0) top=3(1.5e-323)
+/// ```
+pub struct Tracer<W> {
+    out: W,
+    function: Option<String>,
+    opcodes: Option<Range<usize>>,
+    /// The first write error, after which nothing more is written.
+    error: Option<io::Error>,
+}
+
+impl<W: Write> Tracer<W> {
+    pub fn new(out: W) -> Self {
+        Self {
+            out,
+            function: None,
+            opcodes: None,
+            error: None,
+        }
+    }
+
+    /// Only logs the opcodes of the function `name`.
+    pub fn function(self, name: impl Into<String>) -> Self {
+        Self {
+            function: Some(name.into()),
+            ..self
+        }
+    }
+
+    /// Only logs the opcodes whose index is in `range`.
+    pub fn opcodes(self, range: Range<usize>) -> Self {
+        Self {
+            opcodes: Some(range),
+            ..self
+        }
+    }
+
+    /// Flushes the log, reporting the first write error if any.
+    pub fn finish(mut self) -> io::Result<W> {
+        match self.error {
+            Some(e) => Err(e),
+            None => self.out.flush().map(|()| self.out),
+        }
+    }
+
+    fn write(&mut self, machine: &Machine<'_>) -> io::Result<()> {
+        let Some(opcode) = machine.opcode() else {
+            return Ok(());
+        };
+        let function = machine.function().map_or("?", |f| f.name.as_str());
+        write!(self.out, "{:>6} {}: {:?} ", machine.rip, function, opcode)?;
+        for (name, register) in [
+            ("R1", Register::R1),
+            ("R2", Register::R2),
+            ("R3", Register::R3),
+            ("RAX", Register::RAX),
+        ] {
+            write!(self.out, " {}={:?}", name, machine.register(register))?;
+        }
+        match machine.stack.last() {
+            Some(top) => writeln!(self.out, " top={:?}", top),
+            None => writeln!(self.out, " top=-"),
+        }
+    }
+}
+
+impl<W: Write> Observer for Tracer<W> {
+    fn before(&mut self, machine: &Machine<'_>) -> ControlFlow<()> {
+        let selected = self
+            .opcodes
+            .as_ref()
+            .is_none_or(|range| range.contains(&machine.rip))
+            && self
+                .function
+                .as_ref()
+                .is_none_or(|name| machine.function().is_some_and(|f| f.name == *name));
+        if selected && self.error.is_none() {
+            self.error = self.write(machine).err();
+        }
+        ControlFlow::Continue(())
+    }
+}
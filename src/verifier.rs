@@ -0,0 +1,352 @@
+//! Static checks of [`Codes`], so that executing them cannot fault on the stack or jump out of
+//! the program, even with the `unchecked` feature.
+
+use crate::code_generator::{Codes, FunctionInfo, OpCode};
+use crate::error::VerifyError;
+use crate::executor::Register;
+
+/// What is known before an opcode, whichever path led to it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct State {
+    /// The number of values pushed since the function was called, its return address excluded.
+    depth: usize,
+    /// The depth just after `PushRbpAndMovEspToEbp`, where the local variables start, or `None`
+    /// outside of the frame.
+    rbp: Option<usize>,
+    /// A bit per register written on every path.
+    written: u8,
+}
+
+fn bit(register: Register) -> u8 {
+    1 << register as u8
+}
+
+/// Returns the registers read and the register written by `opcode`.
+fn registers(opcode: OpCode) -> (Vec<Register>, Option<Register>) {
+    match opcode {
+        OpCode::Push(r1)
+        | OpCode::SetVar(_, r1)
+        | OpCode::JmpAddrIfZero(r1, _)
+        | OpCode::JmpAddrIfNotEqInt(r1, ..)
+        | OpCode::JmpAddrIfNotNeInt(r1, ..)
+        | OpCode::JmpAddrIfNotLtInt(r1, ..)
+        | OpCode::JmpAddrIfNotLeInt(r1, ..)
+        | OpCode::JmpAddrIfNotGtInt(r1, ..)
+        | OpCode::JmpAddrIfNotGeInt(r1, ..)
+        | OpCode::PutByte(r1) => (vec![r1], None),
+        OpCode::LoadInt(_, r1)
+        | OpCode::LoadFloat(_, r1)
+        | OpCode::Pop(r1)
+        | OpCode::GetVar(_, r1)
+        | OpCode::GetParam(_, r1)
+        | OpCode::ReadByteFromStdin(r1) => (vec![], Some(r1)),
+        OpCode::Mov(r1, r2)
+        | OpCode::LogiNot(r1, r2)
+        | OpCode::BitNot(r1, r2)
+        | OpCode::NegInt(r1, r2)
+        | OpCode::NegFloat(r1, r2)
+        | OpCode::FloatToInt(r1, r2)
+        | OpCode::IntToFloat(r1, r2)
+        | OpCode::GetVarComputed(r1, _, r2)
+        | OpCode::AddIntImm(r1, _, r2)
+        | OpCode::MulIntImm(r1, _, r2)
+        | OpCode::DivIntImm(r1, _, r2)
+        | OpCode::RemIntImm(r1, _, r2) => (vec![r1], Some(r2)),
+        OpCode::SetVarComputed(r1, _, r2) | OpCode::PutBytes(r1, _, r2) => (vec![r1, r2], None),
+        OpCode::AddFloat(r1, r2, r3)
+        | OpCode::AddInt(r1, r2, r3)
+        | OpCode::SubFloat(r1, r2, r3)
+        | OpCode::SubInt(r1, r2, r3)
+        | OpCode::MulFloat(r1, r2, r3)
+        | OpCode::MulInt(r1, r2, r3)
+        | OpCode::DivFloat(r1, r2, r3)
+        | OpCode::DivInt(r1, r2, r3)
+        | OpCode::RemFloat(r1, r2, r3)
+        | OpCode::RemInt(r1, r2, r3)
+        | OpCode::NeInt(r1, r2, r3)
+        | OpCode::EqInt(r1, r2, r3)
+        | OpCode::NeFloat(r1, r2, r3)
+        | OpCode::EqFloat(r1, r2, r3)
+        | OpCode::And(r1, r2, r3)
+        | OpCode::Or(r1, r2, r3)
+        | OpCode::Xor(r1, r2, r3)
+        | OpCode::Shr(r1, r2, r3)
+        | OpCode::ShrUnsigned(r1, r2, r3)
+        | OpCode::Shl(r1, r2, r3)
+        | OpCode::LtInt(r1, r2, r3)
+        | OpCode::LtFloat(r1, r2, r3)
+        | OpCode::LeInt(r1, r2, r3)
+        | OpCode::LeFloat(r1, r2, r3) => (vec![r1, r2], Some(r3)),
+        _ => (vec![], None),
+    }
+}
+
+/// Checks every function of `codes` along all the paths from its start:
+///
+/// - the stack depth is the same on all the paths reaching an opcode,
+/// - no pop or `Free` goes below the local variables, and every function returns with its
+///   frame and arguments as it found them,
+/// - variables and parameters are accessed in the frame of the function,
+/// - jumps stay in their function, calls target the start of a function with enough values
+///   pushed for its parameters, and no path runs past the end of its function without a `Ret`
+///   or an `Exit`,
+/// - no register is read before it is written.
+///
+/// Unreachable opcodes are not checked. `RAX` holds `0` or a return value when a function starts
+/// and after each call, so it counts as written.
+pub fn verify(codes: &Codes) -> Result<(), VerifyError> {
+    let Some(entry) = (codes.functions.iter()).find(|f| f.start == codes.entry_point) else {
+        return Err(VerifyError::new(
+            codes,
+            codes.entry_point,
+            "the entry point is not the start of a function",
+        ));
+    };
+    if entry.params_size != 0 {
+        return Err(VerifyError::new(
+            codes,
+            entry.start,
+            "the entry function takes parameters",
+        ));
+    }
+    for function in &codes.functions {
+        verify_function(codes, function, function.start == codes.entry_point)?;
+    }
+    Ok(())
+}
+
+fn verify_function(codes: &Codes, f: &FunctionInfo, is_entry: bool) -> Result<(), VerifyError> {
+    if f.start == f.end {
+        return Err(VerifyError::new(codes, f.start, "the function is empty"));
+    }
+    let mut states: Vec<Option<State>> = vec![None; f.end - f.start];
+    states[0] = Some(State {
+        depth: 0,
+        rbp: None,
+        written: bit(Register::RAX),
+    });
+    let mut pending = vec![f.start];
+
+    while let Some(index) = pending.pop() {
+        let state = states[index - f.start].unwrap();
+        let error = |message: String| VerifyError::new(codes, index, message);
+        let opcode = codes.opcodes[index];
+
+        let (reads, write) = registers(opcode);
+        if let Some(r) = reads.iter().find(|&&r| state.written & bit(r) == 0) {
+            return Err(error(format!("{:?} is read before it is written", r)));
+        }
+        let mut next = state;
+        if let Some(r) = write {
+            next.written |= bit(r);
+        }
+
+        // The local variables are below the values pushed since the `Alloc`.
+        let floor = state.rbp.map_or(0, |rbp| rbp + f.alloc_size);
+        let pop = |next: &mut State, count: usize| {
+            if state.depth < floor + count {
+                return Err(error(format!(
+                    "popping {} value(s) underflows the frame, which holds {}",
+                    count,
+                    state.depth.saturating_sub(floor)
+                )));
+            }
+            next.depth -= count;
+            Ok(())
+        };
+        let locals = || match state.rbp {
+            None => Err(error(
+                "a variable is accessed outside of the frame".to_owned(),
+            )),
+            Some(rbp) if state.depth < rbp + f.alloc_size => Err(error(
+                "a variable is accessed before the variables are allocated".to_owned(),
+            )),
+            Some(_) => Ok(()),
+        };
+
+        let mut jump = None;
+        let mut falls_through = true;
+        match opcode {
+            OpCode::Push(_) => next.depth += 1,
+            OpCode::Pop(_) => pop(&mut next, 1)?,
+            OpCode::Alloc(size) => next.depth += size,
+            OpCode::Free(size) => pop(&mut next, size)?,
+            OpCode::GetVar(i, _) | OpCode::SetVar(i, _) | OpCode::AddVarInt(i, _) => {
+                locals()?;
+                if i >= f.alloc_size {
+                    return Err(error(format!(
+                        "the variable {} is out of range for `alloc {}`",
+                        i, f.alloc_size
+                    )));
+                }
+            }
+            OpCode::GetVarComputed(_, alloc_size, _)
+            | OpCode::SetVarComputed(_, alloc_size, _)
+            | OpCode::PutBytes(_, alloc_size, _) => {
+                locals()?;
+                if alloc_size > f.alloc_size {
+                    return Err(error(format!(
+                        "`alloc {}` is larger than the `alloc {}` of {}()",
+                        alloc_size, f.alloc_size, f.name
+                    )));
+                }
+            }
+            // The parameters are below the return address and the saved `rbp`, so their indices
+            // start at 1.
+            OpCode::GetParam(i, _) => {
+                if state.rbp.is_none() {
+                    return Err(error(
+                        "a parameter is accessed outside of the frame".to_owned(),
+                    ));
+                }
+                if i == 0 || i > f.params_size {
+                    return Err(error(format!(
+                        "the parameter index {} is out of range for `params {}`",
+                        i, f.params_size
+                    )));
+                }
+            }
+            OpCode::PushRbpAndMovEspToEbp => {
+                if state.rbp.is_some() {
+                    return Err(error("the frame is already set up".to_owned()));
+                }
+                next.depth += 1;
+                next.rbp = Some(next.depth);
+            }
+            OpCode::MovEbpToEspAndPopRbp => {
+                let Some(rbp) = state.rbp else {
+                    return Err(error("there is no frame to tear down".to_owned()));
+                };
+                next.depth = rbp - 1;
+                next.rbp = None;
+            }
+            OpCode::FunctionCall(target) => {
+                let Some(callee) = codes.functions.iter().find(|f| f.start == target) else {
+                    return Err(error(format!(
+                        "the call target {} is not the start of a function",
+                        target
+                    )));
+                };
+                if state.depth < floor + callee.params_size {
+                    return Err(error(format!(
+                        "{}() takes {} argument(s), but {} value(s) are pushed",
+                        callee.name,
+                        callee.params_size,
+                        state.depth.saturating_sub(floor)
+                    )));
+                }
+                next.written |= bit(Register::RAX);
+            }
+            // The frame must hold the arguments alone above the variables, as it is replaced by the
+            // callee's.
+            OpCode::TailCall(params_size, target) => {
+                let Some(callee) = codes.functions.iter().find(|f| f.start == target) else {
+                    return Err(error(format!(
+                        "the call target {} is not the start of a function",
+                        target
+                    )));
+                };
+                if is_entry {
+                    return Err(error(
+                        "the entry function has no caller to return to".to_owned(),
+                    ));
+                }
+                if callee.params_size != params_size {
+                    return Err(error(format!(
+                        "{}() takes {} argument(s), but the tail call passes {}",
+                        callee.name, callee.params_size, params_size
+                    )));
+                }
+                if f.params_size != params_size {
+                    return Err(error(format!(
+                        "the tail call passes {} argument(s) in place of the {} parameter(s) of {}()",
+                        params_size, f.params_size, f.name
+                    )));
+                }
+                if state.rbp.is_none() || state.depth != floor + params_size {
+                    return Err(error(format!(
+                        "{}() takes {} argument(s), but {} value(s) are pushed",
+                        callee.name,
+                        callee.params_size,
+                        state.depth.saturating_sub(floor)
+                    )));
+                }
+                falls_through = false;
+            }
+            OpCode::JmpAddr(target) => {
+                jump = Some(target);
+                falls_through = false;
+            }
+            OpCode::JmpAddrIfZero(_, target)
+            | OpCode::JmpAddrIfNotEqInt(_, _, target)
+            | OpCode::JmpAddrIfNotNeInt(_, _, target)
+            | OpCode::JmpAddrIfNotLtInt(_, _, target)
+            | OpCode::JmpAddrIfNotLeInt(_, _, target)
+            | OpCode::JmpAddrIfNotGtInt(_, _, target)
+            | OpCode::JmpAddrIfNotGeInt(_, _, target) => jump = Some(target),
+            OpCode::Ret => {
+                if is_entry {
+                    return Err(error(
+                        "the entry function has no caller to return to".to_owned(),
+                    ));
+                }
+                if state.rbp.is_some() || state.depth != 0 {
+                    return Err(error(format!(
+                        "returning with {} value(s) left on the stack",
+                        state.depth
+                    )));
+                }
+                falls_through = false;
+            }
+            OpCode::Exit => falls_through = false,
+            OpCode::__JL_0515__(_)
+            | OpCode::__JLIZ_2505__(..)
+            | OpCode::__JLNC_3170__(..)
+            | OpCode::__FNCALL_2255__(_)
+            | OpCode::__TLCALL_4096__(..) => {
+                return Err(error("the opcode refers to an unresolved label".to_owned()));
+            }
+            // The executor faults on it like on the unresolved opcodes.
+            OpCode::Nop => {
+                return Err(error(
+                    "`Nop` is only a placeholder during code generation".to_owned(),
+                ));
+            }
+            _ => {}
+        }
+
+        let successors = jump.into_iter().chain(falls_through.then_some(index + 1));
+        for successor in successors {
+            if !(f.start..f.end).contains(&successor) {
+                return Err(error(if successor == index + 1 {
+                    format!("the execution runs past the end of {}()", f.name)
+                } else {
+                    format!("the jump target {} is outside of {}()", successor, f.name)
+                }));
+            }
+            match &mut states[successor - f.start] {
+                Some(known) if (known.depth, known.rbp) != (next.depth, next.rbp) => {
+                    return Err(VerifyError::new(
+                        codes,
+                        successor,
+                        format!(
+                            "the stack depth is {} on a path from opcode {} but {} on another",
+                            next.depth, index, known.depth
+                        ),
+                    ));
+                }
+                Some(known) => {
+                    if known.written & !next.written != 0 {
+                        known.written &= next.written;
+                        pending.push(successor);
+                    }
+                }
+                slot @ None => {
+                    *slot = Some(next);
+                    pending.push(successor);
+                }
+            }
+        }
+    }
+    Ok(())
+}
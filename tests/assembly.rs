@@ -0,0 +1,96 @@
+use abyssix::Vm;
+use abyssix::assembly::{assemble, disassemble};
+use abyssix::io::MemoryIo;
+
+#[test]
+fn round_trips_the_fixtures() {
+    let dir = std::path::Path::new(env!("CARGO_MANIFEST_DIR")).join("tests");
+    for entry in std::fs::read_dir(dir).unwrap() {
+        let path = entry.unwrap().path();
+        if path.extension().is_none_or(|ext| ext != "abys") {
+            continue;
+        }
+        let Ok(codes) = abyssix::compile(&std::fs::read_to_string(&path).unwrap()) else {
+            continue;
+        };
+        let text = disassemble(&codes);
+        let assembled = assemble(&text).unwrap_or_else(|e| panic!("{}: {:?}", path.display(), e));
+        assert_eq!(assembled.entry_point, codes.entry_point);
+        assert_eq!(
+            format!("{:?}", assembled.opcodes),
+            format!("{:?}", codes.opcodes),
+            "{}",
+            path.display()
+        );
+        assert_eq!(
+            format!("{:?}", assembled.functions),
+            format!("{:?}", codes.functions)
+        );
+    }
+}
+
+#[test]
+fn runs_hand_written_code() {
+    let codes = assemble(
+        "\
+.func main params 0 alloc 0
+    PushRbpAndMovEspToEbp
+    LoadInt(3, R1)
+loop:
+    JmpAddrIfZero(R1, done)
+    Push(R1)
+    FunctionCall(star)
+    Pop(R1)
+    LoadInt(1, R2)
+    SubInt(R1, R2, R1)
+    JmpAddr(loop)
+done:
+    Exit
+
+.func star params 0 alloc 0  ; prints `*`
+    LoadInt(42, R3)
+    PutByte(R3)
+    Ret
+",
+    )
+    .unwrap();
+    let mut vm = Vm::with_io(codes, MemoryIo::memory(b""));
+    vm.run().unwrap();
+    assert_eq!(vm.into_io().output, b"***");
+}
+
+#[test]
+fn reports_errors_with_their_lines() {
+    let errors =
+        assemble(".entry main\n.func main params 0 alloc 0\n  Jmp(L0)\n  PutByte(R1, R2)\n")
+            .unwrap_err();
+    let messages: Vec<String> = errors.iter().map(ToString::to_string).collect();
+    assert_eq!(
+        messages,
+        [
+            "3:3: unknown opcode `Jmp`",
+            "4:3: `PutByte` takes 1 operand(s), but 2 given",
+        ]
+    );
+}
+
+#[test]
+fn verifies_hand_written_code_before_running_it() {
+    let path = std::path::Path::new(env!("CARGO_TARGET_TMPDIR")).join("underflow.abysm");
+    std::fs::write(
+        &path,
+        ".func main params 0 alloc 0\n    PushRbpAndMovEspToEbp\n    Pop(R1)\n    Exit\n",
+    )
+    .unwrap();
+    let output = std::process::Command::new(env!("CARGO_BIN_EXE_abyssix"))
+        .arg("run")
+        .arg(&path)
+        .output()
+        .unwrap();
+    assert_eq!(output.status.code(), Some(65));
+    assert!(
+        String::from_utf8(output.stderr)
+            .unwrap()
+            .starts_with("error: popping 1 value(s) underflows the frame")
+    );
+}
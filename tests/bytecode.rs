@@ -0,0 +1,205 @@
+use abyssix::assembly::assemble;
+use abyssix::bytecode::{decode, encode};
+use abyssix::code_generator::{Codes, OpCode};
+
+const SOURCE: &str = "\
+func twice {
+  params 1;
+  alloc 1;
+  set.0 = param.0 int.* 2;
+  return get.0;
+}
+
+func main {
+  params 0;
+  alloc 2;
+  set.0 = 0;
+  while get.0 int.< 3: {
+    set[1] = twice(get.0) int.+ 1;
+    putc 48 int.+ get.1;
+    set.0 = get.0 int.+ 1;
+  }
+}
+";
+
+fn describe(codes: &Codes) -> String {
+    format!(
+        "{} {:?} {:?} {:?}",
+        codes.entry_point, codes.opcodes, codes.functions, codes.spans
+    )
+}
+
+#[test]
+fn round_trips() {
+    let codes = abyssix::compile(SOURCE).unwrap();
+    let decoded = decode(&encode(&codes, true)).unwrap();
+    assert_eq!(describe(&decoded), describe(&codes));
+
+    let stripped = decode(&encode(&codes, false)).unwrap();
+    assert!(stripped.spans.is_empty());
+    assert_eq!(
+        format!("{:?}", stripped.opcodes),
+        format!("{:?}", codes.opcodes)
+    );
+}
+
+#[test]
+fn round_trips_every_opcode() {
+    let codes = assemble(
+        "\
+.func main params 0 alloc 2
+    PushRbpAndMovEspToEbp
+    Alloc(2)
+    LoadInt(-9223372036854775808, R2)
+    LoadFloat(-0.5, R3)
+    Mov(R2, R1)
+    Push(R1)
+    Pop(RAX)
+    LogiNot(R1, R2)
+    BitNot(R1, R2)
+    NegInt(R1, R2)
+    NegFloat(R1, R2)
+    AddFloat(R1, R2, R3)
+    AddInt(R1, R2, R3)
+    SubFloat(R1, R2, R3)
+    SubInt(R1, R2, R3)
+    MulFloat(R1, R2, R3)
+    MulInt(R1, R2, R3)
+    DivFloat(R1, R2, R3)
+    DivInt(R1, R2, R3)
+    RemFloat(R1, R2, R3)
+    RemInt(R1, R2, R3)
+    NeInt(R1, R2, R3)
+    EqInt(R1, R2, R3)
+    NeFloat(R1, R2, R3)
+    EqFloat(R1, R2, R3)
+    And(R1, R2, R3)
+    Or(R1, R2, R3)
+    Xor(R1, R2, R3)
+    Shr(R1, R2, R3)
+    ShrUnsigned(R1, R2, R3)
+    Shl(R1, R2, R3)
+    LtInt(R1, R2, R3)
+    LtFloat(R1, R2, R3)
+    LeInt(R1, R2, R3)
+    LeFloat(R1, R2, R3)
+    SetVar(1, R1)
+    SetVarComputed(R1, 2, R2)
+    GetVar(0, R1)
+    GetVarComputed(R1, 2, R2)
+    FloatToInt(R1, R2)
+    IntToFloat(R1, R2)
+    AddIntImm(R1, -1, R2)
+    MulIntImm(R1, 3, R2)
+    DivIntImm(R1, 10, R2)
+    RemIntImm(R1, 7, R2)
+    AddVarInt(1, -9223372036854775808)
+    JmpAddrIfNotEqInt(R1, 1, end)
+    JmpAddrIfNotNeInt(R1, 2, end)
+    JmpAddrIfNotLtInt(R1, 3, end)
+    JmpAddrIfNotLeInt(R1, 4, end)
+    JmpAddrIfNotGtInt(R1, 5, end)
+    JmpAddrIfNotGeInt(R1, 6, end)
+    JmpAddrIfZero(R1, end)
+    Push(R1)
+    FunctionCall(f)
+    Free(1)
+    PutByte(R1)
+    PutBytes(R1, 2, R2)
+    ReadByteFromStdin(R1)
+    JmpAddr(end)
+end:
+    Exit
+
+.func f params 1 alloc 0
+    PushRbpAndMovEspToEbp
+    GetParam(1, R1)
+    JmpAddrIfZero(R1, done)
+    Push(R1)
+    TailCall(1, f)
+done:
+    MovEbpToEspAndPopRbp
+    Ret
+",
+    )
+    .unwrap();
+    let decoded = decode(&encode(&codes, true)).unwrap();
+    assert_eq!(describe(&decoded), describe(&codes));
+}
+
+#[test]
+fn rejects_corrupted_files() {
+    let codes = abyssix::compile(SOURCE).unwrap();
+    let bytes = encode(&codes, true);
+
+    for len in 0..bytes.len() {
+        assert!(decode(&bytes[..len]).is_err(), "truncated to {} bytes", len);
+    }
+    // Whatever a flipped byte turns into, it must not be a jump out of the program.
+    for i in 0..bytes.len() {
+        let mut corrupted = bytes.clone();
+        corrupted[i] ^= 0x80;
+        if let Ok(codes) = decode(&corrupted) {
+            for opcode in &codes.opcodes {
+                if let OpCode::JmpAddr(target)
+                | OpCode::JmpAddrIfZero(_, target)
+                | OpCode::FunctionCall(target) = *opcode
+                {
+                    assert!(target < codes.opcodes.len());
+                }
+            }
+        }
+    }
+
+    let mut out_of_range = codes.clone();
+    let jump = (out_of_range.opcodes.iter_mut())
+        .find(|opcode| matches!(opcode, OpCode::JmpAddr(_)))
+        .unwrap();
+    *jump = OpCode::JmpAddr(1000);
+    let error = decode(&encode(&out_of_range, true)).unwrap_err();
+    assert!(
+        error
+            .message
+            .starts_with("the jump target 1000 is outside of main()"),
+        "{}",
+        error
+    );
+
+    let mut future = bytes.clone();
+    future[4] = 4;
+    assert_eq!(
+        decode(&future).unwrap_err().message,
+        "unsupported bytecode version 4 (expected 3)"
+    );
+    assert_eq!(
+        decode(b"func main {}").unwrap_err().message,
+        "not an abyssix bytecode file"
+    );
+}
+
+#[test]
+fn rejects_corrupted_spans() {
+    let mut codes = abyssix::compile(SOURCE).unwrap();
+    codes.spans[0].start = 5;
+    codes.spans[0].end = 0;
+    let error = decode(&encode(&codes, true)).unwrap_err();
+    assert!(
+        error.message.starts_with("a span ends before it starts"),
+        "{}",
+        error
+    );
+
+    // Errors are shown without an excerpt where the span doesn't fit the source.
+    for (start, end, source) in [(5, 0, ""), (9, 12, "putc 1;"), (1, 2, "é")] {
+        let span = abyssix::lexer::Span {
+            start,
+            end,
+            line: 3,
+            column: 4,
+        };
+        assert_eq!(
+            abyssix::CompileError::new("failed", span).render(source, "y.abys"),
+            "error: failed\n --> y.abys:3:4\n"
+        );
+    }
+}
@@ -0,0 +1,125 @@
+use abyssix::Vm;
+use abyssix::code_generator::OpCode;
+use abyssix::io::MemoryIo;
+
+fn run(source: &str, input: &[u8]) -> String {
+    let mut vm = Vm::with_io(abyssix::compile(source).unwrap(), MemoryIo::memory(input));
+    vm.run().unwrap();
+    String::from_utf8(vm.into_io().output).unwrap()
+}
+
+#[test]
+fn evaluates_expressions_in_registers() {
+    let codes = abyssix::compile(
+        "func main { params 0; alloc 3; set.2 = (get.0 int.+ 1) int.* (get.1 int.- 2) int.< get.2; }",
+    )
+    .unwrap();
+    assert!(
+        !(codes.opcodes.iter()).any(|op| matches!(op, OpCode::Push(_) | OpCode::Pop(_))),
+        "{:?}",
+        codes.opcodes
+    );
+}
+
+#[test]
+fn spills_deep_expressions_and_saves_registers_around_calls() {
+    // Each product of sums needs the three registers, so the first one is spilled.
+    let source = "\
+func sub { params 2; alloc 0; return param.0 int.- param.1; }
+func main {
+  params 0; alloc 2;
+  set.0 = 5;
+  set.1 = ((get.0 int.+ 1) int.* (get.0 int.+ 2)) int.- ((get.0 int.+ 3) int.* (get.0 int.+ 4))
+      int.- (((get.0 int.* 2) int.- (get.0 int.* 3)) int.* ((get.0 int.* 4) int.- (get.0 int.* 5)));
+  putc 48 int.+ (get.1 int.+ 100) int./ 10;
+  putc 48 int.+ get.0 int.+ sub(getc, getc) int.* sub(7, sub(3, 1));
+}
+";
+    // `set.1` is -55, and the arguments of the first call are still read in order.
+    assert_eq!(run(source, b"ba"), "4:");
+}
+
+#[test]
+fn fuses_superinstructions_at_o1() {
+    use abyssix::optimizer::OptLevel;
+
+    let source = "func main { params 0; alloc 1; while get.0 int.< 5: { putc 48 int.+ get.0; set.0 = get.0 int.+ 1; } }";
+    let fused = |op: &OpCode| {
+        matches!(
+            op,
+            OpCode::AddIntImm(..) | OpCode::AddVarInt(..) | OpCode::JmpAddrIfNotLtInt(..)
+        )
+    };
+    let o0 = abyssix::compile_with(source, OptLevel::O0).unwrap();
+    let o1 = abyssix::compile_with(source, OptLevel::O1).unwrap();
+    assert!(!o0.opcodes.iter().any(fused));
+    assert_eq!(o1.opcodes.iter().filter(|op| fused(op)).count(), 3);
+    assert_eq!(run(source, b""), "01234");
+}
+
+#[test]
+fn reuses_the_frame_for_tail_calls() {
+    use abyssix::executor::{Machine, Observer};
+    use abyssix::optimizer::OptLevel;
+    use std::ops::ControlFlow;
+
+    struct Depth(usize);
+
+    impl Observer for Depth {
+        fn before(&mut self, machine: &Machine<'_>) -> ControlFlow<()> {
+            self.0 = self.0.max(machine.stack.len());
+            ControlFlow::Continue(())
+        }
+    }
+
+    let source = "\
+func count { params 2; alloc 0; if param.0 int.== 0: { return param.1; } else { } return count(param.0 int.- 1, param.1 int.+ 1); }
+func even { params 1; alloc 1; if param.0 int.== 0: { return 1; } else { return odd(param.0 int.- 1); } }
+func odd { params 1; alloc 0; if param.0 int.== 0: { return 0; } else { return even(param.0 int.- 1); } }
+func half { params 1; alloc 0; return count(param.0 int./ 2, 0); }
+func main { params 0; alloc 0; putc count(1000, 48) int.- 1000; putc 48 int.+ even(1001); putc half(130); }
+";
+    let depth = |level| {
+        let codes = abyssix::compile_with(source, level).unwrap();
+        let mut vm = Vm::with_io(codes, MemoryIo::memory(b""));
+        let mut depth = Depth(0);
+        vm.run_observed(&mut depth).unwrap();
+        assert_eq!(vm.into_io().output, b"00A");
+        depth.0
+    };
+    assert!(depth(OptLevel::O0) > 1000);
+    // `half` calls `count` with fewer arguments than its own parameters, so only that call grows
+    // the stack.
+    assert!(depth(OptLevel::O1) < 20, "{}", depth(OptLevel::O1));
+}
+
+#[test]
+fn omits_the_else_jump_without_an_else_branch() {
+    use abyssix::optimizer::OptLevel;
+
+    let source = "\
+func main {
+  params 0; alloc 1;
+  set.0 = getc;
+  if get.0 int.== 97: putc 65;
+  if get.0 int.== 98: putc 66; else if get.0 int.== 99: putc 67;
+}
+";
+    let codes = abyssix::compile_with(source, OptLevel::O0).unwrap();
+    let jumps = (codes.opcodes.iter())
+        .filter(|op| matches!(op, OpCode::JmpAddr(_)))
+        .count();
+    // Only the `if` with an `else` jumps over it.
+    assert_eq!(jumps, 1, "{:?}", codes.opcodes);
+    assert_eq!(run(source, b"a"), "A");
+    assert_eq!(run(source, b"c"), "C");
+    assert_eq!(run(source, b"d"), "");
+}
+
+#[test]
+fn reports_a_missing_main() {
+    let mut prog = abyssix::parse("func main { params 0; alloc 0; putc 65; }").unwrap();
+    prog.funcs[0].name = "start".to_owned();
+    let errors = abyssix::code_generator::generate(prog).unwrap_err();
+    assert_eq!(errors[0].to_string(), "the function main() is missing");
+}
@@ -0,0 +1,176 @@
+use std::io::Write;
+use std::process::{Command, Stdio};
+use std::time::{Duration, Instant};
+
+use abyssix::Vm;
+use abyssix::debugger::Debugger;
+use abyssix::io::MemoryIo;
+
+const SOURCE: &str = "\
+func square {
+  params 1;
+  alloc 1;
+  set.0 = param.0 int.* param.0;
+  return get.0;
+}
+
+func main {
+  params 0;
+  alloc 2;
+  set.0 = 3;
+  set.1 = square(get.0);
+  putc 48 int.+ get.1;
+}
+";
+
+/// Runs `SOURCE` under the debugger with `commands` and returns what the debugger and the
+/// program wrote.
+fn debug(commands: &str) -> (String, String) {
+    let codes = abyssix::compile(SOURCE).unwrap();
+    let mut out = Vec::new();
+    let mut debugger = Debugger::new(SOURCE, "square.abys", commands.as_bytes(), &mut out);
+    let mut vm = Vm::with_io(codes, MemoryIo::memory(b""));
+    vm.run_observed(&mut debugger).unwrap();
+    (
+        String::from_utf8(out).unwrap(),
+        String::from_utf8(vm.into_io().output).unwrap(),
+    )
+}
+
+#[test]
+fn breakpoints_and_inspection() {
+    let (out, output) =
+        debug("break square\ncontinue\nprint param.0\nbacktrace\nnext\nprint get.0\ncontinue\n");
+    assert_eq!(
+        out,
+        "\
+main() at square.abys:11
+   11 | set.0 = 3;
+(abyssix) breakpoint 1 at square
+(abyssix) square() at square.abys:4
+    4 | set.0 = param.0 int.* param.0;
+(abyssix) param.0 = 3(1.5e-323)
+(abyssix) 0: square() at square.abys:4
+1: main() at square.abys:12
+(abyssix) square() at square.abys:5
+    5 | return get.0;
+(abyssix) get.0 = 9(4.4e-323)
+(abyssix) "
+    );
+    assert_eq!(output, "9");
+}
+
+#[test]
+fn stepping() {
+    let (out, _) = debug("step\nstep\nstep\nfinish\nquit\n");
+    let stops: Vec<&str> = out.lines().filter(|line| line.contains(" at ")).collect();
+    assert_eq!(
+        stops,
+        [
+            "main() at square.abys:11",
+            "(abyssix) main() at square.abys:12",
+            "(abyssix) square() at square.abys:4",
+            "(abyssix) square() at square.abys:5",
+            "(abyssix) main() at square.abys:12",
+        ]
+    );
+}
+
+#[test]
+fn quitting_stops_the_program() {
+    let (_, output) = debug("b 13\nc\nq\n");
+    assert_eq!(output, "");
+}
+
+#[test]
+fn the_program_reads_the_standard_input_too() {
+    let path = std::path::Path::new(env!("CARGO_TARGET_TMPDIR")).join("echo.abys");
+    std::fs::write(
+        &path,
+        "func main { params 0; alloc 1; set.0 = getc; putc get.0; }\n",
+    )
+    .unwrap();
+    let mut child = Command::new(env!("CARGO_BIN_EXE_abyssix"))
+        .arg("debug")
+        .arg(&path)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .spawn()
+        .unwrap();
+    // The command line is followed by the input of `getc`.
+    child
+        .stdin
+        .take()
+        .unwrap()
+        .write_all(b"continue\nX")
+        .unwrap();
+    // A deadlock on the standard input would otherwise hang the test.
+    let deadline = Instant::now() + Duration::from_secs(10);
+    while child.try_wait().unwrap().is_none() {
+        if Instant::now() > deadline {
+            child.kill().unwrap();
+            panic!("the debugged program hangs reading the standard input");
+        }
+        std::thread::sleep(Duration::from_millis(10));
+    }
+    let output = child.wait_with_output().unwrap();
+    assert!(output.status.success());
+    assert!(
+        String::from_utf8(output.stdout)
+            .unwrap()
+            .ends_with("(abyssix) X")
+    );
+}
+
+#[test]
+fn warns_without_line_information() {
+    let codes = abyssix::assembly::assemble(
+        ".func main params 0 alloc 0\n    LoadInt(65, R1)\n    PutByte(R1)\n    Exit\n",
+    )
+    .unwrap();
+    let mut out = Vec::new();
+    let mut debugger = Debugger::new("", "a.abysm", "break 2\n".as_bytes(), &mut out);
+    let mut vm = Vm::with_io(codes, MemoryIo::memory(b""));
+    vm.run_observed(&mut debugger).unwrap();
+    assert_eq!(
+        String::from_utf8(out).unwrap(),
+        "warning: a.abysm has no line information, so no breakpoint can be hit\n"
+    );
+    assert_eq!(vm.into_io().output, b"A");
+}
+
+#[test]
+fn shows_spans_on_line_0() {
+    // A decoded file may hold any line.
+    let mut codes = abyssix::compile(SOURCE).unwrap();
+    for span in &mut codes.spans {
+        span.line = 0;
+    }
+    let mut out = Vec::new();
+    let mut debugger = Debugger::new(SOURCE, "square.abys", "continue\n".as_bytes(), &mut out);
+    let mut vm = Vm::with_io(codes, MemoryIo::memory(b""));
+    vm.run_observed(&mut debugger).unwrap();
+    assert!(
+        String::from_utf8(out)
+            .unwrap()
+            .starts_with("main() at square.abys:0\n    0 | \n")
+    );
+}
+
+#[test]
+fn runs_to_the_end_when_the_commands_end() {
+    let (out, output) = debug("break square\n");
+    assert!(
+        out.ends_with("(abyssix) breakpoint 1 at square\n(abyssix) \n"),
+        "{}",
+        out
+    );
+    assert_eq!(output, "9");
+
+    let output = Command::new(env!("CARGO_BIN_EXE_abyssix"))
+        .args(["debug", "-"])
+        .stdin(Stdio::null())
+        .output()
+        .unwrap();
+    assert_eq!(output.status.code(), Some(64));
+}
@@ -0,0 +1,153 @@
+//! Golden-output tests: runs every `tests/*.abys` program through the `abyssix` binary and
+//! compares the results with the expectation files next to it.
+//!
+//! - `NAME.in`, if present, is fed to standard input; otherwise the input is empty.
+//! - `NAME.out` holds the expected standard output. It is absent when nothing is written.
+//! - `NAME.err` holds `exit status: N` followed by the expected standard error. It is absent
+//!   when the program exits with 0 and writes nothing to standard error.
+//! - `NAME.checked`, if present, says why the program relies on the runtime checks. It is skipped
+//!   with the `unchecked` feature, where a fault is undefined behavior.
+//!
+//! `cargo test --test golden -- --bless` (or `ABYSSIX_BLESS=1 cargo test`) rewrites the
+//! expectations from the actual results. Other arguments select the fixtures whose name
+//! contains one of them.
+
+use std::fs::{self, File};
+use std::path::{Path, PathBuf};
+use std::process::{Command, ExitCode, Stdio};
+
+struct Outcome {
+    stdout: Vec<u8>,
+    /// `None` for a clean exit.
+    err: Option<Vec<u8>>,
+}
+
+fn run(manifest_dir: &Path, name: &str) -> Outcome {
+    let input = manifest_dir.join("tests").join(format!("{}.in", name));
+    let stdin = if input.exists() {
+        Stdio::from(File::open(&input).expect("failed to open the input fixture"))
+    } else {
+        Stdio::null()
+    };
+    // A relative path keeps the paths in the error messages independent of the checkout.
+    let output = Command::new(env!("CARGO_BIN_EXE_abyssix"))
+        .args(["run", &format!("tests/{}.abys", name)])
+        .current_dir(manifest_dir)
+        .stdin(stdin)
+        .output()
+        .expect("failed to run abyssix");
+
+    let err = if output.status.success() && output.stderr.is_empty() {
+        None
+    } else {
+        let status = output
+            .status
+            .code()
+            .map_or_else(|| "killed by a signal".to_owned(), |code| code.to_string());
+        let mut err = format!("exit status: {}\n", status).into_bytes();
+        err.extend_from_slice(&output.stderr);
+        Some(err)
+    };
+    Outcome {
+        stdout: output.stdout,
+        err,
+    }
+}
+
+/// Compares `actual` with the file at `path`, where `None` means the file must not exist.
+/// Returns a description of the mismatch, after updating the file if `bless` is set.
+fn check(path: &Path, actual: Option<&[u8]>, bless: bool) -> Option<String> {
+    let expected = fs::read(path).ok();
+    if expected.as_deref() == actual {
+        return None;
+    }
+    if bless {
+        match actual {
+            Some(actual) => fs::write(path, actual).expect("failed to write an expectation"),
+            None => fs::remove_file(path).expect("failed to remove an expectation"),
+        }
+        return None;
+    }
+    let show = |bytes: Option<&[u8]>| {
+        bytes.map_or_else(
+            || "(no file)\n".to_owned(),
+            |bytes| String::from_utf8_lossy(bytes).into_owned(),
+        )
+    };
+    Some(format!(
+        "{} differs\n--- expected\n{}--- actual\n{}",
+        path.display(),
+        show(expected.as_deref()),
+        show(actual)
+    ))
+}
+
+fn main() -> ExitCode {
+    let manifest_dir = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    let bless = args.iter().any(|arg| arg == "--bless")
+        || std::env::var_os("ABYSSIX_BLESS").is_some_and(|value| value != "0");
+    // Flags such as `--quiet` come from `cargo test` and are not filters.
+    let filters: Vec<&String> = args.iter().filter(|arg| !arg.starts_with('-')).collect();
+
+    let mut names: Vec<String> = fs::read_dir(manifest_dir.join("tests"))
+        .expect("failed to list the fixtures")
+        .map(|entry| entry.expect("failed to list the fixtures").path())
+        .filter(|path| path.extension().is_some_and(|ext| ext == "abys"))
+        .map(|path| path.file_stem().unwrap().to_string_lossy().into_owned())
+        .filter(|name| filters.is_empty() || filters.iter().any(|f| name.contains(f.as_str())))
+        .collect();
+    names.sort();
+
+    println!("\nrunning {} golden tests", names.len());
+    let mut failures = Vec::new();
+    let mut ignored = 0;
+    for name in &names {
+        let base = manifest_dir.join("tests").join(name);
+        if cfg!(feature = "unchecked") && base.with_extension("checked").exists() {
+            println!("test {} ... ignored", name);
+            ignored += 1;
+            continue;
+        }
+        let outcome = run(&manifest_dir, name);
+        let mismatches: Vec<String> = [
+            check(
+                &base.with_extension("out"),
+                Some(&outcome.stdout[..]).filter(|stdout| !stdout.is_empty()),
+                bless,
+            ),
+            check(&base.with_extension("err"), outcome.err.as_deref(), bless),
+        ]
+        .into_iter()
+        .flatten()
+        .collect();
+        if mismatches.is_empty() {
+            println!("test {} ... ok", name);
+        } else {
+            println!("test {} ... FAILED", name);
+            failures.push((name, mismatches));
+        }
+    }
+
+    for (name, mismatches) in &failures {
+        println!("\n---- {} ----", name);
+        for mismatch in mismatches {
+            print!("{}", mismatch);
+        }
+    }
+    if !failures.is_empty() {
+        println!("\nrun with `--bless` to accept the actual results");
+    }
+    println!(
+        "\ntest result: {}. {} passed; {} failed; {} ignored\n",
+        if failures.is_empty() { "ok" } else { "FAILED" },
+        names.len() - failures.len() - ignored,
+        failures.len(),
+        ignored
+    );
+    if failures.is_empty() {
+        ExitCode::SUCCESS
+    } else {
+        ExitCode::FAILURE
+    }
+}
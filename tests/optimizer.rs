@@ -0,0 +1,181 @@
+use abyssix::code_generator::OpCode;
+use abyssix::io::MemoryIo;
+use abyssix::optimizer::OptLevel;
+use abyssix::{Codes, Vm, compile_with};
+
+fn main_body(body: &str, level: OptLevel) -> Codes {
+    let source = format!("func main {{ params 0; alloc 2; {} }}", body);
+    compile_with(&source, level).unwrap()
+}
+
+/// Runs `body` in `main` at both levels, checks that they print the same and returns the output.
+fn output(body: &str) -> String {
+    let outputs: Vec<Vec<u8>> = [OptLevel::O0, OptLevel::O1]
+        .into_iter()
+        .map(|level| {
+            let mut vm = Vm::with_io(main_body(body, level), MemoryIo::memory(b""));
+            vm.run().unwrap();
+            vm.into_io().output
+        })
+        .collect();
+    assert_eq!(outputs[0], outputs[1], "`{}`", body);
+    String::from_utf8(outputs[1].clone()).unwrap()
+}
+
+#[test]
+fn folds_constant_expressions() {
+    let codes = main_body("set.0 = 2 int.* 3 int.+ 4; putc get.0;", OptLevel::O1);
+    assert!(
+        codes
+            .opcodes
+            .iter()
+            .any(|op| matches!(op, OpCode::LoadInt(10, _)))
+    );
+    assert!(
+        !codes
+            .opcodes
+            .iter()
+            .any(|op| matches!(op, OpCode::MulInt(..)))
+    );
+
+    assert_eq!(output("putc 48 int.+ 2 int.* 3; putc 10;"), "6\n");
+    // The integers wrap around as they do when executed.
+    assert_eq!(
+        output(
+            "putc 48 int.+ (9223372036854775807 int.+ 1 int.== int.- 9223372036854775807 int.- 1);"
+        ),
+        "1"
+    );
+    assert_eq!(
+        output("putc (1 << 66) int.+ 48; putc (int.- 16 >>> 60) int.+ 48;"),
+        "4?"
+    );
+    assert_eq!(output("putc 48 int.+ f_to_i (1.5 float.* 3.0);"), "4");
+    assert_eq!(
+        output("putc 48 int.+ (0.0 float./ 0.0 float.!= 0.0 float./ 0.0);"),
+        "1"
+    );
+}
+
+#[test]
+fn removes_identities_and_dead_branches() {
+    let at = |level| {
+        main_body(
+            "set.0 = getc int.+ 0 int.* 1; if 0: { putc 65; } else { putc get.0; } while 0: { putc 66; } 1 int.+ 2;",
+            level,
+        )
+    };
+    let (o0, o1) = (at(OptLevel::O0), at(OptLevel::O1));
+    assert!(o1.opcodes.len() < o0.opcodes.len());
+    for opcode in &o1.opcodes {
+        assert!(
+            !matches!(
+                opcode,
+                OpCode::AddInt(..)
+                    | OpCode::MulInt(..)
+                    | OpCode::JmpAddrIfZero(..)
+                    | OpCode::LoadInt(65 | 66, _)
+            ),
+            "{:?} is left",
+            opcode
+        );
+    }
+    assert_eq!(
+        output("set.0 = 70; set[1 int.- 1] = get[0] int.+ 0; putc get.0 | 0;"),
+        "F"
+    );
+}
+
+#[test]
+#[cfg_attr(feature = "unchecked", ignore = "relies on the runtime checks")]
+fn keeps_faults_and_effects() {
+    // The division by zero is reported at run time, even when its value is unused.
+    for level in [OptLevel::O0, OptLevel::O1] {
+        let mut vm = Vm::with_io(
+            main_body("getc int./ 0 int.* 0;", level),
+            MemoryIo::memory(b""),
+        );
+        assert!(vm.run().is_err());
+        let mut vm = Vm::with_io(
+            main_body("putc getc int.* 0;", level),
+            MemoryIo::memory(b"A"),
+        );
+        vm.run().unwrap();
+        assert!(vm.io().input.is_empty());
+    }
+    // Calls are checked before the dead code is removed.
+    let errors = compile_with(
+        "func main { params 0; alloc 0; if 0: { undefined(); } else { } }",
+        OptLevel::O1,
+    )
+    .unwrap_err();
+    assert_eq!(errors[0].message, "the function undefined() is not defined");
+}
+
+#[test]
+#[cfg_attr(feature = "unchecked", ignore = "relies on the runtime checks")]
+fn inlines_small_functions() {
+    let source = "\
+func sq { params 1; alloc 0; return param.0 int.* param.0; }
+inline func digit { params 2; alloc 1; set.0 = param.0 int.+ param.1; putc 48 int.+ get.0 int.% 10; return get.0; }
+func acc { params 1; alloc 1; set.0 = get.0 int.+ param.0; return get.0; }
+noinline func twice { params 1; alloc 0; return param.0 int.* 2; }
+func main {
+  params 0; alloc 2;
+  while get.0 int.< 3: {
+    set.1 = digit(sq(get.0 int.+ 1), twice(get.0));
+    putc 48 int.+ acc(get.0);
+    set.0 = get.0 int.+ 1;
+  }
+  putc 48 int.+ sq(getc int.- 48);
+  putc get[getc];
+}
+";
+    let run = |level| {
+        let codes = compile_with(source, level).unwrap();
+        let calls = (codes.opcodes.iter())
+            .filter(|op| matches!(op, OpCode::FunctionCall(_)))
+            .count();
+        let mut vm = Vm::with_io(codes, MemoryIo::memory(b"7\x02"));
+        // `get[2]` is out of `alloc 2`, even with the variables of the inlined functions after it.
+        let error = vm.run().unwrap_err();
+        assert!(error.to_string().contains("alloc 2"), "{}", error);
+        (calls, String::from_utf8(vm.into_io().output).unwrap())
+    };
+    // The variable of `acc` is `0` at each call.
+    assert_eq!(run(OptLevel::O0), (5, "106132a".to_owned()));
+    assert_eq!(run(OptLevel::O1), (4, "106132a".to_owned()));
+    assert_eq!(run(OptLevel::O2), (1, "106132a".to_owned()));
+}
+
+#[test]
+fn removes_dead_code_and_unused_functions() {
+    let source = "\
+func main { params 0; alloc 0; putc f(); }
+func f { params 0; alloc 0; if 1 int.< 2: { return 70; } else { return 71; } putc 72; g(); }
+func g { params 0; alloc 0; return 0; }
+";
+    let prog = abyssix::parse(source).unwrap();
+    let warnings: Vec<String> = (abyssix::optimizer::warnings(&prog).iter())
+        .map(|warning| warning.to_string())
+        .collect();
+    assert_eq!(
+        warnings,
+        [
+            "2:78: this code is never executed, as the statement before it always returns or jumps",
+            "3:6: the function g() is never called from main()",
+        ]
+    );
+
+    let codes = compile_with(source, OptLevel::O1).unwrap();
+    let names: Vec<&str> = codes.functions.iter().map(|f| f.name.as_str()).collect();
+    assert_eq!(names, ["main", "f"]);
+    assert!(
+        !codes
+            .opcodes
+            .iter()
+            .any(|op| matches!(op, OpCode::LoadInt(72, _) | OpCode::JmpAddr(_)))
+    );
+    let o0 = compile_with(source, OptLevel::O0).unwrap();
+    assert_eq!(o0.functions.len(), 3);
+}
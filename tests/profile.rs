@@ -0,0 +1,62 @@
+use abyssix::Vm;
+use abyssix::io::MemoryIo;
+use abyssix::optimizer::OptLevel;
+use abyssix::profile::Profiler;
+
+const SOURCE: &str = "\
+func down {
+  params 1;
+  alloc 0;
+  if param.0 int.== 0: {
+    return 0;
+  } else {
+    return down(param.0 int.- 1);
+  }
+}
+
+func main {
+  params 0;
+  alloc 0;
+  putc 65 int.+ down(3);
+}
+";
+
+#[test]
+fn counts_calls_and_stacks() {
+    // `-O1` would turn the recursion into tail calls, which leave a single frame of `down`.
+    let codes = abyssix::compile_with(SOURCE, OptLevel::O0).unwrap();
+    let mut profiler = Profiler::new();
+    let mut vm = Vm::with_io(codes, MemoryIo::memory(b""));
+    vm.run_observed(&mut profiler).unwrap();
+    assert_eq!(vm.into_io().output, b"A");
+
+    let profile = profiler.finish();
+    let calls: Vec<(&str, u64)> = (profile.functions.iter())
+        .map(|f| (f.name.as_str(), f.calls))
+        .collect();
+    assert_eq!(calls, [("down", 4), ("main", 1)]);
+    let down = &profile.functions[0];
+    assert!(down.exclusive <= down.inclusive);
+
+    let total: u64 = profile.functions.iter().map(|f| f.opcodes).sum();
+    assert_eq!(
+        profile.stacks.iter().map(|(_, count)| count).sum::<u64>(),
+        total
+    );
+    assert_eq!(
+        profile.lines.iter().map(|(_, count)| count).sum::<u64>(),
+        total
+    );
+    let depths: Vec<usize> = profile
+        .stacks
+        .iter()
+        .map(|(stack, _)| stack.len())
+        .collect();
+    assert_eq!(depths, [1, 2, 3, 4, 5]);
+
+    let mut folded = Vec::new();
+    profile.write_folded(&mut folded).unwrap();
+    let folded = String::from_utf8(folded).unwrap();
+    assert!(folded.starts_with("main "));
+    assert!(folded.contains("\nmain;down;down;down;down "));
+}
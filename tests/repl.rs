@@ -0,0 +1,73 @@
+use abyssix::io::MemoryIo;
+use abyssix::repl::{Evaluated, ReplError, Session};
+
+fn eval(session: &mut Session, input: &str) -> Result<Option<Evaluated>, ReplError> {
+    session.eval(input, &mut MemoryIo::memory(b""))
+}
+
+#[test]
+fn locals_and_functions_persist() {
+    let mut session = Session::new();
+    assert_eq!(eval(&mut session, "set.3 = 20;").unwrap(), None);
+    eval(
+        &mut session,
+        "func twice {\n  params 1;\n  alloc 0;\n  return param.0 int.* 2;\n}\n",
+    )
+    .unwrap();
+    assert_eq!(
+        eval(&mut session, "twice(get.3) int.+ 2").unwrap(),
+        Some(Evaluated::Int(42))
+    );
+    assert_eq!(
+        eval(&mut session, "i_to_f get.3 float./ 8.0;").unwrap(),
+        Some(Evaluated::Float(2.5))
+    );
+}
+
+#[test]
+fn output_and_errors() {
+    let mut session = Session::new();
+    let mut io = MemoryIo::memory(b"");
+    session.eval("putc 72; putc 105;", &mut io).unwrap();
+    assert_eq!(io.output, b"Hi");
+
+    let Err(ReplError::Compile(errors)) = eval(&mut session, "set.0 = missing();") else {
+        panic!("an undefined function must be reported");
+    };
+    assert_eq!(
+        errors[0].to_string(),
+        "2:9: the function missing() is not defined"
+    );
+    // The division by zero is undefined behavior without the runtime checks.
+    if cfg!(not(feature = "unchecked")) {
+        assert!(matches!(
+            eval(&mut session, "1 int.% 0"),
+            Err(ReplError::Runtime(_))
+        ));
+    }
+}
+
+#[test]
+fn unclosed_braces_continue_the_input() {
+    assert!(Session::is_incomplete("while 1: {\n  putc 1;\n"));
+    assert!(!Session::is_incomplete("while 1: {\n  putc 1;\n}\n"));
+}
+
+#[test]
+#[cfg_attr(feature = "unchecked", ignore = "relies on the runtime checks")]
+fn computed_indices_stay_in_the_scratch_variables() {
+    let mut session = Session::new();
+    assert_eq!(eval(&mut session, "7").unwrap(), Some(Evaluated::Int(7)));
+    assert!(matches!(
+        eval(&mut session, "get[255 int.+ 1]"),
+        Err(ReplError::Runtime(_))
+    ));
+    assert!(matches!(
+        eval(&mut session, "set.0 = 256; set[get.0] = 1;"),
+        Err(ReplError::Runtime(_))
+    ));
+    assert_eq!(
+        eval(&mut session, "get[255]").unwrap(),
+        Some(Evaluated::Int(0))
+    );
+}
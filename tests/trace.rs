@@ -0,0 +1,40 @@
+use abyssix::Vm;
+use abyssix::io::MemoryIo;
+use abyssix::trace::Tracer;
+
+const SOURCE: &str = "\
+func one {
+  params 0;
+  alloc 0;
+  return 1;
+}
+
+func main {
+  params 0;
+  alloc 0;
+  putc one() int.+ 64;
+}
+";
+
+#[test]
+fn logs_the_selected_opcodes() {
+    let codes = abyssix::compile(SOURCE).unwrap();
+    let one = codes.functions.iter().find(|f| f.name == "one").unwrap();
+    let (start, end) = (one.start, one.end);
+
+    let mut tracer = Tracer::new(Vec::new()).function("one");
+    let mut vm = Vm::with_io(codes, MemoryIo::memory(b""));
+    vm.run_observed(&mut tracer).unwrap();
+    assert_eq!(vm.into_io().output, b"A");
+
+    let log = String::from_utf8(tracer.finish().unwrap()).unwrap();
+    let indices: Vec<usize> = log
+        .lines()
+        .map(|line| line.split_whitespace().next().unwrap().parse().unwrap())
+        .collect();
+    // The prologue, `return 1;` and its epilogue, but not the unreachable epilogue after it.
+    assert_eq!(indices, (start..start + 5).collect::<Vec<_>>());
+    assert!(indices.iter().all(|&i| i < end));
+    assert!(log.lines().all(|line| line.contains(" one: ")));
+    assert!(log.starts_with(&format!("{:>6} one: PushRbpAndMovEspToEbp  R1=", start)));
+}
@@ -0,0 +1,138 @@
+use abyssix::assembly::assemble;
+use abyssix::verifier::verify;
+
+/// Verifies `main` followed by a function `f` taking a parameter, and returns the message of the
+/// error found.
+fn error(main: &str, f: &str) -> String {
+    let text = format!(
+        ".func main params 0 alloc 1\n{}\n.func f params 1 alloc 1\n{}",
+        main, f
+    );
+    let codes = assemble(&text).unwrap();
+    verify(&codes).unwrap_err().to_string()
+}
+
+const MAIN: &str = "\
+    PushRbpAndMovEspToEbp
+    Alloc(1)
+    LoadInt(1, R1)
+    Push(R1)
+    FunctionCall(f)
+    Free(1)
+    Exit";
+
+const F: &str = "\
+    PushRbpAndMovEspToEbp
+    Alloc(1)
+    GetParam(1, R1)
+    SetVar(0, R1)
+    MovEbpToEspAndPopRbp
+    Ret";
+
+#[test]
+fn accepts_generated_code() {
+    let dir = std::path::Path::new(env!("CARGO_MANIFEST_DIR")).join("tests");
+    for entry in std::fs::read_dir(dir).unwrap() {
+        let path = entry.unwrap().path();
+        if path.extension().is_some_and(|ext| ext == "abys")
+            && let Ok(codes) = abyssix::compile(&std::fs::read_to_string(&path).unwrap())
+        {
+            verify(&codes).unwrap_or_else(|e| panic!("{}: {}", path.display(), e));
+        }
+    }
+    verify(
+        &assemble(&format!(
+            ".func main params 0 alloc 1\n{}\n.func f params 1 alloc 1\n{}",
+            MAIN, F
+        ))
+        .unwrap(),
+    )
+    .unwrap();
+}
+
+#[test]
+fn rejects_unbalanced_stacks() {
+    assert_eq!(
+        error(
+            "\
+    PushRbpAndMovEspToEbp
+    Alloc(1)
+    GetVar(0, R1)
+    JmpAddrIfZero(R1, join)
+    Push(R1)
+join:
+    Exit",
+            F
+        ),
+        "the stack depth is 3 on a path from opcode 4 but 2 on another in main() at opcode 5"
+    );
+    assert_eq!(
+        error(
+            MAIN,
+            &F.replace("    SetVar(0, R1)", "    Push(R1)\n    Free(2)")
+        ),
+        "popping 2 value(s) underflows the frame, which holds 1 in f() at opcode 11"
+    );
+    assert_eq!(
+        error(MAIN, &F.replace("    MovEbpToEspAndPopRbp\n", "")),
+        "returning with 2 value(s) left on the stack in f() at opcode 11"
+    );
+    assert_eq!(
+        error(&MAIN.replace("    Push(R1)\n", ""), F),
+        "f() takes 1 argument(s), but 0 value(s) are pushed in main() at opcode 3"
+    );
+}
+
+#[test]
+fn rejects_escaping_control_flow() {
+    assert_eq!(
+        error(&MAIN.replace("    Exit", "    JmpAddr(8)"), F),
+        "the jump target 8 is outside of main() in main() at opcode 6"
+    );
+    assert_eq!(
+        error(MAIN, &F.replace("    Ret", "    LoadInt(0, R1)")),
+        "the execution runs past the end of f() in f() at opcode 12"
+    );
+    assert_eq!(
+        error(&MAIN.replace("FunctionCall(f)", "FunctionCall(9)"), F),
+        "the call target 9 is not the start of a function in main() at opcode 4"
+    );
+}
+
+#[test]
+fn rejects_invalid_registers_and_frames() {
+    assert_eq!(
+        error(&MAIN.replace("    LoadInt(1, R1)\n", ""), F),
+        "R1 is read before it is written in main() at opcode 2"
+    );
+    assert_eq!(
+        error(MAIN, &F.replace("GetParam(1, R1)", "GetParam(2, R1)")),
+        "the parameter index 2 is out of range for `params 1` in f() at opcode 9"
+    );
+    assert_eq!(
+        error(MAIN, &F.replace("SetVar(0, R1)", "SetVar(1, R1)")),
+        "the variable 1 is out of range for `alloc 1` in f() at opcode 10"
+    );
+    assert_eq!(
+        error(&MAIN.replace("    Alloc(1)\n", "    GetVar(0, R2)\n"), F),
+        "a variable is accessed before the variables are allocated in main() at opcode 1"
+    );
+}
+
+#[test]
+fn rejects_placeholder_opcodes() {
+    let mut codes = assemble(&format!(
+        ".func main params 0 alloc 1\n{}\n.func f params 1 alloc 1\n{}",
+        MAIN, F
+    ))
+    .unwrap();
+    codes.opcodes[2] = abyssix::code_generator::OpCode::Nop;
+    assert_eq!(
+        verify(&codes).unwrap_err().to_string(),
+        "`Nop` is only a placeholder during code generation in main() at opcode 2"
+    );
+    assert_eq!(
+        assemble(".func main params 0 alloc 0\n    Nop\n").unwrap_err()[0].to_string(),
+        "2:5: unknown opcode `Nop`"
+    );
+}